@@ -0,0 +1,454 @@
+//! Additional accounts and pots. Every user keeps their main checking
+//! account on the `users` row (`account_number` and `balance`), which is
+//! what other customers pay into. Further accounts live in `accounts`, get
+//! their own check-digit account number, and are funded by instant internal
+//! transfers between the user's own accounts.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+use crate::{account_numbers, interest};
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments::{self, TransferError, TransferOutcome};
+use crate::statements;
+
+const MAX_ACCOUNTS_PER_USER: i64 = 10;
+const MAX_NAME_LEN: usize = 50;
+const MAIN_ACCOUNT_NAME: &str = "Main account";
+const ACCOUNT_KINDS: [&str; 3] = ["checking", "savings", "pot"];
+const ALLOCATION_ATTEMPTS: usize = 5;
+
+#[derive(sqlx::FromRow)]
+struct MainAccountRow {
+    account_number: String,
+    balance: Decimal,
+    created_at: NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow)]
+struct AccountRow {
+    account_number: String,
+    name: String,
+    kind: String,
+    balance: Decimal,
+    created_at: NaiveDateTime,
+}
+
+impl From<AccountRow> for AccountView {
+    fn from(row: AccountRow) -> Self {
+        AccountView {
+            account_number: row.account_number,
+            name: row.name,
+            kind: row.kind,
+            balance: payments::decimal_to_f64(row.balance),
+            is_primary: false,
+            created_at: row.created_at.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+fn main_view(row: MainAccountRow) -> AccountView {
+    AccountView {
+        account_number: row.account_number,
+        name: MAIN_ACCOUNT_NAME.to_string(),
+        kind: "checking".to_string(),
+        balance: payments::decimal_to_f64(row.balance),
+        is_primary: true,
+        created_at: row.created_at.and_utc().to_rfc3339(),
+    }
+}
+
+fn bad_request(error: &str, message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        error: error.to_string(),
+        message,
+    })
+}
+
+fn clean_name(value: &str) -> Result<String, String> {
+    let name = value.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(format!("Account name must be 1 to {} characters", MAX_NAME_LEN));
+    }
+    Ok(name.to_string())
+}
+
+/// Lists the user's accounts, main account first, with balances either live
+/// or as of `as_of`, and their exact total. `main_balance` is the main
+/// account's balance at the same point in time, which the caller has already
+/// worked out.
+pub async fn balances(
+    pool: &PgPool,
+    user_id: Uuid,
+    main_balance: Decimal,
+    as_of: Option<NaiveDateTime>,
+) -> Result<(Vec<AccountView>, Decimal), sqlx::Error> {
+    let main = sqlx::query_as::<_, MainAccountRow>("SELECT account_number, balance, created_at FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    let rows = sqlx::query_as::<_, AccountRow>(
+        "SELECT account_number, name, kind, balance, created_at FROM accounts \
+         WHERE user_id = $1 AND created_at <= COALESCE($2, NOW()) \
+           AND (closed_at IS NULL OR closed_at > COALESCE($2, NOW())) \
+         ORDER BY created_at"
+    )
+    .bind(user_id)
+    .bind(as_of)
+    .fetch_all(pool)
+    .await?;
+
+    let mut total = main_balance;
+    let mut views = vec![main_view(MainAccountRow { balance: main_balance, ..main })];
+    for mut row in rows {
+        if let Some(as_of) = as_of {
+            row.balance -= statements::net_movement_after(pool, &row.account_number, as_of).await?;
+        }
+        total += row.balance;
+        views.push(row.into());
+    }
+    Ok((views, total))
+}
+
+/// Locks one of the user's open accounts. Returns whether it is the main
+/// account and its balance.
+async fn lock_own(conn: &mut PgConnection, user_id: Uuid, account_number: &str) -> Result<Option<(bool, Decimal)>, sqlx::Error> {
+    let main = sqlx::query_scalar::<_, Decimal>(
+        "SELECT balance - held_balance FROM users WHERE id = $1 AND account_number = $2 FOR UPDATE"
+    )
+        .bind(user_id)
+        .bind(account_number)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some(balance) = main {
+        return Ok(Some((true, balance)));
+    }
+
+    let other = sqlx::query_scalar::<_, Decimal>(
+        "SELECT balance FROM accounts WHERE user_id = $1 AND account_number = $2 AND closed_at IS NULL FOR UPDATE"
+    )
+    .bind(user_id)
+    .bind(account_number)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(other.map(|balance| (false, balance)))
+}
+
+async fn adjust(conn: &mut PgConnection, user_id: Uuid, main: bool, account_number: &str, delta: Decimal) -> Result<(), sqlx::Error> {
+    let sql = if main {
+        "UPDATE users SET balance = balance + $3 WHERE id = $1 AND account_number = $2"
+    } else {
+        "UPDATE accounts SET balance = balance + $3 WHERE user_id = $1 AND account_number = $2"
+    };
+    sqlx::query(sql)
+        .bind(user_id)
+        .bind(account_number)
+        .bind(delta)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Moves money between two of the user's own accounts on the caller's
+/// connection; the caller commits.
+pub async fn move_between_own(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    from_account: &str,
+    to_account: &str,
+    amount: Decimal,
+    description: Option<&str>,
+) -> Result<TransferOutcome, TransferError> {
+    if amount <= Decimal::ZERO {
+        return Err(TransferError::InvalidAmount);
+    }
+
+    // Lock in a fixed order so two opposite transfers cannot deadlock
+    let (first, second) = if from_account <= to_account { (from_account, to_account) } else { (to_account, from_account) };
+    let first_lock = lock_own(&mut *conn, user_id, first).await?.ok_or(TransferError::AccountNotFound)?;
+    let second_lock = lock_own(&mut *conn, user_id, second).await?.ok_or(TransferError::AccountNotFound)?;
+    let ((from_main, from_balance), (to_main, _)) =
+        if first == from_account { (first_lock, second_lock) } else { (second_lock, first_lock) };
+
+    if from_balance < amount {
+        return Err(TransferError::InsufficientFunds);
+    }
+
+    adjust(&mut *conn, user_id, from_main, from_account, -amount).await?;
+    adjust(&mut *conn, user_id, to_main, to_account, amount).await?;
+    let (transaction_id, created_at) =
+        payments::record_transaction(&mut *conn, from_account, to_account, amount, description).await?;
+
+    Ok(TransferOutcome {
+        transaction_id,
+        from_account: from_account.to_string(),
+        to_account: to_account.to_string(),
+        amount,
+        sender_balance: from_balance - amount,
+        created_at,
+        held_for_review: false,
+    })
+}
+
+pub async fn list_accounts(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let main_balance = match sqlx::query_scalar::<_, Decimal>("SELECT balance FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(b) => b,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    match balances(pool.get_ref(), user_id, main_balance, None).await {
+        Ok((accounts, total)) => HttpResponse::Ok().json(json!({
+            "accounts": accounts,
+            "total_balance": payments::decimal_to_f64(total),
+            "currency": "USD",
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn open_account(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<OpenAccountRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let name = match clean_name(&body.name) {
+        Ok(n) => n,
+        Err(message) => return bad_request("invalid_name", message),
+    };
+    let kind = body.kind.as_deref().unwrap_or("pot");
+    if !ACCOUNT_KINDS.contains(&kind) {
+        return bad_request("invalid_kind", format!("Account kind must be one of {}", ACCOUNT_KINDS.join(", ")));
+    }
+
+    let open = match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM accounts WHERE user_id = $1 AND closed_at IS NULL")
+        .bind(user_id)
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(c) => c,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if open >= MAX_ACCOUNTS_PER_USER {
+        return bad_request("account_limit", format!("At most {} additional accounts can be open", MAX_ACCOUNTS_PER_USER));
+    }
+
+    // Account numbers share one namespace with main accounts on `users`
+    for _ in 0..ALLOCATION_ATTEMPTS {
+        let inserted = sqlx::query_as::<_, AccountRow>(
+            "INSERT INTO accounts (id, user_id, account_number, name, kind) \
+             SELECT $1, $2, $3, $4, $5 WHERE NOT EXISTS (SELECT 1 FROM users WHERE account_number = $3) \
+             ON CONFLICT (account_number) DO NOTHING \
+             RETURNING account_number, name, kind, balance, created_at"
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(account_numbers::generate())
+        .bind(&name)
+        .bind(kind)
+        .fetch_optional(pool.get_ref())
+        .await;
+
+        match inserted {
+            Ok(Some(row)) => return HttpResponse::Created().json(AccountView::from(row)),
+            Ok(None) => continue,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+    HttpResponse::InternalServerError().finish()
+}
+
+pub async fn rename_account(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<RenameAccountRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let name = match clean_name(&body.name) {
+        Ok(n) => n,
+        Err(message) => return bad_request("invalid_name", message),
+    };
+
+    match sqlx::query_as::<_, AccountRow>(
+        "UPDATE accounts SET name = $3 WHERE user_id = $1 AND account_number = $2 AND closed_at IS NULL \
+         RETURNING account_number, name, kind, balance, created_at"
+    )
+    .bind(user_id)
+    .bind(path.as_str())
+    .bind(&name)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(Some(row)) => HttpResponse::Ok().json(AccountView::from(row)),
+        Ok(None) => TransferError::AccountNotFound.to_response(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Closes an additional account, paying out any interest owed and sweeping
+/// the remaining balance back to the main account first. The main account
+/// itself cannot be closed here.
+pub async fn close_account(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let account_number = path.into_inner();
+
+    let mut tx = match pool.begin().await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let main_account = match sqlx::query_scalar::<_, String>("SELECT account_number FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await
+    {
+        Ok(a) => a,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if main_account == account_number {
+        return bad_request("cannot_close_main_account", "The main account cannot be closed".to_string());
+    }
+
+    // Same lock order as move_between_own
+    if main_account < account_number && lock_own(&mut tx, user_id, &main_account).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let mut balance = match lock_own(&mut tx, user_id, &account_number).await {
+        Ok(Some((_, balance))) => balance,
+        Ok(None) => return TransferError::AccountNotFound.to_response(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Savings earn interest up to the day they close, paid before the sweep
+    let savings_id = match sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM accounts WHERE user_id = $1 AND account_number = $2 AND kind = $3"
+    )
+    .bind(user_id)
+    .bind(&account_number)
+    .bind(interest::SAVINGS_PRODUCT)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let interest = match savings_id {
+        Some(id) => match interest::close_out(&mut tx, id, chrono::Utc::now().date_naive()).await {
+            Ok(paid) => paid,
+            Err(interest::InterestError::Transfer(e)) => return e.to_response(),
+            Err(e) => {
+                log::error!("could not post closing interest to {}: {}", account_number, e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        None => Decimal::ZERO,
+    };
+    balance += interest;
+
+    let sweep = if balance > Decimal::ZERO {
+        match move_between_own(&mut tx, user_id, &account_number, &main_account, balance, Some("Account closed")).await {
+            Ok(outcome) => Some(outcome.transaction_id),
+            Err(e) => return e.to_response(),
+        }
+    } else {
+        None
+    };
+
+    if sqlx::query("UPDATE accounts SET closed_at = NOW() WHERE user_id = $1 AND account_number = $2")
+        .bind(user_id)
+        .bind(&account_number)
+        .execute(&mut *tx)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(json!({
+        "status": "closed",
+        "account_number": account_number,
+        "swept_to": sweep.map(|_| main_account),
+        "swept_amount": payments::decimal_to_f64(balance),
+        "interest_paid": payments::decimal_to_f64(interest),
+        "transaction_id": sweep.map(|id| id.to_string()),
+    }))
+}
+
+pub async fn internal_transfer(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<InternalTransferRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let amount = match payments::parse_amount(body.amount) {
+        Some(a) => a,
+        None => return TransferError::InvalidAmount.to_response(),
+    };
+    if body.from_account == body.to_account {
+        return bad_request("same_account", "Source and destination accounts must differ".to_string());
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let outcome = match move_between_own(
+        &mut tx,
+        user_id,
+        &body.from_account,
+        &body.to_account,
+        amount,
+        body.description.as_deref(),
+    ).await {
+        Ok(o) => o,
+        Err(e) => return e.to_response(),
+    };
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(json!({
+        "transaction_id": outcome.transaction_id.to_string(),
+        "status": "completed",
+        "from_account": outcome.from_account,
+        "to_account": outcome.to_account,
+        "amount": payments::decimal_to_f64(outcome.amount),
+        "from_balance": payments::decimal_to_f64(outcome.sender_balance),
+        "timestamp": outcome.created_at.and_utc().to_rfc3339(),
+    }))
+}
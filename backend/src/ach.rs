@@ -0,0 +1,589 @@
+//! NACHA ACH rail for outbound withdrawals. Withdrawals wait as `pending`
+//! until the file job writes them as PPD credit entries into an ACH file in
+//! `ACH_OUTBOX_DIR`; they are settled once the file is written. Return files
+//! dropped into `ACH_RETURNS_DIR` are parsed and the matching withdrawals
+//! returned and refunded with the return reason code.
+//!
+//! Files use the standard layout: 94 character records, a blocking factor of
+//! 10 and lines of `9` padding up to a full block.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc, Weekday};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+use std::env;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use crate::rails::{self, PaymentRail, RailError, RailEvent, RailTransfer};
+
+const RECORD_LEN: usize = 94;
+const BLOCKING_FACTOR: usize = 10;
+/// Service class code for a batch of credits only.
+const SERVICE_CLASS_CREDITS: &str = "220";
+/// Transaction code for a credit to a checking account.
+const CHECKING_CREDIT: &str = "22";
+const ENTRY_DESCRIPTION: &str = "WITHDRAWAL";
+const HASH_MODULUS: u64 = 10_000_000_000;
+/// Trace numbers are the ODFI routing prefix and a seven digit sequence.
+const MAX_TRACE_SEQUENCE: i64 = 9_999_999;
+const FILE_ID_MODIFIERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const DEFAULT_OUTBOX_DIR: &str = "data/ach/outbound";
+const DEFAULT_RETURNS_DIR: &str = "data/ach/returns";
+const DEFAULT_JOB_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AchError {
+    #[error("invalid ACH file: {0}")]
+    Format(String),
+    #[error("ACH trace numbers are exhausted")]
+    TraceNumbersExhausted,
+    #[error("ACH file i/o failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Rail(#[from] RailError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// The originator's details for file and batch headers, from `ACH_*`
+/// variables.
+pub struct AchConfig {
+    immediate_destination: String,
+    immediate_origin: String,
+    destination_name: String,
+    origin_name: String,
+    company_name: String,
+    company_id: String,
+    odfi: String,
+    outbox: PathBuf,
+    returns: PathBuf,
+}
+
+impl AchConfig {
+    pub fn from_env() -> Result<AchConfig, String> {
+        let var = |name: &str| env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let immediate_destination =
+            var("ACH_IMMEDIATE_DESTINATION").ok_or("ACH_IMMEDIATE_DESTINATION is not set")?;
+        if !rails::routing_number_valid(&immediate_destination) {
+            return Err("ACH_IMMEDIATE_DESTINATION is not a valid routing number".to_string());
+        }
+        let odfi = var("ACH_ODFI_ROUTING").unwrap_or_else(|| immediate_destination.clone());
+        if !rails::routing_number_valid(&odfi) {
+            return Err("ACH_ODFI_ROUTING is not a valid routing number".to_string());
+        }
+        let company_id = var("ACH_COMPANY_ID").ok_or("ACH_COMPANY_ID is not set")?;
+        if company_id.len() > 10 || !company_id.is_ascii() {
+            return Err("ACH_COMPANY_ID must be at most 10 characters".to_string());
+        }
+        let company_name = var("ACH_COMPANY_NAME").unwrap_or_else(|| "DELTAUP".to_string());
+        Ok(AchConfig {
+            immediate_origin: var("ACH_IMMEDIATE_ORIGIN").unwrap_or_else(|| company_id.clone()),
+            destination_name: var("ACH_DESTINATION_NAME").unwrap_or_default(),
+            origin_name: var("ACH_ORIGIN_NAME").unwrap_or_else(|| company_name.clone()),
+            odfi: odfi[..8].to_string(),
+            immediate_destination,
+            company_name,
+            company_id,
+            outbox: PathBuf::from(var("ACH_OUTBOX_DIR").unwrap_or_else(|| DEFAULT_OUTBOX_DIR.to_string())),
+            returns: PathBuf::from(var("ACH_RETURNS_DIR").unwrap_or_else(|| DEFAULT_RETURNS_DIR.to_string())),
+        })
+    }
+}
+
+/// Queues withdrawals for the next ACH file. Deposits would be debit
+/// entries, which this originator does not send.
+pub struct AchRail;
+
+impl AchRail {
+    pub const NAME: &'static str = "ach";
+}
+
+impl PaymentRail for AchRail {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn submit(&self, transfer: &RailTransfer) -> Result<String, RailError> {
+        if transfer.direction != rails::WITHDRAWAL {
+            return Err(RailError::Rejected("ACH deposits are not supported".to_string()));
+        }
+        // The entry amount field holds ten digits of cents
+        if transfer.amount >= Decimal::from(100_000_000) {
+            return Err(RailError::Rejected("amount does not fit an ACH entry".to_string()));
+        }
+        log::debug!(
+            "queued ACH credit of {} for {} at routing {}",
+            transfer.amount,
+            transfer.account_holder,
+            transfer.routing_number
+        );
+        Ok(format!("ACH-{}", &transfer.id.simple().to_string()[..16].to_uppercase()))
+    }
+}
+
+/// One PPD credit entry.
+pub struct AchEntry {
+    pub routing_number: String,
+    pub account_number: String,
+    pub amount: Decimal,
+    pub individual_id: String,
+    pub individual_name: String,
+    pub trace_number: String,
+}
+
+/// Left-justified, space-filled, uppercase alphanumeric field.
+fn alpha(value: &str, len: usize) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() || c == ' ' { c.to_ascii_uppercase() } else { ' ' })
+        .take(len)
+        .collect();
+    format!("{:<len$}", value, len = len)
+}
+
+/// Right-justified, zero-filled numeric field.
+fn numeric(value: u64, len: usize) -> String {
+    let digits = format!("{:0len$}", value, len = len);
+    digits[digits.len() - len..].to_string()
+}
+
+fn cents(amount: Decimal) -> u64 {
+    (amount * Decimal::from(100)).round().to_u64().unwrap_or(0)
+}
+
+/// The first business day after `day`, used as the effective entry date.
+pub fn effective_date(day: NaiveDate) -> NaiveDate {
+    let mut next = day + Duration::days(1);
+    while matches!(next.weekday(), Weekday::Sat | Weekday::Sun) {
+        next += Duration::days(1);
+    }
+    next
+}
+
+/// Writes `entries` as a single-batch ACH file.
+pub fn build_file(
+    config: &AchConfig,
+    created: NaiveDateTime,
+    file_id_modifier: char,
+    effective: NaiveDate,
+    entries: &[AchEntry],
+) -> String {
+    let batch_number = 1;
+    let mut records = Vec::with_capacity(entries.len() + 4);
+
+    records.push(format!(
+        "101{:>10}{:>10}{}{}{}094{}1{}{}{}",
+        config.immediate_destination,
+        alpha(&config.immediate_origin, 10).trim_end(),
+        created.format("%y%m%d"),
+        created.format("%H%M"),
+        file_id_modifier,
+        BLOCKING_FACTOR,
+        alpha(&config.destination_name, 23),
+        alpha(&config.origin_name, 23),
+        alpha("", 8),
+    ));
+    records.push(format!(
+        "5{}{}{}{}PPD{}{}{}{}1{}{}",
+        SERVICE_CLASS_CREDITS,
+        alpha(&config.company_name, 16),
+        alpha("", 20),
+        alpha(&config.company_id, 10),
+        alpha(ENTRY_DESCRIPTION, 10),
+        alpha("", 6),
+        effective.format("%y%m%d"),
+        alpha("", 3),
+        config.odfi,
+        numeric(batch_number, 7),
+    ));
+
+    let mut entry_hash = 0u64;
+    let mut total_credit = 0u64;
+    for entry in entries {
+        let rdfi: u64 = entry.routing_number[..8].parse().unwrap_or(0);
+        entry_hash += rdfi;
+        total_credit += cents(entry.amount);
+        records.push(format!(
+            "6{}{}{}{}{}{}{}  0{}",
+            CHECKING_CREDIT,
+            &entry.routing_number[..8],
+            &entry.routing_number[8..9],
+            alpha(&entry.account_number, 17),
+            numeric(cents(entry.amount), 10),
+            alpha(&entry.individual_id, 15),
+            alpha(&entry.individual_name, 22),
+            entry.trace_number,
+        ));
+    }
+    let entry_hash = entry_hash % HASH_MODULUS;
+    let entry_count = entries.len() as u64;
+
+    records.push(format!(
+        "8{}{}{}{}{}{}{}{}{}{}",
+        SERVICE_CLASS_CREDITS,
+        numeric(entry_count, 6),
+        numeric(entry_hash, 10),
+        numeric(0, 12),
+        numeric(total_credit, 12),
+        alpha(&config.company_id, 10),
+        alpha("", 19),
+        alpha("", 6),
+        config.odfi,
+        numeric(batch_number, 7),
+    ));
+
+    let record_count = records.len() + 1;
+    let block_count = record_count.div_ceil(BLOCKING_FACTOR);
+    records.push(format!(
+        "9{}{}{}{}{}{}{}",
+        numeric(1, 6),
+        numeric(block_count as u64, 6),
+        numeric(entry_count, 8),
+        numeric(entry_hash, 10),
+        numeric(0, 12),
+        numeric(total_credit, 12),
+        alpha("", 39),
+    ));
+    while records.len() % BLOCKING_FACTOR != 0 {
+        records.push("9".repeat(RECORD_LEN));
+    }
+
+    debug_assert!(records.iter().all(|r| r.len() == RECORD_LEN));
+    let mut file = records.join("\n");
+    file.push('\n');
+    file
+}
+
+/// A returned entry from an ACH return file.
+#[derive(Debug)]
+pub struct AchReturn {
+    pub original_trace: String,
+    pub return_code: String,
+}
+
+/// Reads the return addenda (record type 7, addenda type 99) of a return
+/// file.
+pub fn parse_returns(contents: &str) -> Result<Vec<AchReturn>, AchError> {
+    let mut returns = Vec::new();
+    let mut saw_header = false;
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != RECORD_LEN || !line.is_ascii() {
+            return Err(AchError::Format(format!("record {} is not {} characters", n + 1, RECORD_LEN)));
+        }
+        match &line[..1] {
+            "1" => saw_header = true,
+            "7" if &line[1..3] == "99" => {
+                let original_trace = line[6..21].to_string();
+                if !original_trace.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(AchError::Format(format!("record {} has a malformed trace number", n + 1)));
+                }
+                returns.push(AchReturn { return_code: line[3..6].to_string(), original_trace });
+            }
+            "5" | "6" | "7" | "8" | "9" => {}
+            _ => return Err(AchError::Format(format!("record {} has an unknown record type", n + 1))),
+        }
+    }
+    if !saw_header {
+        return Err(AchError::Format("missing file header record".to_string()));
+    }
+    Ok(returns)
+}
+
+#[derive(sqlx::FromRow)]
+struct QueuedRow {
+    id: Uuid,
+    rail_reference: String,
+    amount: Decimal,
+    routing_number: String,
+    external_account: String,
+    account_holder: String,
+}
+
+/// Writes every queued withdrawal into a new ACH file and settles them.
+/// Returns the file's path, or `None` when nothing was queued.
+pub async fn generate_file(pool: &PgPool, config: &AchConfig) -> Result<Option<PathBuf>, AchError> {
+    let mut tx = pool.begin().await?;
+    let queued = sqlx::query_as::<_, QueuedRow>(
+        "SELECT id, rail_reference, amount, routing_number, external_account, account_holder \
+         FROM external_transfers \
+         WHERE rail = $1 AND direction = $2 AND status = 'pending' AND ach_file_id IS NULL AND rail_reference IS NOT NULL \
+         ORDER BY created_at FOR UPDATE SKIP LOCKED"
+    )
+    .bind(AchRail::NAME)
+    .bind(rails::WITHDRAWAL)
+    .fetch_all(&mut *tx)
+    .await?;
+    if queued.is_empty() {
+        return Ok(None);
+    }
+
+    let created = Utc::now().naive_utc();
+    let file_date = created.date();
+    let files_today = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM ach_files WHERE file_date = $1")
+        .bind(file_date)
+        .fetch_one(&mut *tx)
+        .await?;
+    let modifier = FILE_ID_MODIFIERS
+        .chars()
+        .nth(files_today as usize)
+        .ok_or_else(|| AchError::Format(format!("no file ID modifiers left for {}", file_date)))?;
+
+    let mut entries = Vec::with_capacity(queued.len());
+    for row in &queued {
+        let sequence = sqlx::query_scalar::<_, i64>("SELECT nextval('ach_trace_seq')")
+            .fetch_one(&mut *tx)
+            .await?;
+        // A wrapped sequence would reuse a trace number and misroute returns
+        if !(1..=MAX_TRACE_SEQUENCE).contains(&sequence) {
+            return Err(AchError::TraceNumbersExhausted);
+        }
+        entries.push(AchEntry {
+            routing_number: row.routing_number.clone(),
+            account_number: row.external_account.clone(),
+            amount: row.amount,
+            individual_id: row.id.simple().to_string()[..15].to_string(),
+            individual_name: row.account_holder.clone(),
+            trace_number: format!("{}{}", config.odfi, numeric(sequence as u64, 7)),
+        });
+    }
+
+    let contents = build_file(config, created, modifier, effective_date(file_date), &entries);
+    let file_id = Uuid::new_v4();
+    let file_name = format!("{}-{}.ach", file_date.format("%Y%m%d"), modifier);
+    sqlx::query(
+        "INSERT INTO ach_files (id, file_name, file_date, file_id_modifier, entry_count, total_credit) \
+         VALUES ($1, $2, $3, $4, $5, $6)"
+    )
+    .bind(file_id)
+    .bind(&file_name)
+    .bind(file_date)
+    .bind(modifier.to_string())
+    .bind(entries.len() as i32)
+    .bind(queued.iter().map(|r| r.amount).sum::<Decimal>())
+    .execute(&mut *tx)
+    .await?;
+
+    for (row, entry) in queued.iter().zip(&entries) {
+        sqlx::query("UPDATE external_transfers SET ach_file_id = $2, ach_trace_number = $3 WHERE id = $1")
+            .bind(row.id)
+            .bind(file_id)
+            .bind(&entry.trace_number)
+            .execute(&mut *tx)
+            .await?;
+        let settled = RailEvent { reference: row.rail_reference.clone(), status: "settled".to_string(), return_code: None };
+        rails::apply_event_on(&mut tx, AchRail::NAME, &settled).await?;
+    }
+
+    // Entries are only marked as sent once the file is in place, and the
+    // file is withdrawn again if that fails
+    tokio::fs::create_dir_all(&config.outbox).await?;
+    let path = config.outbox.join(&file_name);
+    let partial = path.with_extension("ach.part");
+    tokio::fs::write(&partial, contents).await?;
+    if let Err(e) = tokio::fs::rename(&partial, &path).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e.into());
+    }
+    if let Err(e) = tx.commit().await {
+        if let Err(remove) = tokio::fs::remove_file(&path).await {
+            log::error!("could not withdraw unsent ACH file {}: {}", path.display(), remove);
+        }
+        return Err(e.into());
+    }
+
+    log::info!("wrote ACH file {} with {} entries", path.display(), entries.len());
+    Ok(Some(path))
+}
+
+/// Applies one return file. Returns how many withdrawals were returned.
+async fn apply_return_file(pool: &PgPool, contents: &str) -> Result<usize, AchError> {
+    let mut applied = 0;
+    for ret in parse_returns(contents)? {
+        let reference = sqlx::query_scalar::<_, String>(
+            "SELECT rail_reference FROM external_transfers WHERE rail = $1 AND ach_trace_number = $2"
+        )
+        .bind(AchRail::NAME)
+        .bind(&ret.original_trace)
+        .fetch_optional(pool)
+        .await?;
+        let Some(reference) = reference else {
+            log::warn!("ACH return {} for unknown trace number {}", ret.return_code, ret.original_trace);
+            continue;
+        };
+        let event = RailEvent { reference, status: "returned".to_string(), return_code: Some(ret.return_code.clone()) };
+        match rails::apply_event(pool, AchRail::NAME, &event).await {
+            Ok(_) => applied += 1,
+            Err(RailError::Database(e)) => return Err(e.into()),
+            Err(e) => log::error!("could not apply ACH return for trace {}: {}", ret.original_trace, e),
+        }
+    }
+    Ok(applied)
+}
+
+async fn move_into(file: &Path, dir: &Path) -> Result<(), std::io::Error> {
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::rename(file, dir.join(file.file_name().unwrap_or_default())).await
+}
+
+/// Processes every file waiting in the returns directory, moving each to
+/// `processed/` or, if it cannot be parsed, `rejected/`.
+pub async fn process_returns(pool: &PgPool, config: &AchConfig) -> Result<usize, AchError> {
+    let mut dir = match tokio::fs::read_dir(&config.returns).await {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut applied = 0;
+    while let Some(entry) = dir.next_entry().await? {
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+        let path = entry.path();
+        let contents = tokio::fs::read_to_string(&path).await?;
+        match apply_return_file(pool, &contents).await {
+            Ok(n) => {
+                applied += n;
+                move_into(&path, &config.returns.join("processed")).await?;
+            }
+            Err(AchError::Format(reason)) => {
+                log::error!("rejected ACH return file {}: {}", path.display(), reason);
+                move_into(&path, &config.returns.join("rejected")).await?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(applied)
+}
+
+/// Writes ACH files and reads return files every `ACH_JOB_INTERVAL_SECS`
+/// when ACH is the active rail.
+pub fn spawn_job(pool: PgPool) {
+    if rails::rail().map(|r| r.name()) != Some(AchRail::NAME) {
+        return;
+    }
+    let config = AchConfig::from_env().expect("Invalid ACH configuration");
+    let every = env::var("ACH_JOB_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_JOB_INTERVAL_SECS);
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(every));
+        loop {
+            interval.tick().await;
+            if let Err(e) = generate_file(&pool, &config).await {
+                log::error!("ACH file generation failed: {}", e);
+            }
+            match process_returns(&pool, &config).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("applied {} ACH return(s)", n),
+                Err(e) => log::error!("ACH return processing failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AchConfig {
+        AchConfig {
+            immediate_destination: "021000021".to_string(),
+            immediate_origin: "1234567890".to_string(),
+            destination_name: "FED".to_string(),
+            origin_name: "DELTAUP".to_string(),
+            company_name: "DELTAUP".to_string(),
+            company_id: "1234567890".to_string(),
+            odfi: "02100002".to_string(),
+            outbox: PathBuf::new(),
+            returns: PathBuf::new(),
+        }
+    }
+
+    fn entry(routing_number: &str, amount: &str, sequence: u64) -> AchEntry {
+        AchEntry {
+            routing_number: routing_number.to_string(),
+            account_number: "123456789".to_string(),
+            amount: amount.parse().unwrap(),
+            individual_id: "ID".to_string(),
+            individual_name: "Jane Doe".to_string(),
+            trace_number: format!("02100002{}", numeric(sequence, 7)),
+        }
+    }
+
+    fn sample_file() -> String {
+        let created = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let entries = [entry("011000015", "123.45", 1), entry("021000021", "50.00", 2)];
+        build_file(&config(), created, 'A', effective_date(created.date()), &entries)
+    }
+
+    #[test]
+    fn records_are_94_characters_in_full_blocks() {
+        let file = sample_file();
+        let lines: Vec<&str> = file.lines().collect();
+        assert!(lines.iter().all(|l| l.len() == RECORD_LEN));
+        assert_eq!(lines.len(), 10);
+        assert_eq!(&lines[0][..3], "101");
+        assert_eq!(lines[6], "9".repeat(RECORD_LEN));
+        assert_eq!(lines[9], "9".repeat(RECORD_LEN));
+    }
+
+    #[test]
+    fn control_records_carry_hash_and_totals() {
+        let file = sample_file();
+        let lines: Vec<&str> = file.lines().collect();
+        let batch_control = lines[4];
+        assert_eq!(&batch_control[..4], "8220");
+        assert_eq!(&batch_control[4..10], "000002");
+        // 01100001 + 02100002
+        assert_eq!(&batch_control[10..20], "0003200003");
+        assert_eq!(&batch_control[20..32], "000000000000");
+        assert_eq!(&batch_control[32..44], "000000017345");
+
+        let file_control = lines[5];
+        assert_eq!(&file_control[..13], "9000001000001");
+        assert_eq!(&file_control[13..21], "00000002");
+        assert_eq!(&file_control[21..31], "0003200003");
+        assert_eq!(&file_control[43..55], "000000017345");
+    }
+
+    #[test]
+    fn entry_hash_wraps_at_ten_digits() {
+        let created = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let entries: Vec<AchEntry> = (1..=200).map(|n| entry("999999992", "1.00", n)).collect();
+        let file = build_file(&config(), created, 'A', effective_date(created.date()), &entries);
+        let batch_control = file.lines().nth(202).unwrap();
+        assert_eq!(&batch_control[10..20], numeric((99_999_999 * 200) % HASH_MODULUS, 10));
+        assert_eq!(file.lines().count() % BLOCKING_FACTOR, 0);
+    }
+
+    #[test]
+    fn effective_date_skips_the_weekend() {
+        let friday = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        assert_eq!(effective_date(friday), NaiveDate::from_ymd_opt(2026, 10, 19).unwrap());
+    }
+
+    #[test]
+    fn parses_return_addenda() {
+        let addenda = format!("799R01021000020000002      02100002{}021000020000009", " ".repeat(44));
+        let file = format!("{}\n{}\n", sample_file().lines().next().unwrap(), addenda);
+        let returns = parse_returns(&file).unwrap();
+        assert_eq!(returns.len(), 1);
+        assert_eq!(returns[0].return_code, "R01");
+        assert_eq!(returns[0].original_trace, "021000020000002");
+    }
+
+    #[test]
+    fn rejects_malformed_return_files() {
+        let header = sample_file().lines().next().unwrap().to_string();
+        assert!(matches!(parse_returns(&format!("{}\n799R01", header)), Err(AchError::Format(_))));
+        assert!(matches!(parse_returns(&"3".repeat(RECORD_LEN)), Err(AchError::Format(_))));
+        assert!(matches!(parse_returns(&"9".repeat(RECORD_LEN)), Err(AchError::Format(_))));
+        let bad_trace = format!("799R010210000200000X2      02100002{}021000020000009", " ".repeat(44));
+        assert!(matches!(parse_returns(&format!("{}\n{}", header, bad_trace)), Err(AchError::Format(_))));
+    }
+}
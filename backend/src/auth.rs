@@ -6,6 +6,10 @@ use bcrypt::{hash, verify, DEFAULT_COST};
 use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
 use std::env;
 use sqlx::PgPool;
+use crate::account_numbers;
+use crate::{funding, payments};
+use crate::kyc::KycTier;
+use crate::sanctions;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -18,6 +22,9 @@ pub struct RegisterRequest {
     pub username: String,
     pub email: String,
     pub password: String,
+    /// Legal name, screened against the sanctions list instead of the
+    /// username when given.
+    pub full_name: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -39,12 +46,12 @@ pub struct UserResponse {
     pub email: String,
     pub account_number: String,
     pub balance: f64,
+    pub kyc_tier: String,
 }
 
-fn generate_account_number() -> String {
-    let random_num: u64 = rand::random();
-    format!("{:012}", random_num % 1_000_000_000_000)
-}
+/// Fresh account numbers are tried this many times before giving up; with
+/// ten random digits a second collision in a row is vanishingly unlikely.
+const ACCOUNT_NUMBER_ATTEMPTS: usize = 5;
 
 fn create_jwt(user_id: &str) -> Result<String, jsonwebtoken::errors::Error> {
     let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
@@ -70,8 +77,27 @@ pub async fn register(
     req: web::Json<RegisterRequest>
 ) -> impl Responder {
     let user_id = Uuid::new_v4();
-    let account_number = generate_account_number();
-    
+    let full_name = req.full_name.as_deref().map(str::trim).filter(|n| !n.is_empty());
+
+    let screened_name = full_name.unwrap_or(&req.username);
+    let hits = sanctions::screen_name(screened_name);
+    if !hits.is_empty() {
+        let subject = sanctions::Subject::Registration { email: req.email.clone() };
+        let recorded = match pool.acquire().await {
+            Ok(mut conn) => sanctions::record_matches(&mut conn, &subject, screened_name, &hits).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = recorded {
+            log::error!("failed to record sanctions match: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+        log::warn!("registration of {} held on sanctions screening", req.email);
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "registration_under_review",
+            "message": "We could not open your account automatically. Our compliance team will contact you."
+        }));
+    }
+
     let password_hash = match hash(&req.password, DEFAULT_COST) {
         Ok(h) => h,
         Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
@@ -79,43 +105,100 @@ pub async fn register(
         })),
     };
 
+    // The user and their welcome credit are committed together, so every
+    // account that exists has been paid its credit
+    let mut tx = match pool.begin().await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
     // Store in database using query instead of query! to avoid compile-time DB requirement
-    let result = sqlx::query(
-        "INSERT INTO users (id, username, email, password_hash, account_number, balance) VALUES ($1, $2, $3, $4, $5, $6)"
-    )
-    .bind(user_id)
-    .bind(&req.username)
-    .bind(&req.email)
-    .bind(password_hash)
-    .bind(&account_number)
-    .bind(rust_decimal::Decimal::from(1000))
-    .execute(pool.get_ref())
-    .await;
+    let mut account_number = account_numbers::generate();
+    let mut result = Err(sqlx::Error::RowNotFound);
+    for _ in 0..ACCOUNT_NUMBER_ATTEMPTS {
+        // Additional accounts share the account number namespace
+        result = sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, account_number, full_name) \
+             SELECT $1, $2, $3, $4, $5, $6 WHERE NOT EXISTS (SELECT 1 FROM accounts WHERE account_number = $5) \
+             ON CONFLICT (account_number) DO NOTHING"
+        )
+        .bind(user_id)
+        .bind(&req.username)
+        .bind(&req.email)
+        .bind(&password_hash)
+        .bind(&account_number)
+        .bind(full_name)
+        .execute(&mut *tx)
+        .await;
 
+        match &result {
+            Ok(done) if done.rows_affected() == 0 => account_number = account_numbers::generate(),
+            _ => break,
+        }
+    }
     match result {
-        Ok(_) => {
-            let token = match create_jwt(&user_id.to_string()) {
-                Ok(t) => t,
-                Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "Failed to create token"
-                })),
-            };
-
-            let user = UserResponse {
-                id: user_id.to_string(),
-                username: req.username.clone(),
-                email: req.email.clone(),
-                account_number,
-                balance: 1000.0,
+        Ok(done) if done.rows_affected() == 0 => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Registration failed",
+                "message": "Could not allocate an account number, please try again"
+            }));
+        }
+        Ok(_) => {}
+        Err(sqlx::Error::Database(db)) if db.is_unique_violation() => {
+            let message = match db.constraint() {
+                Some("users_username_key") => "Username is already taken",
+                Some("users_email_key") => "Email is already registered",
+                _ => "Could not allocate an account number, please try again",
             };
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Registration failed",
+                "message": message
+            }));
+        }
+        Err(e) => {
+            log::error!("registration failed: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Registration failed"
+            }));
+        }
+    }
 
-            HttpResponse::Ok().json(AuthResponse { token, user })
-        },
-        Err(e) => HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Registration failed",
-            "message": e.to_string()
-        }))
+    // Accounts open at zero; a configured welcome credit is paid by the
+    // marketing house account
+    let mut balance = rust_decimal::Decimal::ZERO;
+    if let Some(amount) = funding::signup_credit() {
+        if let Err(e) = funding::grant_signup_credit(&mut tx, &account_number, amount).await {
+            log::error!("failed to pay signup credit to {}: {}", account_number, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Registration failed"
+            }));
+        }
+        balance = amount;
+    }
+    if let Err(e) = tx.commit().await {
+        log::error!("registration failed: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Registration failed"
+        }));
     }
+
+    let token = match create_jwt(&user_id.to_string()) {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to create token"
+        })),
+    };
+
+    let user = UserResponse {
+        id: user_id.to_string(),
+        username: req.username.clone(),
+        email: req.email.clone(),
+        account_number,
+        balance: payments::decimal_to_f64(balance),
+        kyc_tier: KycTier::Unverified.as_str().to_string(),
+    };
+
+    HttpResponse::Ok().json(AuthResponse { token, user })
 }
 
 pub async fn login(
@@ -123,7 +206,7 @@ pub async fn login(
     req: web::Json<LoginRequest>
 ) -> impl Responder {
     let user_row = sqlx::query_as::<_, UserRow>(
-        "SELECT id, username, email, password_hash, account_number, balance FROM users WHERE email = $1"
+        "SELECT id, username, email, password_hash, account_number, balance, kyc_tier FROM users WHERE email = $1"
     )
     .bind(&req.email)
     .fetch_optional(pool.get_ref())
@@ -156,6 +239,7 @@ pub async fn login(
         email: user_row.email,
         account_number: user_row.account_number,
         balance: user_row.balance.to_string().parse().unwrap_or(0.0),
+        kyc_tier: user_row.kyc_tier,
     };
 
     HttpResponse::Ok().json(AuthResponse { token, user })
@@ -169,6 +253,7 @@ struct UserRow {
     password_hash: String,
     account_number: String,
     balance: rust_decimal::Decimal,
+    kyc_tier: String,
 }
 
 pub async fn get_profile(
@@ -202,7 +287,7 @@ pub async fn get_profile(
     };
 
     let user_record = sqlx::query_as::<_, ProfileRow>(
-        "SELECT id, username, email, account_number, balance FROM users WHERE id = $1"
+        "SELECT id, username, email, account_number, balance, kyc_tier FROM users WHERE id = $1"
     )
     .bind(user_uuid)
     .fetch_optional(pool.get_ref())
@@ -216,6 +301,7 @@ pub async fn get_profile(
                 email: row.email,
                 account_number: row.account_number,
                 balance: row.balance.to_string().parse().unwrap_or(0.0),
+                kyc_tier: row.kyc_tier,
             };
             HttpResponse::Ok().json(user)
         },
@@ -231,5 +317,6 @@ struct ProfileRow {
     email: String,
     account_number: String,
     balance: rust_decimal::Decimal,
+    kyc_tier: String,
 }
 
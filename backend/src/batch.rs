@@ -0,0 +1,658 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::{fees, recipients};
+use crate::payments::{self, TransferError};
+
+/// Upper bound on lines accepted in a single batch upload.
+const MAX_BATCH_ITEMS: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct BatchQuery {
+    pub mode: Option<BatchMode>,
+}
+
+#[derive(Deserialize)]
+struct CsvLine {
+    recipient_account: String,
+    amount: String,
+    description: Option<String>,
+}
+
+/// One parsed line of a batch, before it is persisted.
+struct BatchLine {
+    line_number: i32,
+    recipient_account: String,
+    amount: Option<Decimal>,
+    description: Option<String>,
+    error: Option<(&'static str, String)>,
+}
+
+impl BatchLine {
+    fn new(line_number: i32, recipient_account: String, amount: Option<Decimal>, description: Option<String>) -> Self {
+        let description = description.filter(|d| !d.trim().is_empty());
+        BatchLine { line_number, recipient_account: recipient_account.trim().to_string(), amount, description, error: None }
+    }
+
+    fn invalid(line_number: i32, code: &'static str, message: String) -> Self {
+        BatchLine {
+            line_number,
+            recipient_account: String::new(),
+            amount: None,
+            description: None,
+            error: Some((code, message)),
+        }
+    }
+}
+
+fn parse_csv(body: &[u8]) -> Result<Vec<BatchLine>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+
+    // Validate the header row up front so a wrong file fails as a whole
+    reader.headers()?;
+
+    Ok(reader
+        .deserialize::<CsvLine>()
+        .enumerate()
+        .map(|(idx, row)| {
+            let line_number = idx as i32 + 1;
+            match row {
+                Ok(row) => match Decimal::from_str(&row.amount) {
+                    Ok(amount) => BatchLine::new(line_number, row.recipient_account, Some(amount), row.description),
+                    Err(_) => {
+                        let mut line = BatchLine::new(line_number, row.recipient_account, None, row.description);
+                        line.error = Some(("invalid_amount", format!("'{}' is not a valid amount", row.amount)));
+                        line
+                    }
+                },
+                Err(e) => BatchLine::invalid(line_number, "invalid_line", e.to_string()),
+            }
+        })
+        .collect())
+}
+
+/// Checks each line and replaces its recipient with the account number it
+/// resolved to in `resolved`, keyed by what the line gave.
+fn validate_lines(lines: &mut [BatchLine], resolved: &HashMap<String, String>) {
+    for line in lines.iter_mut().filter(|l| l.error.is_none()) {
+        line.error = if line.recipient_account.is_empty() {
+            Some(("invalid_recipient", "Recipient account is required".to_string()))
+        } else if let Some(account_number) = resolved.get(&line.recipient_account) {
+            line.recipient_account = account_number.clone();
+            match line.amount {
+                Some(a) if a <= Decimal::ZERO => Some(("invalid_amount", TransferError::InvalidAmount.to_string())),
+                Some(a) if a.scale() > 2 => Some(("invalid_amount", "Amount must have at most 2 decimal places".to_string())),
+                Some(_) => None,
+                None => Some(("invalid_amount", TransferError::InvalidAmount.to_string())),
+            }
+        } else {
+            let error = recipients::not_found_error(&line.recipient_account);
+            Some((error.code(), error.to_string()))
+        };
+    }
+}
+
+pub async fn create_batch(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    query: web::Query<BatchQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let (mode, mut lines) = if req.content_type() == "text/csv" {
+        match parse_csv(&body) {
+            Ok(lines) => (query.mode.unwrap_or_default(), lines),
+            Err(e) => return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "invalid_csv".to_string(),
+                message: e.to_string(),
+            }),
+        }
+    } else {
+        let request: BatchTransferRequest = match serde_json::from_slice(&body) {
+            Ok(r) => r,
+            Err(e) => return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "invalid_request".to_string(),
+                message: e.to_string(),
+            }),
+        };
+        let lines = request.items.into_iter().enumerate().map(|(idx, item)| {
+            let line_number = idx as i32 + 1;
+            // Payees carry their own first-payment limits, which batches do not apply
+            if item.payee_id.is_some() {
+                return BatchLine::invalid(
+                    line_number,
+                    "payee_not_supported",
+                    "Saved payees cannot be paid in a batch; give recipient_account instead".to_string(),
+                );
+            }
+            BatchLine::new(line_number, item.recipient_account.unwrap_or_default(), Decimal::from_f64(item.amount), item.description)
+        }).collect();
+        (request.mode.or(query.mode).unwrap_or_default(), lines)
+    };
+
+    if lines.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "empty_batch".to_string(),
+            message: "Batch must contain at least one transfer".to_string(),
+        });
+    }
+    if lines.len() > MAX_BATCH_ITEMS {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "batch_too_large".to_string(),
+            message: format!("Batch may contain at most {} transfers", MAX_BATCH_ITEMS),
+        });
+    }
+
+    // Lines may name the recipient any way a single transfer can
+    let mut resolved = HashMap::new();
+    let wanted: HashSet<&str> =
+        lines.iter().filter(|l| l.error.is_none() && !l.recipient_account.is_empty()).map(|l| l.recipient_account.as_str()).collect();
+    for input in wanted {
+        match recipients::resolve(pool.get_ref(), input).await {
+            Ok(Some((_, recipient))) => {
+                resolved.insert(input.to_string(), recipient.account_number);
+            }
+            Ok(None) => {}
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    validate_lines(&mut lines, &resolved);
+
+    let invalid_count = lines.iter().filter(|l| l.error.is_some()).count();
+    let rejected = mode == BatchMode::AllOrNothing && invalid_count > 0;
+    let total_amount: Decimal = lines.iter().filter(|l| l.error.is_none()).filter_map(|l| l.amount).sum();
+
+    let batch_id = Uuid::new_v4();
+    let mut tx = match pool.begin().await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if sqlx::query(
+        "INSERT INTO transfer_batches (id, user_id, mode, status, total_items, failed, total_amount) VALUES ($1, $2, $3, $4, $5, $6, $7)"
+    )
+    .bind(batch_id)
+    .bind(user_id)
+    .bind(mode.as_str())
+    .bind(if rejected { "rejected" } else { "processing" })
+    .bind(lines.len() as i32)
+    .bind(invalid_count as i32)
+    .bind(total_amount)
+    .execute(&mut *tx)
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    for line in &lines {
+        let (status, error, message) = match &line.error {
+            Some((code, message)) => ("invalid", Some(*code), Some(message.as_str())),
+            None if rejected => ("skipped", None, Some("Batch rejected because other lines are invalid")),
+            None => ("pending", None, None),
+        };
+        if sqlx::query(
+            "INSERT INTO transfer_batch_items (batch_id, line_number, recipient_account, amount, description, status, error, message) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+        )
+        .bind(batch_id)
+        .bind(line.line_number)
+        .bind(&line.recipient_account)
+        .bind(line.amount.map(|a| a.round_dp(2)))
+        .bind(&line.description)
+        .bind(status)
+        .bind(error)
+        .bind(message)
+        .execute(&mut *tx)
+        .await
+        .is_err()
+        {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    if rejected
+        && sqlx::query("UPDATE transfer_batches SET completed_at = NOW() WHERE id = $1")
+            .bind(batch_id)
+            .execute(&mut *tx)
+            .await
+            .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if !rejected {
+        spawn_processing(pool.get_ref().clone(), batch_id, user_id, mode);
+    }
+
+    match fetch_batch(pool.get_ref(), batch_id, user_id).await {
+        Ok(Some(batch)) if rejected => HttpResponse::BadRequest().json(batch),
+        Ok(Some(batch)) => HttpResponse::Accepted().json(batch),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn get_batch(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match fetch_batch(pool.get_ref(), path.into_inner(), user_id).await {
+        Ok(Some(batch)) => HttpResponse::Ok().json(batch),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "batch_not_found".to_string(),
+            message: "Batch not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PendingItemRow {
+    line_number: i32,
+    recipient_account: String,
+    amount: Decimal,
+    description: Option<String>,
+}
+
+enum ItemResult {
+    Completed(Uuid),
+    Held(Uuid),
+    Failed(&'static str, String),
+    Cancelled(String),
+}
+
+impl ItemResult {
+    fn from_outcome(outcome: &payments::TransferOutcome) -> Self {
+        if outcome.held_for_review {
+            ItemResult::Held(outcome.transaction_id)
+        } else {
+            ItemResult::Completed(outcome.transaction_id)
+        }
+    }
+}
+
+/// Records the result of a pending line. Returns false if the line is no
+/// longer pending, in which case a payment made for it must be rolled back.
+async fn record_item(conn: &mut PgConnection, batch_id: Uuid, line_number: i32, result: &ItemResult) -> Result<bool, sqlx::Error> {
+    let (status, transaction_id, error, message) = match result {
+        ItemResult::Completed(id) => ("completed", Some(*id), None, None),
+        // Accepted, with the funds held until a reviewer releases it
+        ItemResult::Held(id) => ("pending_review", Some(*id), None, None),
+        ItemResult::Failed(code, message) => ("failed", None, Some(*code), Some(message.as_str())),
+        ItemResult::Cancelled(message) => ("cancelled", None, None, Some(message.as_str())),
+    };
+    let updated = sqlx::query(
+        "UPDATE transfer_batch_items SET status = $1, transaction_id = $2, error = $3, message = $4 \
+         WHERE batch_id = $5 AND line_number = $6 AND status = 'pending'"
+    )
+    .bind(status)
+    .bind(transaction_id)
+    .bind(error)
+    .bind(message)
+    .bind(batch_id)
+    .bind(line_number)
+    .execute(conn)
+    .await?;
+    Ok(updated.rows_affected() == 1)
+}
+
+/// Overall status once every line has been tried, from the number of lines
+/// that went through and the number that were tried and failed.
+fn batch_status(succeeded: i64, failed: i64) -> &'static str {
+    if failed == 0 {
+        "completed"
+    } else if succeeded == 0 {
+        "failed"
+    } else {
+        "partially_completed"
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ItemCountsRow {
+    succeeded: i64,
+    failed: i64,
+    invalid: i64,
+}
+
+/// Closes a batch with totals taken from its lines.
+async fn finish_batch(pool: &PgPool, batch_id: Uuid) -> Result<(), sqlx::Error> {
+    let counts = sqlx::query_as::<_, ItemCountsRow>(
+        "SELECT COUNT(*) FILTER (WHERE status IN ('completed', 'pending_review')) AS succeeded, \
+                COUNT(*) FILTER (WHERE status IN ('failed', 'cancelled')) AS failed, \
+                COUNT(*) FILTER (WHERE status = 'invalid') AS invalid \
+         FROM transfer_batch_items WHERE batch_id = $1"
+    )
+    .bind(batch_id)
+    .fetch_one(pool)
+    .await?;
+
+    sqlx::query(
+        "UPDATE transfer_batches SET status = $1, succeeded = $2, failed = $3, completed_at = NOW() WHERE id = $4"
+    )
+    .bind(batch_status(counts.succeeded, counts.failed))
+    .bind(counts.succeeded as i32)
+    .bind((counts.failed + counts.invalid) as i32)
+    .bind(batch_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Executes every pending line of a batch. In all-or-nothing mode the lines
+/// share one database transaction and the first failure rolls back the rest;
+/// in best-effort mode each line commits on its own. A line's result is
+/// stored on the same transaction as its payment, so a batch interrupted
+/// part way can be resumed without paying any line twice.
+async fn process_batch(pool: &PgPool, batch_id: Uuid, user_id: Uuid, mode: BatchMode) -> Result<(), sqlx::Error> {
+    let items = sqlx::query_as::<_, PendingItemRow>(
+        "SELECT line_number, recipient_account, amount, description FROM transfer_batch_items WHERE batch_id = $1 AND status = 'pending' ORDER BY line_number"
+    )
+    .bind(batch_id)
+    .fetch_all(pool)
+    .await?;
+
+    match mode {
+        BatchMode::BestEffort => {
+            for item in &items {
+                let mut tx = pool.begin().await?;
+                let result = match payments::transfer(
+                    &mut tx,
+                    user_id,
+                    &item.recipient_account,
+                    item.amount,
+                    item.description.as_deref(),
+                    fees::BATCH,
+                ).await {
+                    Ok(outcome) => ItemResult::from_outcome(&outcome),
+                    Err(e) => {
+                        tx.rollback().await?;
+                        let mut conn = pool.acquire().await?;
+                        record_item(&mut conn, batch_id, item.line_number, &ItemResult::Failed(e.code(), e.to_string())).await?;
+                        continue;
+                    }
+                };
+                if !record_item(&mut tx, batch_id, item.line_number, &result).await? {
+                    continue;
+                }
+                if let Err(e) = tx.commit().await {
+                    let mut conn = pool.acquire().await?;
+                    record_item(&mut conn, batch_id, item.line_number, &ItemResult::Failed("internal_error", e.to_string())).await?;
+                }
+            }
+        }
+        BatchMode::AllOrNothing => {
+            let mut tx = pool.begin().await?;
+            let mut failure: Option<(i32, TransferError)> = None;
+            for item in &items {
+                match payments::transfer(
+                    &mut tx,
+                    user_id,
+                    &item.recipient_account,
+                    item.amount,
+                    item.description.as_deref(),
+                    fees::BATCH,
+                ).await {
+                    Ok(outcome) => {
+                        if !record_item(&mut tx, batch_id, item.line_number, &ItemResult::from_outcome(&outcome)).await? {
+                            // Another run has already taken this batch
+                            return Ok(());
+                        }
+                    }
+                    Err(e) => {
+                        failure = Some((item.line_number, e));
+                        break;
+                    }
+                }
+            }
+
+            let failure = match failure {
+                Some(f) => {
+                    tx.rollback().await?;
+                    Some(f)
+                }
+                None => tx.commit().await.err().map(|e| (0, TransferError::Database(e))),
+            };
+
+            if let Some((failed_line, e)) = failure {
+                // Nothing was committed; report the culprit and cancel every other line
+                let mut conn = pool.acquire().await?;
+                for item in &items {
+                    let result = if item.line_number == failed_line {
+                        ItemResult::Failed(e.code(), e.to_string())
+                    } else {
+                        ItemResult::Cancelled(format!("Batch rolled back: {}", e))
+                    };
+                    record_item(&mut conn, batch_id, item.line_number, &result).await?;
+                }
+            }
+        }
+    }
+
+    finish_batch(pool, batch_id).await
+}
+
+/// Gives up on a batch whose processing failed: lines not yet executed are
+/// marked failed and the batch is closed with what did go through.
+async fn abandon_batch(pool: &PgPool, batch_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE transfer_batch_items SET status = 'failed', error = 'internal_error', \
+             message = 'Batch processing was interrupted' \
+         WHERE batch_id = $1 AND status = 'pending'"
+    )
+    .bind(batch_id)
+    .execute(pool)
+    .await?;
+    finish_batch(pool, batch_id).await
+}
+
+/// Processes a batch in the background, closing it as failed if that does
+/// not succeed.
+fn spawn_processing(pool: PgPool, batch_id: Uuid, user_id: Uuid, mode: BatchMode) {
+    actix_rt::spawn(async move {
+        if let Err(e) = process_batch(&pool, batch_id, user_id, mode).await {
+            log::error!("batch {} failed to process: {}", batch_id, e);
+            if let Err(e) = abandon_batch(&pool, batch_id).await {
+                log::error!("batch {} could not be marked failed: {}", batch_id, e);
+            }
+        }
+    });
+}
+
+#[derive(sqlx::FromRow)]
+struct ProcessingBatchRow {
+    id: Uuid,
+    user_id: Uuid,
+    mode: String,
+}
+
+/// Picks up batches left `processing` by a restart. Lines already executed
+/// were recorded with their payment, so only the pending ones are run.
+pub async fn resume_processing(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let batches = sqlx::query_as::<_, ProcessingBatchRow>(
+        "SELECT id, user_id, mode FROM transfer_batches WHERE status = 'processing' ORDER BY created_at"
+    )
+    .fetch_all(pool)
+    .await?;
+    let count = batches.len();
+    for batch in batches {
+        match BatchMode::parse(&batch.mode) {
+            Some(mode) => spawn_processing(pool.clone(), batch.id, batch.user_id, mode),
+            None => {
+                log::error!("batch {} has unknown mode '{}'", batch.id, batch.mode);
+                abandon_batch(pool, batch.id).await?;
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[derive(sqlx::FromRow)]
+struct BatchRow {
+    id: Uuid,
+    mode: String,
+    status: String,
+    total_items: i32,
+    succeeded: i32,
+    failed: i32,
+    total_amount: Decimal,
+    created_at: NaiveDateTime,
+    completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(sqlx::FromRow)]
+struct BatchItemRow {
+    line_number: i32,
+    recipient_account: String,
+    amount: Option<Decimal>,
+    description: Option<String>,
+    status: String,
+    transaction_id: Option<Uuid>,
+    error: Option<String>,
+    message: Option<String>,
+}
+
+async fn fetch_batch(pool: &PgPool, batch_id: Uuid, user_id: Uuid) -> Result<Option<BatchResponse>, sqlx::Error> {
+    let batch = match sqlx::query_as::<_, BatchRow>(
+        "SELECT id, mode, status, total_items, succeeded, failed, total_amount, created_at, completed_at FROM transfer_batches WHERE id = $1 AND user_id = $2"
+    )
+    .bind(batch_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await? {
+        Some(b) => b,
+        None => return Ok(None),
+    };
+
+    let items = sqlx::query_as::<_, BatchItemRow>(
+        "SELECT line_number, recipient_account, amount, description, status, transaction_id, error, message FROM transfer_batch_items WHERE batch_id = $1 ORDER BY line_number"
+    )
+    .bind(batch_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(BatchResponse {
+        batch_id: batch.id.to_string(),
+        mode: batch.mode,
+        status: batch.status,
+        total_items: batch.total_items,
+        succeeded: batch.succeeded,
+        failed: batch.failed,
+        total_amount: payments::decimal_to_f64(batch.total_amount),
+        created_at: batch.created_at.and_utc().to_rfc3339(),
+        completed_at: batch.completed_at.map(|t| t.and_utc().to_rfc3339()),
+        items: items.into_iter().map(|i| BatchItemResponse {
+            line_number: i.line_number,
+            recipient_account: i.recipient_account,
+            amount: i.amount.map(payments::decimal_to_f64),
+            description: i.description,
+            status: i.status,
+            transaction_id: i.transaction_id.map(|id| id.to_string()),
+            error: i.error,
+            message: i.message,
+        }).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn error_code(line: &BatchLine) -> Option<&'static str> {
+        line.error.as_ref().map(|(code, _)| *code)
+    }
+
+    #[test]
+    fn parses_csv_lines() {
+        let csv = "recipient_account,amount,description\n\
+                   123456789092, 12.50 ,Rent\n\
+                   $alice,7,\n\
+                   987654321012,ten,Lunch\n\
+                   555555555012\n";
+        let lines = parse_csv(csv.as_bytes()).unwrap();
+        assert_eq!(lines.len(), 4);
+
+        assert_eq!(lines[0].line_number, 1);
+        assert_eq!(lines[0].recipient_account, "123456789092");
+        assert_eq!(lines[0].amount, Some(dec("12.50")));
+        assert_eq!(lines[0].description.as_deref(), Some("Rent"));
+        assert!(lines[0].error.is_none());
+
+        assert_eq!(lines[1].recipient_account, "$alice");
+        assert_eq!(lines[1].description, None);
+
+        assert_eq!(lines[2].amount, None);
+        assert_eq!(error_code(&lines[2]), Some("invalid_amount"));
+        assert_eq!(error_code(&lines[3]), Some("invalid_line"));
+        assert_eq!(lines[3].line_number, 4);
+    }
+
+    #[test]
+    fn rejects_csv_without_the_expected_columns() {
+        let lines = parse_csv(b"account,value\n123456789092,5\n").unwrap();
+        assert_eq!(error_code(&lines[0]), Some("invalid_line"));
+        assert!(parse_csv(b"recipient_account,amount\n\xff,5\n").is_ok_and(|l| error_code(&l[0]) == Some("invalid_line")));
+    }
+
+    #[test]
+    fn validates_lines_against_resolved_recipients() {
+        let resolved = HashMap::from([
+            ("$alice".to_string(), "123456789092".to_string()),
+            ("987654321012".to_string(), "987654321012".to_string()),
+        ]);
+        let mut lines = vec![
+            BatchLine::new(1, "$alice".to_string(), Some(dec("10.00")), None),
+            BatchLine::new(2, "987654321012".to_string(), Some(dec("0")), None),
+            BatchLine::new(3, "987654321012".to_string(), Some(dec("1.005")), None),
+            BatchLine::new(4, " ".to_string(), Some(dec("5")), None),
+            BatchLine::new(5, "$nobody".to_string(), Some(dec("5")), None),
+            BatchLine::invalid(6, "payee_not_supported", "Saved payees cannot be paid in a batch".to_string()),
+        ];
+        validate_lines(&mut lines, &resolved);
+
+        assert!(lines[0].error.is_none());
+        assert_eq!(lines[0].recipient_account, "123456789092");
+        assert_eq!(error_code(&lines[1]), Some("invalid_amount"));
+        assert_eq!(error_code(&lines[2]), Some("invalid_amount"));
+        assert_eq!(error_code(&lines[3]), Some("invalid_recipient"));
+        assert_eq!(lines[4].recipient_account, "$nobody");
+        assert_eq!(error_code(&lines[4]), Some(recipients::not_found_error("$nobody").code()));
+        assert_eq!(error_code(&lines[5]), Some("payee_not_supported"));
+    }
+
+    #[test]
+    fn batch_status_from_line_results() {
+        assert_eq!(batch_status(3, 0), "completed");
+        assert_eq!(batch_status(0, 0), "completed");
+        assert_eq!(batch_status(0, 2), "failed");
+        assert_eq!(batch_status(1, 2), "partially_completed");
+    }
+}
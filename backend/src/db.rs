@@ -59,13 +59,619 @@ pub async fn init_db(database_url: &str) -> Result<sqlx::PgPool, sqlx::Error> {
     .execute(&pool)
     .await?;
 
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS transfer_batches (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL,
+            mode VARCHAR(20) NOT NULL,
+            status VARCHAR(30) NOT NULL,
+            total_items INTEGER NOT NULL,
+            succeeded INTEGER NOT NULL DEFAULT 0,
+            failed INTEGER NOT NULL DEFAULT 0,
+            total_amount DECIMAL(15, 2) NOT NULL DEFAULT 0.00,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            completed_at TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS transfer_batch_items (
+            batch_id UUID NOT NULL REFERENCES transfer_batches(id),
+            line_number INTEGER NOT NULL,
+            recipient_account VARCHAR(50) NOT NULL,
+            amount DECIMAL(15, 2),
+            description TEXT,
+            status VARCHAR(20) NOT NULL,
+            transaction_id UUID,
+            error VARCHAR(50),
+            message TEXT,
+            PRIMARY KEY (batch_id, line_number)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payment_requests (
+            id UUID PRIMARY KEY,
+            requester_id UUID NOT NULL REFERENCES users(id),
+            payee_id UUID NOT NULL REFERENCES users(id),
+            amount DECIMAL(15, 2) NOT NULL,
+            note TEXT,
+            status VARCHAR(20) NOT NULL,
+            transaction_id UUID,
+            expires_at TIMESTAMP NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            responded_at TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS qr_codes (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            account_number VARCHAR(50) NOT NULL,
+            amount DECIMAL(15, 2),
+            reference VARCHAR(140),
+            one_time BOOLEAN NOT NULL DEFAULT FALSE,
+            payload TEXT NOT NULL,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            last_transaction_id UUID,
+            revoked_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payment_initiations (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            message_id VARCHAR(35) NOT NULL,
+            status VARCHAR(20) NOT NULL,
+            accepted INTEGER NOT NULL DEFAULT 0,
+            rejected INTEGER NOT NULL DEFAULT 0,
+            report TEXT,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            completed_at TIMESTAMP,
+            UNIQUE (user_id, message_id)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS statements (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            account_number VARCHAR(50) NOT NULL,
+            period VARCHAR(7) NOT NULL,
+            period_start TIMESTAMP NOT NULL,
+            period_end TIMESTAMP NOT NULL,
+            opening_balance DECIMAL(15, 2) NOT NULL,
+            total_debits DECIMAL(15, 2) NOT NULL,
+            total_credits DECIMAL(15, 2) NOT NULL,
+            closing_balance DECIMAL(15, 2) NOT NULL,
+            transaction_count INTEGER NOT NULL,
+            closed_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            UNIQUE (user_id, period)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Closed periods are frozen: refuse entries dated inside them
+    sqlx::query(
+        r#"
+        CREATE OR REPLACE FUNCTION refuse_closed_period_entries() RETURNS trigger AS $$
+        BEGIN
+            IF EXISTS (
+                SELECT 1 FROM statements s
+                WHERE s.account_number IN (NEW.from_account, NEW.to_account)
+                  AND NEW.created_at < s.period_end
+            ) THEN
+                RAISE EXCEPTION 'accounting period is closed for %', NEW.created_at
+                    USING ERRCODE = 'DU001';
+            END IF;
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("DROP TRIGGER IF EXISTS transactions_closed_period ON transactions")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER transactions_closed_period
+        BEFORE INSERT OR UPDATE OF created_at, amount, from_account, to_account ON transactions
+        FOR EACH ROW EXECUTE FUNCTION refuse_closed_period_entries()
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payees (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            nickname VARCHAR(100) NOT NULL,
+            account_number VARCHAR(50) NOT NULL,
+            default_description TEXT,
+            first_paid_at TIMESTAMP,
+            last_paid_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            UNIQUE (user_id, account_number)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS aliases (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            alias VARCHAR(40) NOT NULL UNIQUE,
+            kind VARCHAR(10) NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Phone aliases only resolve once the number is verified; tags need no proof
+    sqlx::query("ALTER TABLE aliases ADD COLUMN IF NOT EXISTS verified_at TIMESTAMP")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query("UPDATE aliases SET verified_at = created_at WHERE kind = 'tag' AND verified_at IS NULL")
+        .execute(&pool)
+        .await?;
+
+    // Rows that exist when this column is added predate check digits; new rows do not
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS legacy_account_number BOOLEAN NOT NULL DEFAULT TRUE")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE users ALTER COLUMN legacy_account_number SET DEFAULT FALSE")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS accounts (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            account_number VARCHAR(50) NOT NULL UNIQUE,
+            name VARCHAR(50) NOT NULL,
+            kind VARCHAR(20) NOT NULL,
+            balance DECIMAL(15, 2) NOT NULL DEFAULT 0.00 CHECK (balance >= 0),
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            closed_at TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS house_accounts (
+            code VARCHAR(40) PRIMARY KEY,
+            account_number VARCHAR(50) NOT NULL UNIQUE,
+            name VARCHAR(100) NOT NULL,
+            balance DECIMAL(20, 2) NOT NULL DEFAULT 0.00,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO house_accounts (code, account_number, name) VALUES
+            ('interest_expense', 'HOUSE-INTEREST-EXPENSE', 'Interest expense'),
+            ('fee_revenue', 'HOUSE-FEE-REVENUE', 'Fee revenue'),
+            ('marketing', 'HOUSE-MARKETING', 'Marketing promotions'),
+            ('deposits', 'HOUSE-DEPOSITS', 'Branch and admin deposits'),
+            ('card_topups', 'HOUSE-CARD-TOPUPS', 'Card top-up settlement'),
+            ('external_clearing', 'HOUSE-EXTERNAL-CLEARING', 'External rail clearing')
+        ON CONFLICT (code) DO NOTHING
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS interest_rate_tiers (
+            id SERIAL PRIMARY KEY,
+            product VARCHAR(20) NOT NULL,
+            min_balance DECIMAL(15, 2) NOT NULL,
+            annual_rate DECIMAL(9, 6) NOT NULL,
+            day_count VARCHAR(10) NOT NULL DEFAULT 'ACT/365',
+            UNIQUE (product, min_balance)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Default savings tiers; edit the table to change rates
+    sqlx::query(
+        r#"
+        INSERT INTO interest_rate_tiers (product, min_balance, annual_rate, day_count)
+        SELECT * FROM (VALUES
+            ('savings', 0.00, 0.020000, 'ACT/365'),
+            ('savings', 10000.00, 0.030000, 'ACT/365')
+        ) AS defaults
+        WHERE NOT EXISTS (SELECT 1 FROM interest_rate_tiers WHERE product = 'savings')
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE accounts ADD COLUMN IF NOT EXISTS interest_carry DECIMAL(20, 10) NOT NULL DEFAULT 0")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS interest_accruals (
+            id UUID PRIMARY KEY,
+            account_id UUID NOT NULL REFERENCES accounts(id),
+            accrual_date DATE NOT NULL,
+            balance DECIMAL(15, 2) NOT NULL,
+            amount DECIMAL(20, 10) NOT NULL,
+            posted_at TIMESTAMP,
+            transaction_id UUID REFERENCES transactions(id),
+            UNIQUE (account_id, accrual_date)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE transactions ADD COLUMN IF NOT EXISTS parent_transaction_id UUID REFERENCES transactions(id)")
+        .execute(&pool)
+        .await?;
+
+    // When a payment held for review was approved; created_at stays the time it was made
+    sqlx::query("ALTER TABLE transactions ADD COLUMN IF NOT EXISTS completed_at TIMESTAMP")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS fee_schedules (
+            id SERIAL PRIMARY KEY,
+            transaction_type VARCHAR(30) NOT NULL,
+            account_tier VARCHAR(20) NOT NULL DEFAULT 'default',
+            min_amount DECIMAL(15, 2) NOT NULL DEFAULT 0.00,
+            flat_fee DECIMAL(15, 2) NOT NULL DEFAULT 0.00,
+            percentage DECIMAL(9, 6) NOT NULL DEFAULT 0,
+            min_fee DECIMAL(15, 2),
+            max_fee DECIMAL(15, 2),
+            active BOOLEAN NOT NULL DEFAULT TRUE,
+            UNIQUE (transaction_type, account_tier, min_amount)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Default: merchant QR payments pay 1.5%, at least 0.10 and at most 10.00
+    sqlx::query(
+        r#"
+        INSERT INTO fee_schedules (transaction_type, percentage, min_fee, max_fee)
+        SELECT 'qr_payment', 0.015, 0.10, 10.00
+        WHERE NOT EXISTS (SELECT 1 FROM fee_schedules)
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS transaction_limits (
+            id SERIAL PRIMARY KEY,
+            tier VARCHAR(20) UNIQUE,
+            user_id UUID UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+            per_transaction DECIMAL(15, 2),
+            daily DECIMAL(15, 2),
+            monthly DECIMAL(15, 2),
+            hourly_count INTEGER CHECK (hourly_count >= 0),
+            updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            CHECK ((tier IS NULL) <> (user_id IS NULL))
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO transaction_limits (tier, per_transaction, daily, monthly, hourly_count)
+        VALUES ('default', 5000.00, 10000.00, 50000.00, 20),
+               ('unverified', 250.00, 500.00, 1000.00, 5),
+               ('basic', 2000.00, 5000.00, 20000.00, 20),
+               ('full', 10000.00, 25000.00, 100000.00, 50)
+        ON CONFLICT (tier) DO NOTHING
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Limits on money arriving from outside the bank; unset, the outgoing
+    // daily and monthly limits apply
+    for column in ["inbound_daily DECIMAL(15, 2)", "inbound_monthly DECIMAL(15, 2)"] {
+        sqlx::query(&format!("ALTER TABLE transaction_limits ADD COLUMN IF NOT EXISTS {}", column))
+            .execute(&pool)
+            .await?;
+    }
+
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS held_balance DECIMAL(15, 2) NOT NULL DEFAULT 0.00 CHECK (held_balance >= 0)")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payment_reviews (
+            transaction_id UUID PRIMARY KEY REFERENCES transactions(id),
+            payment_type VARCHAR(30) NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    let review_columns = [
+        "ALTER TABLE payment_reviews ADD COLUMN IF NOT EXISTS decision VARCHAR(10) CHECK (decision IN ('approved', 'rejected'))",
+        "ALTER TABLE payment_reviews ADD COLUMN IF NOT EXISTS reviewed_by UUID REFERENCES users(id)",
+        "ALTER TABLE payment_reviews ADD COLUMN IF NOT EXISTS reason TEXT",
+        "ALTER TABLE payment_reviews ADD COLUMN IF NOT EXISTS decided_at TIMESTAMP",
+    ];
+    for cmd in review_columns {
+        sqlx::query(cmd).execute(&pool).await?;
+    }
+
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS role VARCHAR(20) NOT NULL DEFAULT 'customer'")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notifications (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            kind VARCHAR(40) NOT NULL,
+            message TEXT NOT NULL,
+            transaction_id UUID REFERENCES transactions(id),
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            read_at TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS screening_hits (
+            id UUID PRIMARY KEY,
+            transaction_id UUID NOT NULL REFERENCES transactions(id),
+            rule_name VARCHAR(100) NOT NULL,
+            action VARCHAR(10) NOT NULL,
+            detail TEXT NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE users ADD COLUMN IF NOT EXISTS full_name VARCHAR(255)")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sanctions_matches (
+            id UUID PRIMARY KEY,
+            subject_type VARCHAR(20) NOT NULL,
+            screened_name VARCHAR(255) NOT NULL,
+            email VARCHAR(255),
+            transaction_id UUID REFERENCES transactions(id),
+            list_uid VARCHAR(50) NOT NULL,
+            list_name TEXT NOT NULL,
+            program TEXT NOT NULL,
+            score DOUBLE PRECISION NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS kyc_tier VARCHAR(20) NOT NULL DEFAULT 'unverified' \
+         CHECK (kyc_tier IN ('unverified', 'basic', 'full'))"
+    )
+    .execute(&pool)
+    .await?;
+
+    // Fee rows are priced per KYC tier; a row for any other tier would never apply
+    sqlx::query("ALTER TABLE fee_schedules DROP CONSTRAINT IF EXISTS fee_schedules_account_tier_check")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        "ALTER TABLE fee_schedules ADD CONSTRAINT fee_schedules_account_tier_check \
+         CHECK (account_tier IN ('default', 'unverified', 'basic', 'full'))"
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS kyc_submissions (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            requested_tier VARCHAR(20) NOT NULL CHECK (requested_tier IN ('basic', 'full')),
+            legal_name VARCHAR(255) NOT NULL,
+            date_of_birth DATE NOT NULL,
+            address TEXT NOT NULL,
+            status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'approved', 'rejected')),
+            reviewed_by UUID REFERENCES users(id),
+            reason TEXT,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            decided_at TIMESTAMP
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS kyc_documents (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            submission_id UUID REFERENCES kyc_submissions(id),
+            kind VARCHAR(30) NOT NULL,
+            content_type VARCHAR(50) NOT NULL,
+            size_bytes BIGINT NOT NULL,
+            sha256 VARCHAR(64) NOT NULL,
+            storage_path TEXT NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("ALTER TABLE sanctions_matches ADD COLUMN IF NOT EXISTS kyc_submission_id UUID REFERENCES kyc_submissions(id)")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query("ALTER TABLE sanctions_matches ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES users(id)")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS external_transfers (
+            id UUID PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id),
+            direction VARCHAR(10) NOT NULL CHECK (direction IN ('deposit', 'withdrawal')),
+            amount DECIMAL(15, 2) NOT NULL CHECK (amount > 0),
+            status VARCHAR(10) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'settled', 'failed', 'returned')),
+            rail VARCHAR(20) NOT NULL,
+            rail_reference VARCHAR(64),
+            routing_number VARCHAR(9) NOT NULL,
+            external_account VARCHAR(17) NOT NULL,
+            account_holder VARCHAR(22) NOT NULL,
+            return_code VARCHAR(3),
+            transaction_id UUID REFERENCES transactions(id),
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            settled_at TIMESTAMP,
+            UNIQUE (rail, rail_reference)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ach_files (
+            id UUID PRIMARY KEY,
+            file_name VARCHAR(100) NOT NULL UNIQUE,
+            file_date DATE NOT NULL,
+            file_id_modifier CHAR(1) NOT NULL,
+            entry_count INTEGER NOT NULL,
+            total_credit DECIMAL(15, 2) NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+            UNIQUE (file_date, file_id_modifier)
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("CREATE SEQUENCE IF NOT EXISTS ach_trace_seq MINVALUE 1 MAXVALUE 9999999 NO CYCLE")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query("ALTER SEQUENCE ach_trace_seq NO CYCLE")
+        .execute(&pool)
+        .await?;
+
+    for column in [
+        "ach_file_id UUID REFERENCES ach_files(id)",
+        "ach_trace_number VARCHAR(15)",
+    ] {
+        sqlx::query(&format!("ALTER TABLE external_transfers ADD COLUMN IF NOT EXISTS {}", column))
+            .execute(&pool)
+            .await?;
+    }
+
     // Run index creation commands separately to avoid "multiple commands in prepared statement" error
     let index_commands = [
         "CREATE INDEX IF NOT EXISTS idx_users_email ON users(email)",
         "CREATE INDEX IF NOT EXISTS idx_users_account_number ON users(account_number)",
         "CREATE INDEX IF NOT EXISTS idx_transactions_from_account ON transactions(from_account)",
         "CREATE INDEX IF NOT EXISTS idx_transactions_to_account ON transactions(to_account)",
+        "CREATE INDEX IF NOT EXISTS idx_transactions_created_at ON transactions(created_at DESC, id DESC)",
         "CREATE INDEX IF NOT EXISTS idx_oauth_codes_user_id ON oauth_codes(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_transfer_batches_user_id ON transfer_batches(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_payment_requests_payee_id ON payment_requests(payee_id)",
+        "CREATE INDEX IF NOT EXISTS idx_payment_requests_requester_id ON payment_requests(requester_id)",
+        "CREATE INDEX IF NOT EXISTS idx_qr_codes_user_id ON qr_codes(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_payees_user_id ON payees(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_aliases_user_id ON aliases(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_accounts_user_id ON accounts(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_payment_reviews_undecided ON payment_reviews(created_at) WHERE decision IS NULL",
+        "CREATE INDEX IF NOT EXISTS idx_notifications_user_id ON notifications(user_id, created_at DESC)",
+        "CREATE INDEX IF NOT EXISTS idx_screening_hits_transaction_id ON screening_hits(transaction_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_kyc_submissions_one_pending ON kyc_submissions(user_id) WHERE status = 'pending'",
+        "CREATE INDEX IF NOT EXISTS idx_kyc_documents_user_id ON kyc_documents(user_id)",
+        "CREATE INDEX IF NOT EXISTS idx_external_transfers_user_id ON external_transfers(user_id, created_at DESC)",
+        "CREATE INDEX IF NOT EXISTS idx_external_transfers_ach_trace ON external_transfers(ach_trace_number) WHERE ach_trace_number IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_external_transfers_open ON external_transfers(rail, created_at) WHERE status IN ('pending', 'settled')",
+        "CREATE INDEX IF NOT EXISTS idx_transactions_parent ON transactions(parent_transaction_id) WHERE parent_transaction_id IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_interest_accruals_unposted ON interest_accruals(account_id, accrual_date) WHERE posted_at IS NULL",
+        "CREATE INDEX IF NOT EXISTS idx_users_email_lower ON users(LOWER(email))",
+        "CREATE INDEX IF NOT EXISTS idx_statements_account_period_end ON statements(account_number, period_end DESC)",
     ];
 
     for cmd in index_commands {
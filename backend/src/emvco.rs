@@ -0,0 +1,294 @@
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use crate::models::FieldError;
+
+/// Globally unique identifier placed in sub-tag 00 of our merchant account
+/// information template.
+pub const DELTAUP_GUID: &str = "COM.DELTAUP";
+/// ISO 4217 numeric code for USD, the only currency DeltaUp settles in.
+pub const CURRENCY_USD: &str = "840";
+
+const TAG_PAYLOAD_FORMAT: &str = "00";
+const TAG_POINT_OF_INITIATION: &str = "01";
+const TAG_MCC: &str = "52";
+const TAG_CURRENCY: &str = "53";
+const TAG_AMOUNT: &str = "54";
+const TAG_COUNTRY: &str = "58";
+const TAG_MERCHANT_NAME: &str = "59";
+const TAG_MERCHANT_CITY: &str = "60";
+const TAG_ADDITIONAL_DATA: &str = "62";
+const TAG_CRC: &str = "63";
+
+const SUBTAG_GUID: &str = "00";
+const SUBTAG_ACCOUNT: &str = "01";
+const SUBTAG_BILL_NUMBER: &str = "01";
+const SUBTAG_REFERENCE_LABEL: &str = "05";
+
+fn field_error(field: &str, message: impl Into<String>) -> FieldError {
+    FieldError { field: field.to_string(), message: message.into() }
+}
+
+/// Payment details extracted from a merchant-presented EMVCo QR code.
+#[derive(Debug)]
+pub struct EmvPayment {
+    pub account: String,
+    pub amount: Option<Decimal>,
+    pub reference: Option<String>,
+    pub merchant_name: String,
+}
+
+/// Returns true when the string looks like an EMVCo MPM payload rather than
+/// one of our signed JSON codes.
+pub fn is_emvco(data: &str) -> bool {
+    data.trim().starts_with("000201")
+}
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF) as mandated by EMVCo.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Splits a TLV string into `(tag, value)` pairs, preserving order.
+fn parse_tlv(data: &str, context: &str) -> Result<Vec<(String, String)>, FieldError> {
+    let mut fields = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        if rest.len() < 4 || !rest.is_char_boundary(4) {
+            return Err(field_error(context, "Truncated tag header"));
+        }
+        let (tag, len) = (&rest[..2], &rest[2..4]);
+        if !tag.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(field_error(context, format!("Invalid tag '{}'", tag)));
+        }
+        let len: usize = len
+            .parse()
+            .map_err(|_| field_error(tag, "Invalid length"))?;
+        let value = rest
+            .get(4..4 + len)
+            .ok_or_else(|| field_error(tag, "Value is shorter than its declared length"))?;
+        fields.push((tag.to_string(), value.to_string()));
+        rest = &rest[4 + len..];
+    }
+    Ok(fields)
+}
+
+/// Parses and validates a merchant-presented QR string, collecting every
+/// field-level problem instead of stopping at the first one.
+pub fn parse(data: &str) -> Result<EmvPayment, Vec<FieldError>> {
+    let data = data.trim();
+
+    // The CRC covers everything up to and including the "6304" header
+    let crc_error = match data.len().checked_sub(4).filter(|i| data.is_char_boundary(*i)) {
+        Some(split) if data[..split].ends_with("6304") => {
+            let expected = format!("{:04X}", crc16(&data.as_bytes()[..split]));
+            if data[split..].eq_ignore_ascii_case(&expected) {
+                None
+            } else {
+                Some(field_error(TAG_CRC, "CRC checksum does not match"))
+            }
+        }
+        _ => Some(field_error(TAG_CRC, "CRC must be the last field")),
+    };
+    if let Some(e) = crc_error {
+        return Err(vec![e]);
+    }
+
+    let fields: BTreeMap<String, String> = parse_tlv(data, "payload")
+        .map_err(|e| vec![e])?
+        .into_iter()
+        .collect();
+    let mut errors = Vec::new();
+
+    if fields.get(TAG_PAYLOAD_FORMAT).map(String::as_str) != Some("01") {
+        errors.push(field_error(TAG_PAYLOAD_FORMAT, "Payload format indicator must be 01"));
+    }
+
+    if let Some(poi) = fields.get(TAG_POINT_OF_INITIATION) {
+        if poi != "11" && poi != "12" {
+            errors.push(field_error(TAG_POINT_OF_INITIATION, "Point of initiation must be 11 or 12"));
+        }
+    }
+
+    // Merchant account information lives in one of the templates 26..=51
+    let mut account = None;
+    for (tag, value) in fields.range("26".to_string()..="51".to_string()) {
+        match parse_tlv(value, tag) {
+            Ok(sub) => {
+                let sub: BTreeMap<_, _> = sub.into_iter().collect();
+                if sub.get(SUBTAG_GUID).is_some_and(|g| g.eq_ignore_ascii_case(DELTAUP_GUID)) {
+                    match sub.get(SUBTAG_ACCOUNT) {
+                        Some(a) if !a.is_empty() => account = Some(a.clone()),
+                        _ => errors.push(field_error(tag, "Merchant account number is missing")),
+                    }
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    if account.is_none() && !errors.iter().any(|e| ("26".."52").contains(&e.field.as_str())) {
+        errors.push(field_error("26", "No DeltaUp merchant account information template"));
+    }
+
+    match fields.get(TAG_MCC) {
+        Some(mcc) if mcc.len() == 4 && mcc.bytes().all(|b| b.is_ascii_digit()) => {}
+        _ => errors.push(field_error(TAG_MCC, "Merchant category code must be 4 digits")),
+    }
+
+    match fields.get(TAG_CURRENCY).map(String::as_str) {
+        Some(CURRENCY_USD) => {}
+        Some(other) => errors.push(field_error(TAG_CURRENCY, format!("Unsupported currency '{}'", other))),
+        None => errors.push(field_error(TAG_CURRENCY, "Transaction currency is required")),
+    }
+
+    let amount = match fields.get(TAG_AMOUNT) {
+        Some(raw) => match Decimal::from_str(raw) {
+            Ok(a) if a > Decimal::ZERO && a.scale() <= 2 => Some(a),
+            _ => {
+                errors.push(field_error(TAG_AMOUNT, format!("Invalid transaction amount '{}'", raw)));
+                None
+            }
+        },
+        None => None,
+    };
+
+    match fields.get(TAG_COUNTRY) {
+        Some(c) if c.len() == 2 => {}
+        _ => errors.push(field_error(TAG_COUNTRY, "Country code must be 2 characters")),
+    }
+
+    let merchant_name = match fields.get(TAG_MERCHANT_NAME) {
+        Some(n) if !n.is_empty() => n.clone(),
+        _ => {
+            errors.push(field_error(TAG_MERCHANT_NAME, "Merchant name is required"));
+            String::new()
+        }
+    };
+
+    if fields.get(TAG_MERCHANT_CITY).is_none_or(|c| c.is_empty()) {
+        errors.push(field_error(TAG_MERCHANT_CITY, "Merchant city is required"));
+    }
+
+    let mut reference = None;
+    if let Some(value) = fields.get(TAG_ADDITIONAL_DATA) {
+        match parse_tlv(value, TAG_ADDITIONAL_DATA) {
+            Ok(sub) => {
+                let sub: BTreeMap<_, _> = sub.into_iter().collect();
+                reference = sub
+                    .get(SUBTAG_REFERENCE_LABEL)
+                    .or_else(|| sub.get(SUBTAG_BILL_NUMBER))
+                    .cloned();
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    match (account, errors.is_empty()) {
+        (Some(account), true) => Ok(EmvPayment { account, amount, reference, merchant_name }),
+        _ => Err(errors),
+    }
+}
+
+fn tlv(tag: &str, value: &str) -> String {
+    format!("{}{:02}{}", tag, value.len(), value)
+}
+
+/// EMVCo lengths count bytes of an ASCII alphabet, so drop anything else.
+fn ascii_field(value: &str, max: usize) -> String {
+    value.chars().filter(char::is_ascii).take(max).collect()
+}
+
+/// Builds an EMVCo MPM string for a DeltaUp merchant account. Codes with an
+/// amount are marked dynamic (12), the rest static (11).
+pub fn generate(
+    account: &str,
+    merchant_name: &str,
+    merchant_city: &str,
+    mcc: &str,
+    amount: Option<Decimal>,
+    reference: Option<&str>,
+) -> String {
+    let mut out = String::new();
+    out.push_str(&tlv(TAG_PAYLOAD_FORMAT, "01"));
+    out.push_str(&tlv(TAG_POINT_OF_INITIATION, if amount.is_some() { "12" } else { "11" }));
+    out.push_str(&tlv("26", &(tlv(SUBTAG_GUID, DELTAUP_GUID) + &tlv(SUBTAG_ACCOUNT, account))));
+    out.push_str(&tlv(TAG_MCC, mcc));
+    out.push_str(&tlv(TAG_CURRENCY, CURRENCY_USD));
+    if let Some(amount) = amount {
+        out.push_str(&tlv(TAG_AMOUNT, &format!("{:.2}", amount)));
+    }
+    out.push_str(&tlv(TAG_COUNTRY, "US"));
+    out.push_str(&tlv(TAG_MERCHANT_NAME, &ascii_field(merchant_name, 25)));
+    out.push_str(&tlv(TAG_MERCHANT_CITY, &ascii_field(merchant_city, 15)));
+    if let Some(reference) = reference {
+        out.push_str(&tlv(TAG_ADDITIONAL_DATA, &tlv(SUBTAG_REFERENCE_LABEL, &ascii_field(reference, 25))));
+    }
+    out.push_str("6304");
+    let crc = crc16(out.as_bytes());
+    out.push_str(&format!("{:04X}", crc));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The merchant-presented example from the EMVCo MPM specification.
+    const SPEC_EXAMPLE: &str = "00020101021229300012D156000000000510A93FO3230Q31280012D15600000001030812345678520441115802CN5914BEST TRANSPORT6007BEIJING64200002ZH0104最佳运输0202北京540523.7253031565502016233030412340603***0708A60086670902ME91320016A0112233449988770708123456786304A13A";
+
+    #[test]
+    fn crc16_matches_known_values() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        let split = SPEC_EXAMPLE.len() - 4;
+        assert_eq!(crc16(&SPEC_EXAMPLE.as_bytes()[..split]), 0xA13A);
+    }
+
+    #[test]
+    fn generated_codes_parse_back() {
+        let amount = Decimal::from_str("12.50").unwrap();
+        let payload = generate("123456789012", "Corner Café", "Springfield", "5812", Some(amount), Some("INV-42"));
+        assert!(payload.starts_with("000201010212"));
+        assert_eq!(&payload[payload.len() - 8..payload.len() - 4], "6304");
+
+        let payment = parse(&payload).unwrap();
+        assert_eq!(payment.account, "123456789012");
+        assert_eq!(payment.amount, Some(amount));
+        assert_eq!(payment.reference.as_deref(), Some("INV-42"));
+        assert_eq!(payment.merchant_name, "Corner Caf");
+
+        let static_code = generate("123456789012", "Shop", "Town", "5411", None, None);
+        assert!(static_code.starts_with("000201010211"));
+        assert_eq!(parse(&static_code).unwrap().amount, None);
+    }
+
+    #[test]
+    fn rejects_a_wrong_crc() {
+        let payload = generate("123456789012", "Shop", "Town", "5411", None, None);
+        let last = if payload.ends_with('0') { '1' } else { '0' };
+        let tampered = format!("{}{}", &payload[..payload.len() - 1], last);
+        let errors = parse(&tampered).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, TAG_CRC);
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        // The specification example without its language template: a valid
+        // CRC, but no DeltaUp template and a non-USD currency
+        let foreign = "00020101021229300012D156000000000510A93FO3230Q31280012D15600000001030812345678520441115802CN\
+                       5914BEST TRANSPORT6007BEIJING540523.7253031565502016233030412340603***0708A60086670902ME\
+                       91320016A0112233449988770708123456786304FF8B";
+        let errors = parse(foreign).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert!(fields.contains(&"26"));
+        assert!(fields.contains(&TAG_CURRENCY));
+        assert!(!fields.contains(&TAG_CRC));
+    }
+}
@@ -0,0 +1,207 @@
+//! Fee engine. A `fee_schedules` row charges `flat_fee + amount * percentage`,
+//! bounded by optional `min_fee` and `max_fee`. Tiered pricing is several
+//! rows for the same transaction type with increasing `min_amount`; the row
+//! with the highest threshold not above the amount applies. Rows for the
+//! payer's KYC tier (`unverified`, `basic` or `full`, looked up with
+//! [`kyc::tier_of`] when the fee is quoted) take precedence over the
+//! `default` tier.
+//!
+//! Fees are paid by the payer on top of the amount and posted as a separate
+//! ledger line to the fee revenue house account, linked to the payment via
+//! `parent_transaction_id`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::{house, kyc};
+use crate::models::*;
+use crate::payments::{self, TransferError};
+
+pub const TRANSFER: &str = "transfer";
+pub const QR_PAYMENT: &str = "qr_payment";
+pub const TRANSACTION_TYPES: [&str; 2] = [TRANSFER, QR_PAYMENT];
+// Payment types screened by the fraud rules that carry no fee
+pub const BATCH: &str = "batch";
+pub const PAYMENT_REQUEST: &str = "payment_request";
+pub const PAIN001: &str = "pain001";
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct FeeScheduleRow {
+    flat_fee: Decimal,
+    percentage: Decimal,
+    min_fee: Option<Decimal>,
+    max_fee: Option<Decimal>,
+}
+
+impl FeeScheduleRow {
+    fn fee_for(&self, amount: Decimal) -> Decimal {
+        let mut fee = self.flat_fee + amount * self.percentage;
+        if let Some(min) = self.min_fee {
+            fee = fee.max(min);
+        }
+        if let Some(max) = self.max_fee {
+            fee = fee.min(max);
+        }
+        fee.max(Decimal::ZERO)
+            .round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+    }
+}
+
+/// Fee due on a payment of `amount`, zero when no schedule matches.
+pub async fn quote<'e, E: PgExecutor<'e>>(
+    executor: E,
+    transaction_type: &str,
+    account_tier: &str,
+    amount: Decimal,
+) -> Result<Decimal, sqlx::Error> {
+    let schedule = sqlx::query_as::<_, FeeScheduleRow>(
+        "SELECT flat_fee, percentage, min_fee, max_fee FROM fee_schedules \
+         WHERE active AND transaction_type = $1 AND account_tier IN ($2, 'default') AND min_amount <= $3 \
+         ORDER BY (account_tier = $2) DESC, min_amount DESC LIMIT 1"
+    )
+    .bind(transaction_type)
+    .bind(account_tier)
+    .bind(amount)
+    .fetch_optional(executor)
+    .await?;
+    Ok(schedule.map(|s| s.fee_for(amount)).unwrap_or(Decimal::ZERO))
+}
+
+/// Debits `fee` from the payer and credits fee revenue, on the caller's
+/// transaction. The payer's row must already be locked by the payment.
+pub async fn charge(
+    conn: &mut PgConnection,
+    payer_id: Uuid,
+    payer_account: &str,
+    fee: Decimal,
+    transaction_type: &str,
+    parent_transaction_id: Uuid,
+) -> Result<Uuid, TransferError> {
+    let balance = sqlx::query_scalar::<_, Decimal>("SELECT balance - held_balance FROM users WHERE id = $1 FOR UPDATE")
+        .bind(payer_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    if balance < fee {
+        return Err(TransferError::InsufficientFunds);
+    }
+
+    sqlx::query("UPDATE users SET balance = balance - $1 WHERE id = $2")
+        .bind(fee)
+        .bind(payer_id)
+        .execute(&mut *conn)
+        .await?;
+    let revenue_account = house::adjust(&mut *conn, house::FEE_REVENUE, fee).await?;
+
+    let description = format!("Fee: {}", transaction_type.replace('_', " "));
+    let (fee_transaction_id, _) = payments::record_transaction_with_parent(
+        &mut *conn,
+        payer_account,
+        &revenue_account,
+        fee,
+        Some(&description),
+        Some(parent_transaction_id),
+    )
+    .await?;
+    Ok(fee_transaction_id)
+}
+
+/// Quotes and charges the fee for a payment just executed on `conn`.
+/// Returns the fee and, when one was charged, its ledger line.
+pub async fn apply(
+    conn: &mut PgConnection,
+    payer_id: Uuid,
+    payment: &payments::TransferOutcome,
+    transaction_type: &str,
+) -> Result<(Decimal, Option<Uuid>), TransferError> {
+    let tier = kyc::tier_of(&mut *conn, payer_id).await?;
+    let fee = quote(&mut *conn, transaction_type, tier.as_str(), payment.amount).await?;
+    if fee.is_zero() {
+        return Ok((fee, None));
+    }
+    let fee_transaction_id = charge(
+        conn,
+        payer_id,
+        &payment.from_account,
+        fee,
+        transaction_type,
+        payment.transaction_id,
+    )
+    .await?;
+    Ok((fee, Some(fee_transaction_id)))
+}
+
+#[derive(Deserialize)]
+pub struct FeeQuoteQuery {
+    pub transaction_type: String,
+    pub amount: f64,
+}
+
+/// `GET /api/fees/quote` shows the fee before the payment is made.
+pub async fn get_quote(pool: web::Data<PgPool>, req: HttpRequest, query: web::Query<FeeQuoteQuery>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    if !TRANSACTION_TYPES.contains(&query.transaction_type.as_str()) {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_transaction_type".to_string(),
+            message: format!("transaction_type must be one of {}", TRANSACTION_TYPES.join(", ")),
+        });
+    }
+    let amount = match payments::parse_amount(query.amount) {
+        Some(a) => a,
+        None => return TransferError::InvalidAmount.to_response(),
+    };
+
+    let tier = match kyc::tier_of(pool.get_ref(), user_id).await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match quote(pool.get_ref(), &query.transaction_type, tier.as_str(), amount).await {
+        Ok(fee) => HttpResponse::Ok().json(FeeQuoteResponse {
+            transaction_type: query.transaction_type.clone(),
+            amount: payments::decimal_to_f64(amount),
+            fee: payments::decimal_to_f64(fee),
+            total: payments::decimal_to_f64(amount + fee),
+            currency: "USD".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    fn schedule(flat_fee: &str, percentage: &str, min_fee: Option<&str>, max_fee: Option<&str>) -> FeeScheduleRow {
+        FeeScheduleRow {
+            flat_fee: dec(flat_fee),
+            percentage: dec(percentage),
+            min_fee: min_fee.map(dec),
+            max_fee: max_fee.map(dec),
+        }
+    }
+
+    #[test]
+    fn fee_is_clamped_to_min_and_max() {
+        let qr = schedule("0", "0.015", Some("0.10"), Some("10.00"));
+        assert_eq!(qr.fee_for(dec("100")), dec("1.50"));
+        assert_eq!(qr.fee_for(dec("2")), dec("0.10"));
+        assert_eq!(qr.fee_for(dec("5000")), dec("10.00"));
+    }
+
+    #[test]
+    fn fee_rounds_half_away_from_zero() {
+        let flat_and_percent = schedule("0.25", "0.01", None, None);
+        assert_eq!(flat_and_percent.fee_for(dec("10.50")), dec("0.36"));
+        assert_eq!(schedule("-1", "0", None, None).fee_for(dec("10")), Decimal::ZERO);
+    }
+}
@@ -0,0 +1,372 @@
+//! Rule-based fraud and AML screening of outgoing payments. Rules are read
+//! at startup from the JSON file named by `FRAUD_RULES_PATH`, falling back to
+//! the bundled `config/fraud_rules.json`. Every rule that matches a payment
+//! is a hit; the payment gets the strictest action among its hits, so one
+//! `block` hit declines it and one `review` hit holds it for review.
+
+use chrono::{Duration, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sqlx::PgConnection;
+use std::env;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+const DEFAULT_RULES: &str = include_str!("../config/fraud_rules.json");
+
+static RULES: OnceLock<RuleSet> = OnceLock::new();
+
+/// What a matching rule does to a payment. Ordered from least to most strict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    Allow,
+    Review,
+    Block,
+}
+
+impl RuleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleAction::Allow => "allow",
+            RuleAction::Review => "review",
+            RuleAction::Block => "block",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RuleKind {
+    /// Any single payment of at least `min_amount`.
+    LargeAmount { min_amount: Decimal },
+    /// A payment of at least `min_amount` to an account the sender has never
+    /// paid before.
+    NewPayeeHighAmount { min_amount: Decimal },
+    FanOut(FanOutRule),
+    Structuring(StructuringRule),
+}
+
+/// Paying more than `max_recipients` different accounts within
+/// `window_minutes`.
+#[derive(Debug, Deserialize)]
+struct FanOutRule {
+    max_recipients: i64,
+    window_minutes: i64,
+}
+
+impl FanOutRule {
+    /// Judges a payment given the number of different accounts paid within
+    /// the window, its own recipient included.
+    fn hit(&self, recipients: i64) -> Option<String> {
+        (recipients > self.max_recipients)
+            .then(|| format!("{} recipients within {} minutes", recipients, self.window_minutes))
+    }
+}
+
+/// `min_count` payments, this one included, within `window_hours` that fall
+/// just under a reporting threshold: at least `threshold - margin` and below
+/// `threshold`, and a multiple of `round_to` if given.
+#[derive(Debug, Deserialize)]
+struct StructuringRule {
+    threshold: Decimal,
+    margin: Decimal,
+    round_to: Option<Decimal>,
+    min_count: i64,
+    window_hours: i64,
+}
+
+impl StructuringRule {
+    fn in_band(&self, amount: Decimal) -> bool {
+        amount >= self.threshold - self.margin
+            && amount < self.threshold
+            && self.round_to.is_none_or(|r| (amount % r).is_zero())
+    }
+
+    /// Judges a payment of `amount` given the sender's payments within the
+    /// window.
+    fn hit(&self, amount: Decimal, recent: &[Decimal]) -> Option<String> {
+        if !self.in_band(amount) {
+            return None;
+        }
+        let count = recent.iter().filter(|a| self.in_band(**a)).count() as i64 + 1;
+        (count >= self.min_count).then(|| {
+            format!("{} payments just under {} within {} hours", count, self.threshold, self.window_hours)
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Rule {
+    name: String,
+    action: RuleAction,
+    /// Payment types the rule applies to; empty means all of them.
+    #[serde(default)]
+    applies_to: Vec<String>,
+    #[serde(flatten)]
+    kind: RuleKind,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    fn parse(json: &str) -> Result<RuleSet, String> {
+        let set: RuleSet = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for rule in &set.rules {
+            let valid = match &rule.kind {
+                RuleKind::LargeAmount { min_amount } | RuleKind::NewPayeeHighAmount { min_amount } => {
+                    *min_amount > Decimal::ZERO
+                }
+                RuleKind::FanOut(rule) => rule.max_recipients > 0 && rule.window_minutes > 0,
+                RuleKind::Structuring(rule) => {
+                    rule.margin > Decimal::ZERO
+                        && rule.margin < rule.threshold
+                        && rule.round_to.is_none_or(|r| r > Decimal::ZERO)
+                        && rule.min_count > 0
+                        && rule.window_hours > 0
+                }
+            };
+            if rule.name.trim().is_empty() || !valid {
+                return Err(format!("rule '{}' is not valid", rule.name));
+            }
+        }
+        Ok(set)
+    }
+}
+
+/// Loads the rule set. Called once at startup so a broken file stops the
+/// server instead of letting payments through unscreened.
+pub fn init() -> Result<usize, String> {
+    let json = match env::var("FRAUD_RULES_PATH") {
+        Ok(path) => std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?,
+        Err(_) => DEFAULT_RULES.to_string(),
+    };
+    let set = RuleSet::parse(&json)?;
+    let count = set.rules.len();
+    RULES.set(set).map_err(|_| "fraud rules already loaded".to_string())?;
+    Ok(count)
+}
+
+fn rules() -> &'static RuleSet {
+    RULES.get_or_init(|| RuleSet::parse(DEFAULT_RULES).expect("bundled fraud rules are valid"))
+}
+
+/// A rule that matched a payment.
+#[derive(Debug, Clone)]
+pub struct RuleHit {
+    pub rule: String,
+    pub action: RuleAction,
+    pub detail: String,
+}
+
+/// The outcome of screening one payment.
+#[derive(Debug)]
+pub struct Screening {
+    pub action: RuleAction,
+    pub hits: Vec<RuleHit>,
+}
+
+/// A payment about to leave `from_account`, which belongs to `sender_id`.
+pub struct Payment<'a> {
+    pub payment_type: &'a str,
+    pub sender_id: Uuid,
+    pub from_account: &'a str,
+    pub to_account: &'a str,
+    pub amount: Decimal,
+}
+
+// Outgoing payments counted by the history-based rules: completed or held,
+// excluding fee lines and moves between the sender's own accounts.
+const OUTGOING: &str = "from_account = $1 AND status IN ('completed', 'pending_review') \
+     AND parent_transaction_id IS NULL \
+     AND to_account NOT IN (SELECT account_number FROM accounts WHERE user_id = $2)";
+
+async fn evaluate(conn: &mut PgConnection, kind: &RuleKind, payment: &Payment<'_>) -> Result<Option<String>, sqlx::Error> {
+    match kind {
+        RuleKind::LargeAmount { min_amount } => Ok((payment.amount >= *min_amount)
+            .then(|| format!("amount {} is at least {}", payment.amount, min_amount))),
+        RuleKind::NewPayeeHighAmount { min_amount } => {
+            if payment.amount < *min_amount {
+                return Ok(None);
+            }
+            let paid_before = sqlx::query_scalar::<_, bool>(&format!(
+                "SELECT EXISTS (SELECT 1 FROM transactions WHERE {} AND to_account = $3 AND status = 'completed')",
+                OUTGOING
+            ))
+            .bind(payment.from_account)
+            .bind(payment.sender_id)
+            .bind(payment.to_account)
+            .fetch_one(&mut *conn)
+            .await?;
+            Ok((!paid_before).then(|| format!("first payment to this account is {}", payment.amount)))
+        }
+        RuleKind::FanOut(rule) => {
+            let recipients = sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) FROM (\
+                     SELECT to_account FROM transactions WHERE {} AND created_at >= $4 \
+                     UNION SELECT $3\
+                 ) AS recipients",
+                OUTGOING
+            ))
+            .bind(payment.from_account)
+            .bind(payment.sender_id)
+            .bind(payment.to_account)
+            .bind(Utc::now().naive_utc() - Duration::minutes(rule.window_minutes))
+            .fetch_one(&mut *conn)
+            .await?;
+            Ok(rule.hit(recipients))
+        }
+        RuleKind::Structuring(rule) => {
+            if !rule.in_band(payment.amount) {
+                return Ok(None);
+            }
+            let recent = sqlx::query_scalar::<_, Decimal>(&format!(
+                "SELECT amount FROM transactions WHERE {} AND created_at >= $3 \
+                 AND amount >= $4 AND amount < $5",
+                OUTGOING
+            ))
+            .bind(payment.from_account)
+            .bind(payment.sender_id)
+            .bind(Utc::now().naive_utc() - Duration::hours(rule.window_hours))
+            .bind(rule.threshold - rule.margin)
+            .bind(rule.threshold)
+            .fetch_all(&mut *conn)
+            .await?;
+            Ok(rule.hit(payment.amount, &recent))
+        }
+    }
+}
+
+/// Runs every applicable rule against `payment` on the caller's connection.
+pub async fn screen(conn: &mut PgConnection, payment: &Payment<'_>) -> Result<Screening, sqlx::Error> {
+    let mut hits = Vec::new();
+    for rule in &rules().rules {
+        if !rule.applies_to.is_empty() && !rule.applies_to.iter().any(|t| t == payment.payment_type) {
+            continue;
+        }
+        if let Some(detail) = evaluate(&mut *conn, &rule.kind, payment).await? {
+            hits.push(RuleHit { rule: rule.name.clone(), action: rule.action, detail });
+        }
+    }
+    let action = hits.iter().map(|h| h.action).max().unwrap_or(RuleAction::Allow);
+    Ok(Screening { action, hits })
+}
+
+/// Stores the rule hits of a recorded payment.
+pub async fn record_hits(conn: &mut PgConnection, transaction_id: Uuid, hits: &[RuleHit]) -> Result<(), sqlx::Error> {
+    for hit in hits {
+        sqlx::query(
+            "INSERT INTO screening_hits (id, transaction_id, rule_name, action, detail) VALUES ($1, $2, $3, $4, $5)"
+        )
+        .bind(Uuid::new_v4())
+        .bind(transaction_id)
+        .bind(&hit.rule)
+        .bind(hit.action.as_str())
+        .bind(&hit.detail)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn parse_one(rule: &str) -> Result<RuleSet, String> {
+        RuleSet::parse(&format!(r#"{{"rules": [{}]}}"#, rule))
+    }
+
+    fn structuring() -> StructuringRule {
+        StructuringRule {
+            threshold: dec("10000"),
+            margin: dec("1000"),
+            round_to: Some(dec("100")),
+            min_count: 2,
+            window_hours: 72,
+        }
+    }
+
+    #[test]
+    fn bundled_rules_parse() {
+        let set = RuleSet::parse(DEFAULT_RULES).unwrap();
+        assert_eq!(set.rules.len(), 6);
+        let qr = set.rules.iter().find(|r| r.name == "qr_structuring_below_1000").unwrap();
+        assert_eq!(qr.applies_to, vec!["qr_payment".to_string()]);
+        assert_eq!(qr.action, RuleAction::Review);
+        assert!(matches!(&qr.kind, RuleKind::Structuring(rule) if rule.min_count == 3));
+        assert!(set.rules.iter().find(|r| r.name == "large_amount").unwrap().applies_to.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_rule_types_and_actions() {
+        assert!(parse_one(r#"{"name": "x", "type": "geo_velocity", "action": "review"}"#).is_err());
+        assert!(parse_one(r#"{"name": "x", "type": "large_amount", "min_amount": 100, "action": "flag"}"#).is_err());
+        assert!(parse_one(r#"{"name": "x", "min_amount": 100, "action": "block"}"#).is_err());
+        assert!(parse_one(r#"{"name": "x", "type": "large_amount", "min_amount": 100, "action": "block"}"#).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        assert!(parse_one(r#"{"name": " ", "type": "large_amount", "min_amount": 100, "action": "block"}"#).is_err());
+        assert!(parse_one(r#"{"name": "x", "type": "large_amount", "min_amount": 0, "action": "block"}"#).is_err());
+        assert!(parse_one(r#"{"name": "x", "type": "fan_out", "max_recipients": 0, "window_minutes": 60, "action": "review"}"#).is_err());
+        assert!(parse_one(
+            r#"{"name": "x", "type": "structuring", "threshold": 1000, "margin": 1000, "min_count": 2, "window_hours": 24, "action": "review"}"#
+        )
+        .is_err());
+        assert!(parse_one(
+            r#"{"name": "x", "type": "structuring", "threshold": 1000, "margin": 100, "round_to": 0, "min_count": 2, "window_hours": 24, "action": "review"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn structuring_band() {
+        let rule = structuring();
+        assert!(rule.in_band(dec("9000")));
+        assert!(rule.in_band(dec("9900")));
+        assert!(!rule.in_band(dec("8900")));
+        assert!(!rule.in_band(dec("10000")));
+        assert!(!rule.in_band(dec("9950")));
+
+        let unrounded = StructuringRule { round_to: None, ..structuring() };
+        assert!(unrounded.in_band(dec("9999.99")));
+    }
+
+    #[test]
+    fn structuring_needs_repeated_payments_in_band() {
+        let rule = structuring();
+        assert!(rule.hit(dec("9500"), &[]).is_none());
+        assert!(rule.hit(dec("9500"), &[dec("9950"), dec("8000")]).is_none());
+        assert_eq!(
+            rule.hit(dec("9500"), &[dec("9800")]).as_deref(),
+            Some("2 payments just under 10000 within 72 hours")
+        );
+        // A payment outside the band never triggers, whatever came before
+        assert!(rule.hit(dec("10000"), &[dec("9800"), dec("9900")]).is_none());
+    }
+
+    #[test]
+    fn fan_out_triggers_above_the_recipient_count() {
+        let rule = FanOutRule { max_recipients: 5, window_minutes: 60 };
+        assert!(rule.hit(5).is_none());
+        assert_eq!(rule.hit(6).as_deref(), Some("6 recipients within 60 minutes"));
+    }
+
+    #[test]
+    fn strictest_action_wins() {
+        assert!(RuleAction::Block > RuleAction::Review);
+        assert!(RuleAction::Review > RuleAction::Allow);
+        let hits = [RuleAction::Review, RuleAction::Block, RuleAction::Allow];
+        assert_eq!(hits.iter().copied().max(), Some(RuleAction::Block));
+    }
+}
@@ -0,0 +1,245 @@
+//! Ways money enters a customer account. New accounts open at zero; funds
+//! arrive through a promotional signup credit, an admin deposit or a card
+//! top-up through the simulated provider. Each credit is a ledger line from
+//! a house account, so the bank's books always balance.
+//!
+//! The simulated provider approves test cards without charging anyone, so
+//! it is only offered with `SIMULATED_TOPUPS_ENABLED=true`; top-ups count
+//! towards the customer's inbound limits like external deposits.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::{house, limits};
+use crate::models::*;
+use crate::payments::{self, TransferError};
+
+const DEFAULT_TOPUP_MAX: i64 = 500;
+const MAX_REFERENCE_LEN: usize = 100;
+
+/// Test card endings the simulated provider declines, after the usual card
+/// processor sandboxes.
+const DECLINED_CARDS: [(&str, &str); 2] = [("0002", "card_declined"), ("9995", "insufficient_funds")];
+
+#[derive(Debug, thiserror::Error)]
+pub enum FundingError {
+    #[error("Only admins can do this")]
+    NotAdmin,
+    #[error("Card number is not valid")]
+    InvalidCard,
+    #[error("The card was declined ({0})")]
+    CardDeclined(&'static str),
+    #[error("Top-ups are limited to {0} at a time")]
+    AboveMaximum(Decimal),
+    #[error("A reference of up to {} characters is required", MAX_REFERENCE_LEN)]
+    ReferenceRequired,
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl FundingError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FundingError::NotAdmin => "forbidden",
+            FundingError::InvalidCard => "invalid_card",
+            FundingError::CardDeclined(_) => "card_declined",
+            FundingError::AboveMaximum(_) => "topup_limit_exceeded",
+            FundingError::ReferenceRequired => "reference_required",
+            FundingError::Transfer(e) => e.code(),
+            FundingError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let body = ErrorResponse {
+            error: self.code().to_string(),
+            message: self.to_string(),
+        };
+        match self {
+            FundingError::Database(_) => HttpResponse::InternalServerError().finish(),
+            FundingError::Transfer(e) => e.to_response(),
+            FundingError::NotAdmin => HttpResponse::Forbidden().json(body),
+            FundingError::CardDeclined(_) => HttpResponse::PaymentRequired().json(body),
+            FundingError::InvalidCard | FundingError::AboveMaximum(_) | FundingError::ReferenceRequired => {
+                HttpResponse::BadRequest().json(body)
+            }
+        }
+    }
+}
+
+fn decimal_from_env(name: &str) -> Option<Decimal> {
+    env::var(name).ok().and_then(|v| Decimal::from_str(v.trim()).ok())
+}
+
+/// Promotional credit for new accounts from `SIGNUP_CREDIT_AMOUNT`; none
+/// when unset or zero.
+pub fn signup_credit() -> Option<Decimal> {
+    decimal_from_env("SIGNUP_CREDIT_AMOUNT")
+        .map(|d| d.round_dp(2))
+        .filter(|d| *d > Decimal::ZERO)
+}
+
+/// Whether `POST /api/topups` is served. Off unless
+/// `SIMULATED_TOPUPS_ENABLED=true`.
+pub fn simulated_topups_enabled() -> bool {
+    env::var("SIMULATED_TOPUPS_ENABLED").is_ok_and(|v| v.trim() == "true")
+}
+
+fn topup_max() -> Decimal {
+    decimal_from_env("TOPUP_MAX_AMOUNT").unwrap_or_else(|| Decimal::from(DEFAULT_TOPUP_MAX))
+}
+
+/// Moves `amount` from a house account into a customer's primary account.
+/// Returns the ledger line and the new balance.
+async fn credit(
+    conn: &mut PgConnection,
+    house_code: &str,
+    account_number: &str,
+    amount: Decimal,
+    description: &str,
+) -> Result<(Uuid, Decimal), TransferError> {
+    let new_balance = sqlx::query_scalar::<_, Decimal>(
+        "UPDATE users SET balance = balance + $2 WHERE account_number = $1 RETURNING balance"
+    )
+    .bind(account_number)
+    .bind(amount)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(TransferError::AccountNotFound)?;
+    let house_account = house::adjust(&mut *conn, house_code, -amount).await?;
+    let (transaction_id, _) =
+        payments::record_transaction(&mut *conn, &house_account, account_number, amount, Some(description)).await?;
+    Ok((transaction_id, new_balance))
+}
+
+/// Pays the signup credit into a newly registered account, on the
+/// registration's transaction.
+pub async fn grant_signup_credit(conn: &mut PgConnection, account_number: &str, amount: Decimal) -> Result<Uuid, TransferError> {
+    let (transaction_id, _) = credit(conn, house::MARKETING, account_number, amount, "Welcome credit").await?;
+    Ok(transaction_id)
+}
+
+async fn is_admin(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1 AND role = 'admin')")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+async fn deposit(pool: &PgPool, admin_id: Uuid, body: &AdminDepositRequest) -> Result<FundingResponse, FundingError> {
+    if !is_admin(pool, admin_id).await? {
+        return Err(FundingError::NotAdmin);
+    }
+    let amount = payments::parse_amount(body.amount).ok_or(TransferError::InvalidAmount)?;
+    let reference = body.reference.trim();
+    if reference.is_empty() || reference.chars().count() > MAX_REFERENCE_LEN {
+        return Err(FundingError::ReferenceRequired);
+    }
+
+    let mut tx = pool.begin().await?;
+    let description = format!("Deposit {}", reference);
+    let (transaction_id, new_balance) =
+        credit(&mut tx, house::DEPOSITS, body.account_number.trim(), amount, &description).await?;
+    tx.commit().await?;
+
+    log::info!("deposit of {} to {} by {} ({})", amount, body.account_number.trim(), admin_id, reference);
+    Ok(FundingResponse {
+        transaction_id: transaction_id.to_string(),
+        source: "deposit".to_string(),
+        amount: payments::decimal_to_f64(amount),
+        new_balance: payments::decimal_to_f64(new_balance),
+        reference: reference.to_string(),
+    })
+}
+
+/// `POST /api/admin/deposits` credits a customer account, for example after
+/// cash was paid in at a branch. Admins only.
+pub async fn admin_deposit(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<AdminDepositRequest>,
+) -> HttpResponse {
+    let admin_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match deposit(pool.get_ref(), admin_id, &body).await {
+        Ok(response) => HttpResponse::Created().json(response),
+        Err(e) => e.to_response(),
+    }
+}
+
+fn luhn_valid(digits: &str) -> bool {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// The simulated card provider. Approves any valid card number except the
+/// test cards in [`DECLINED_CARDS`] and returns the provider's reference.
+fn charge_card(card_number: &str) -> Result<String, FundingError> {
+    if !(12..=19).contains(&card_number.len()) || !luhn_valid(card_number) {
+        return Err(FundingError::InvalidCard);
+    }
+    if let Some((_, reason)) = DECLINED_CARDS.iter().find(|(ending, _)| card_number.ends_with(ending)) {
+        return Err(FundingError::CardDeclined(reason));
+    }
+    Ok(format!("SIM-{}", &Uuid::new_v4().simple().to_string()[..12].to_uppercase()))
+}
+
+async fn top_up(pool: &PgPool, user_id: Uuid, body: &TopUpRequest) -> Result<FundingResponse, FundingError> {
+    let amount = payments::parse_amount(body.amount).ok_or(TransferError::InvalidAmount)?;
+    let max = topup_max();
+    if amount > max {
+        return Err(FundingError::AboveMaximum(max));
+    }
+    let card_number: String = body.card_number.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+    if !card_number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(FundingError::InvalidCard);
+    }
+
+    let mut tx = pool.begin().await?;
+    let account_number = sqlx::query_scalar::<_, String>("SELECT account_number FROM users WHERE id = $1 FOR UPDATE")
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(TransferError::AccountNotFound)?;
+    limits::check_inbound(&mut tx, user_id, &account_number, amount).await?;
+
+    let reference = charge_card(&card_number)?;
+    let description = format!("Card top-up ****{} {}", &card_number[card_number.len() - 4..], reference);
+    let (transaction_id, new_balance) = credit(&mut tx, house::TOPUPS, &account_number, amount, &description).await?;
+    tx.commit().await?;
+
+    Ok(FundingResponse {
+        transaction_id: transaction_id.to_string(),
+        source: "card_topup".to_string(),
+        amount: payments::decimal_to_f64(amount),
+        new_balance: payments::decimal_to_f64(new_balance),
+        reference,
+    })
+}
+
+/// `POST /api/topups` adds money to the caller's account from a card, using
+/// the simulated provider. Only routed when [`simulated_topups_enabled`].
+pub async fn create_topup(pool: web::Data<PgPool>, req: HttpRequest, body: web::Json<TopUpRequest>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match top_up(pool.get_ref(), user_id, &body).await {
+        Ok(response) => HttpResponse::Created().json(response),
+        Err(e) => e.to_response(),
+    }
+}
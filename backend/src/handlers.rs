@@ -2,11 +2,14 @@ use actix_web::{web, HttpResponse, HttpRequest};
 use serde_json::json;
 use crate::models::*;
 use uuid::Uuid;
-use chrono::Utc;
 use sqlx::PgPool;
 use jsonwebtoken::{decode, Validation, DecodingKey};
 use std::env;
 use crate::auth::Claims;
+use crate::payments::{self, TransferError};
+use crate::history::{self, Cursor, HistoryRow, TransactionFilter};
+use crate::receipt::{self, Receipt};
+use crate::{accounts, emvco, fees, payees, qr, recipients, statements};
 
 pub async fn health() -> HttpResponse {
     HttpResponse::Ok().json(json!({
@@ -15,7 +18,7 @@ pub async fn health() -> HttpResponse {
     }))
 }
 
-async fn get_user_id_from_req(req: &HttpRequest) -> Option<Uuid> {
+pub(crate) async fn get_user_id_from_req(req: &HttpRequest) -> Option<Uuid> {
     let auth_header = req.headers().get("Authorization")?.to_str().ok()?;
     let token = auth_header.trim_start_matches("Bearer ");
     let secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
@@ -39,12 +42,10 @@ pub async fn transfer(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    if body.amount <= 0.0 {
-        return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "invalid_amount".to_string(),
-            message: "Amount must be greater than 0".to_string(),
-        });
-    }
+    let amount = match payments::parse_amount(body.amount) {
+        Some(a) => a,
+        None => return TransferError::InvalidAmount.to_response(),
+    };
 
     // Start transaction
     let mut tx = match pool.begin().await {
@@ -52,81 +53,68 @@ pub async fn transfer(
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    // Get sender account and balance
-    let sender = match sqlx::query_as::<_, SenderBalanceRow>(
-        "SELECT account_number, balance FROM users WHERE id = $1 FOR UPDATE"
-    )
-    .bind(sender_id)
-    .fetch_one(&mut *tx)
-    .await {
-        Ok(s) => s,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-
-    let sender_balance: f64 = sender.balance.to_string().parse().unwrap_or(0.0);
-    if sender_balance < body.amount {
-        return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "insufficient_funds".to_string(),
-            message: "Insufficient funds for transfer".to_string(),
-        });
-    }
-
-    // Get recipient
-    let recipient = match sqlx::query_as::<_, RecipientRow>(
-        "SELECT id, balance FROM users WHERE account_number = $1 FOR UPDATE"
-    )
-    .bind(&body.recipient_account)
-    .fetch_optional(&mut *tx)
-    .await {
-        Ok(Some(r)) => r,
-        Ok(None) => return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "recipient_not_found".to_string(),
-            message: "Recipient account not found".to_string(),
+    let (recipient_account, payee) = match (body.recipient_account.as_deref(), body.payee_id) {
+        (Some(recipient), None) => match recipients::resolve(&mut *tx, recipient).await {
+            Ok(Some((_, resolved))) => (resolved.account_number, None),
+            Ok(None) => return recipients::not_found_error(recipient).to_response(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        (None, Some(payee_id)) => match payees::lock_for_transfer(&mut tx, sender_id, payee_id, amount).await {
+            Ok(payee) => (payee.account_number.clone(), Some(payee)),
+            Err(e) => return e.to_response(),
+        },
+        _ => return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_recipient".to_string(),
+            message: "Provide either recipient_account or payee_id".to_string(),
         }),
-        Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    // Update balances
-    let amount_decimal = rust_decimal::Decimal::from_f64_retain(body.amount).unwrap();
-    
-    if let Err(_) = sqlx::query("UPDATE users SET balance = balance - $1 WHERE id = $2")
-        .bind(amount_decimal)
-        .bind(sender_id)
-        .execute(&mut *tx).await {
-        return HttpResponse::InternalServerError().finish();
-    }
+    let description = body
+        .description
+        .as_deref()
+        .filter(|d| !d.trim().is_empty())
+        .or(payee.as_ref().and_then(|p| p.default_description.as_deref()));
+
+    let outcome = match payments::transfer(
+        &mut tx,
+        sender_id,
+        &recipient_account,
+        amount,
+        description,
+        fees::TRANSFER,
+    ).await {
+        Ok(o) => o,
+        Err(e) => return e.to_response(),
+    };
 
-    if let Err(_) = sqlx::query("UPDATE users SET balance = balance + $1 WHERE id = $2")
-        .bind(amount_decimal)
-        .bind(recipient.id)
-        .execute(&mut *tx).await {
-        return HttpResponse::InternalServerError().finish();
-    }
+    // Held payments are charged when they are released
+    let (fee, fee_transaction_id) = if outcome.held_for_review {
+        (rust_decimal::Decimal::ZERO, None)
+    } else {
+        match fees::apply(&mut tx, sender_id, &outcome, fees::TRANSFER).await {
+            Ok(f) => f,
+            Err(e) => return e.to_response(),
+        }
+    };
 
-    // Record transaction
-    let transaction_id = Uuid::new_v4();
-    if let Err(_) = sqlx::query(
-        "INSERT INTO transactions (id, from_account, to_account, amount, description, status) VALUES ($1, $2, $3, $4, $5, $6)"
-    )
-    .bind(transaction_id)
-    .bind(&sender.account_number)
-    .bind(&body.recipient_account)
-    .bind(amount_decimal)
-    .bind(&body.description)
-    .bind("completed")
-    .execute(&mut *tx).await {
-        return HttpResponse::InternalServerError().finish();
+    if let Some(payee) = &payee {
+        if payees::record_payment(&mut tx, payee.id).await.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
     }
 
-    if let Err(_) = tx.commit().await {
+    if tx.commit().await.is_err() {
         return HttpResponse::InternalServerError().finish();
     }
 
     HttpResponse::Ok().json(TransferResponse {
-        transaction_id: transaction_id.to_string(),
-        status: "completed".to_string(),
-        amount: body.amount,
-        timestamp: Utc::now().to_rfc3339(),
+        transaction_id: outcome.transaction_id.to_string(),
+        status: if outcome.held_for_review { "pending_review" } else { "completed" }.to_string(),
+        amount: payments::decimal_to_f64(outcome.amount),
+        fee: payments::decimal_to_f64(fee),
+        total_debited: payments::decimal_to_f64(outcome.amount + fee),
+        fee_transaction_id: fee_transaction_id.map(|id| id.to_string()),
+        timestamp: outcome.created_at.and_utc().to_rfc3339(),
     })
 }
 
@@ -134,17 +122,14 @@ pub async fn transfer(
 struct SenderBalanceRow {
     account_number: String,
     balance: rust_decimal::Decimal,
-}
-
-#[derive(sqlx::FromRow)]
-struct RecipientRow {
-    id: Uuid,
-    balance: rust_decimal::Decimal,
+    held_balance: rust_decimal::Decimal,
+    created_at: chrono::NaiveDateTime,
 }
 
 pub async fn get_balance(
     pool: web::Data<PgPool>,
-    req: HttpRequest
+    req: HttpRequest,
+    query: web::Query<BalanceQuery>,
 ) -> HttpResponse {
     let user_id = match get_user_id_from_req(&req).await {
         Some(id) => id,
@@ -152,7 +137,7 @@ pub async fn get_balance(
     };
 
     let user = match sqlx::query_as::<_, SenderBalanceRow>(
-        "SELECT account_number, balance FROM users WHERE id = $1"
+        "SELECT account_number, balance, held_balance, created_at FROM users WHERE id = $1"
     )
     .bind(user_id)
     .fetch_one(pool.get_ref()).await {
@@ -160,10 +145,35 @@ pub async fn get_balance(
         Err(_) => return HttpResponse::NotFound().finish(),
     };
 
+    let (balance, as_of) = match query.as_of {
+        None => (user.balance, None),
+        Some(as_of) if as_of > chrono::Utc::now() => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "invalid_as_of".to_string(),
+                message: "as_of cannot be in the future".to_string(),
+            });
+        }
+        // The account did not exist yet
+        Some(as_of) if as_of.naive_utc() < user.created_at => (rust_decimal::Decimal::ZERO, Some(as_of)),
+        Some(as_of) => match statements::balance_as_of(pool.get_ref(), user_id, as_of.naive_utc()).await {
+            Ok(balance) => (balance, Some(as_of)),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let (accounts, total_balance) = match accounts::balances(pool.get_ref(), user_id, balance, as_of.map(|t| t.naive_utc())).await {
+        Ok(a) => a,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
     HttpResponse::Ok().json(BalanceResponse {
         account_number: user.account_number,
-        balance: user.balance.to_string().parse().unwrap_or(0.0),
+        balance: payments::decimal_to_f64(balance),
         currency: "USD".to_string(),
+        as_of: as_of.map(|t| t.to_rfc3339()),
+        held_balance: as_of.is_none().then(|| payments::decimal_to_f64(user.held_balance)),
+        total_balance: payments::decimal_to_f64(total_balance),
+        accounts,
     })
 }
 
@@ -177,122 +187,80 @@ pub async fn qr_payment(
         None => return HttpResponse::Unauthorized().finish(),
     };
 
-    // Parse QR data (JSON expected)
-    let qr_payment_data: serde_json::Value = match serde_json::from_str(&body.qr_data) {
-        Ok(v) => v,
-        Err(_) => return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "invalid_qr".to_string(),
-            message: "Invalid QR code data".to_string(),
-        }),
+    // EMVCo merchant codes carry no signature, only a CRC; everything else
+    // must be a server-signed DeltaUp code covering account and amount
+    let (recipient_account, amount, reference, signed) = if emvco::is_emvco(&body.qr_data) {
+        let emv = match emvco::parse(&body.qr_data) {
+            Ok(p) => p,
+            Err(fields) => return HttpResponse::BadRequest().json(FieldErrorResponse {
+                error: "invalid_qr".to_string(),
+                message: "Malformed EMVCo QR code".to_string(),
+                fields,
+            }),
+        };
+        let payer_amount = match body.amount {
+            Some(a) => match payments::parse_amount(a) {
+                Some(a) => Some(a),
+                None => return TransferError::InvalidAmount.to_response(),
+            },
+            None => None,
+        };
+        let amount = match (emv.amount, payer_amount) {
+            (Some(fixed), Some(entered)) if fixed != entered => return qr::QrError::AmountMismatch.to_response(),
+            (Some(fixed), _) | (None, Some(fixed)) => fixed,
+            (None, None) => return qr::QrError::AmountRequired.to_response(),
+        };
+        // Fall back to the merchant name so the statement line is recognisable
+        (emv.account, amount, emv.reference.or(Some(emv.merchant_name)), None)
+    } else {
+        let payload = match qr::verify_payload(&body.qr_data) {
+            Ok(p) => p,
+            Err(e) => return e.to_response(),
+        };
+        let amount = match qr::resolve_amount(&payload, body.amount) {
+            Ok(a) => a,
+            Err(e) => return e.to_response(),
+        };
+        (payload.account.clone(), amount, payload.reference.clone(), Some(payload))
     };
 
-    let recipient_account = match qr_payment_data["account"].as_str() {
-        Some(s) => s.to_string(),
-        None => return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "invalid_qr".to_string(),
-            message: "Missing account in QR data".to_string(),
-        }),
-    };
-
-    let amount = match qr_payment_data["amount"].as_f64() {
-        Some(f) => f,
-        None => return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "invalid_qr".to_string(),
-            message: "Missing or invalid amount in QR data".to_string(),
-        }),
-    };
-
-    if amount <= 0.0 {
-        return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "invalid_amount".to_string(),
-            message: "Amount must be greater than 0".to_string(),
-        });
-    }
-
-    let description = qr_payment_data["description"].as_str().map(|s| s.to_string());
-
     // Start transaction
     let mut tx = match pool.begin().await {
         Ok(t) => t,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    // Get sender account and balance
-    let sender = match sqlx::query_as::<_, SenderBalanceRow>(
-        "SELECT account_number, balance FROM users WHERE id = $1 FOR UPDATE"
-    )
-    .bind(sender_id)
-    .fetch_one(&mut *tx)
-    .await {
-        Ok(s) => s,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
-
-    let sender_balance: f64 = sender.balance.to_string().parse().unwrap_or(0.0);
-    if sender_balance < amount {
-        return HttpResponse::BadRequest().json(ErrorResponse {
-            error: "insufficient_funds".to_string(),
-            message: "Insufficient funds for QR payment".to_string(),
-        });
+    if let Some(payload) = &signed {
+        if let Err(e) = qr::claim_code(&mut tx, payload).await {
+            return e.to_response();
+        }
     }
 
-    // Get recipient
-    let recipient = match sqlx::query_as::<_, RecipientRow>(
-        "SELECT id, balance FROM users WHERE account_number = $1 FOR UPDATE"
-    )
-    .bind(&recipient_account)
-    .fetch_optional(&mut *tx)
-    .await {
-        Ok(Some(r)) => r,
-        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse {
-            error: "recipient_not_found".to_string(),
-            message: "Recipient account not found".to_string(),
-        }),
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+    let outcome = match payments::transfer(
+        &mut tx,
+        sender_id,
+        &recipient_account,
+        amount,
+        reference.as_deref(),
+        fees::QR_PAYMENT,
+    ).await {
+        Ok(o) => o,
+        Err(e) => return e.to_response(),
     };
 
-    // Update balances
-    let new_sender_balance = sender_balance - amount;
-    let recipient_balance: f64 = recipient.balance.to_string().parse().unwrap_or(0.0);
-    let new_recipient_balance = recipient_balance + amount;
-
-    if sqlx::query("UPDATE users SET balance = $1 WHERE id = $2")
-        .bind(new_sender_balance)
-        .bind(sender_id)
-        .execute(&mut *tx)
-        .await
-        .is_err()
-    {
-        return HttpResponse::InternalServerError().finish();
-    }
-
-    if sqlx::query("UPDATE users SET balance = $1 WHERE id = $2")
-        .bind(new_recipient_balance)
-        .bind(recipient.id)
-        .execute(&mut *tx)
-        .await
-        .is_err()
-    {
-        return HttpResponse::InternalServerError().finish();
-    }
+    let (fee, fee_transaction_id) = if outcome.held_for_review {
+        (rust_decimal::Decimal::ZERO, None)
+    } else {
+        match fees::apply(&mut tx, sender_id, &outcome, fees::QR_PAYMENT).await {
+            Ok(f) => f,
+            Err(e) => return e.to_response(),
+        }
+    };
 
-    // Record transaction
-    let transaction_id = Uuid::new_v4();
-    if sqlx::query(
-        "INSERT INTO transactions (id, from_account, to_account, amount, description, status, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7)"
-    )
-    .bind(transaction_id)
-    .bind(&sender.account_number)
-    .bind(&recipient_account)
-    .bind(amount)
-    .bind(&description)
-    .bind("completed")
-    .bind(Utc::now().naive_utc())
-    .execute(&mut *tx)
-    .await
-    .is_err()
-    {
-        return HttpResponse::InternalServerError().finish();
+    if let Some(payload) = &signed {
+        if qr::mark_used(&mut tx, payload, outcome.transaction_id).await.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
     }
 
     // Commit transaction
@@ -301,26 +269,54 @@ pub async fn qr_payment(
     }
 
     HttpResponse::Ok().json(json!({
-        "status": "completed",
-        "message": "QR payment processed successfully",
-        "transaction_id": transaction_id.to_string(),
-        "from_account": sender.account_number,
-        "to_account": recipient_account,
-        "amount": amount,
-        "new_balance": new_sender_balance,
-        "timestamp": Utc::now().to_rfc3339()
+        "status": if outcome.held_for_review { "pending_review" } else { "completed" },
+        "message": if outcome.held_for_review {
+            "QR payment is being reviewed and will complete once approved"
+        } else {
+            "QR payment processed successfully"
+        },
+        "transaction_id": outcome.transaction_id.to_string(),
+        "from_account": outcome.from_account,
+        "to_account": outcome.to_account,
+        "amount": payments::decimal_to_f64(outcome.amount),
+        "fee": payments::decimal_to_f64(fee),
+        "total_debited": payments::decimal_to_f64(outcome.amount + fee),
+        "fee_transaction_id": fee_transaction_id.map(|id| id.to_string()),
+        "reference": reference,
+        "new_balance": payments::decimal_to_f64(outcome.sender_balance - fee),
+        "timestamp": outcome.created_at.and_utc().to_rfc3339()
     }))
 }
 
 pub async fn get_transactions(
     pool: web::Data<PgPool>,
-    req: HttpRequest
+    req: HttpRequest,
+    query: web::Query<TransactionFilter>,
 ) -> HttpResponse {
     let user_id = match get_user_id_from_req(&req).await {
         Some(id) => id,
         None => return HttpResponse::Unauthorized().finish(),
     };
 
+    let limit = query.limit.unwrap_or(history::DEFAULT_PAGE_SIZE);
+    if !(1..=history::MAX_PAGE_SIZE).contains(&limit) {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_limit".to_string(),
+            message: format!("limit must be between 1 and {}", history::MAX_PAGE_SIZE),
+        });
+    }
+
+    let cursor = match query.cursor.as_deref() {
+        Some(raw) => match Cursor::decode(raw) {
+            Some(c) => Some(c),
+            None => return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "invalid_cursor".to_string(),
+                message: "Pagination cursor is malformed".to_string(),
+            }),
+        },
+        None => None,
+    };
+
     let user_account = match sqlx::query_as::<_, AccountNumberRow>(
         "SELECT account_number FROM users WHERE id = $1"
     )
@@ -330,24 +326,15 @@ pub async fn get_transactions(
         Err(_) => return HttpResponse::NotFound().finish(),
     };
 
-    let transactions = match sqlx::query_as::<_, TransactionRow>(
-        "SELECT * FROM transactions WHERE from_account = $1 OR to_account = $1 ORDER BY created_at DESC LIMIT 50"
-    )
-    .bind(&user_account)
-    .fetch_all(pool.get_ref()).await {
-        Ok(rows) => rows.into_iter().map(|r| json!({
-            "id": r.id.to_string(),
-            "from_account": r.from_account,
-            "to_account": r.to_account,
-            "amount": r.amount.to_string().parse::<f64>().unwrap_or(0.0),
-            "description": r.description,
-            "status": r.status,
-            "created_at": r.created_at.and_utc().to_rfc3339()
-        })).collect::<Vec<_>>(),
+    let (rows, next) = match history::fetch_page(pool.get_ref(), &user_account, &query, cursor, limit).await {
+        Ok(page) => page,
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    HttpResponse::Ok().json(transactions)
+    HttpResponse::Ok().json(TransactionPage {
+        items: rows.into_iter().map(|r| r.into_view(&user_account)).collect(),
+        next_cursor: next.map(|c| c.encode()),
+    })
 }
 
 #[derive(sqlx::FromRow)]
@@ -356,7 +343,7 @@ struct AccountNumberRow {
 }
 
 #[derive(sqlx::FromRow)]
-struct TransactionRow {
+struct TransactionDetailRow {
     id: Uuid,
     from_account: String,
     to_account: String,
@@ -364,5 +351,166 @@ struct TransactionRow {
     description: Option<String>,
     status: String,
     created_at: chrono::NaiveDateTime,
+    from_username: Option<String>,
+    to_username: Option<String>,
+}
+
+/// Loads a transaction only if the caller is its sender or recipient.
+async fn fetch_own_transaction(
+    pool: &PgPool,
+    req: &HttpRequest,
+    transaction_id: Uuid,
+) -> Result<(String, TransactionDetailRow), HttpResponse> {
+    let user_id = get_user_id_from_req(req).await.ok_or_else(|| HttpResponse::Unauthorized().finish())?;
+
+    let account = sqlx::query_as::<_, AccountNumberRow>("SELECT account_number FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| HttpResponse::NotFound().finish())?
+        .account_number;
+
+    let row = sqlx::query_as::<_, TransactionDetailRow>(
+        "SELECT t.id, t.from_account, t.to_account, t.amount, t.description, t.status, t.created_at, \
+         fu.username AS from_username, tu.username AS to_username \
+         FROM transactions t \
+         LEFT JOIN users fu ON fu.account_number = t.from_account \
+         LEFT JOIN users tu ON tu.account_number = t.to_account \
+         WHERE t.id = $1 AND (t.from_account = $2 OR t.to_account = $2)"
+    )
+    .bind(transaction_id)
+    .bind(&account)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| HttpResponse::InternalServerError().finish())?
+    .ok_or_else(|| HttpResponse::NotFound().json(ErrorResponse {
+        error: "transaction_not_found".to_string(),
+        message: "Transaction not found".to_string(),
+    }))?;
+
+    Ok((account, row))
+}
+
+fn to_receipt(row: &TransactionDetailRow) -> Receipt {
+    Receipt {
+        transaction_id: row.id,
+        reference: receipt::reference_number(row.id, row.amount, row.created_at),
+        created_at: row.created_at,
+        from_account: row.from_account.clone(),
+        from_username: row.from_username.clone(),
+        to_account: row.to_account.clone(),
+        to_username: row.to_username.clone(),
+        amount: row.amount,
+        currency: "USD".to_string(),
+        description: row.description.clone(),
+        status: row.status.clone(),
+    }
+}
+
+pub async fn get_transaction(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> HttpResponse {
+    let (account, row) = match fetch_own_transaction(pool.get_ref(), &req, path.into_inner()).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let reference_number = receipt::reference_number(row.id, row.amount, row.created_at);
+    let receipt_url = format!("/api/transactions/{}/receipt", row.id);
+    let sent = row.from_account == account;
+    let view = HistoryRow {
+        id: row.id,
+        counterparty_username: if sent { row.to_username.clone() } else { row.from_username.clone() },
+        from_account: row.from_account,
+        to_account: row.to_account,
+        amount: row.amount,
+        description: row.description,
+        status: row.status,
+        created_at: row.created_at,
+    }
+    .into_view(&account);
+
+    HttpResponse::Ok().json(TransactionDetail {
+        transaction: view,
+        from_username: row.from_username,
+        to_username: row.to_username,
+        reference_number,
+        receipt_url,
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct ReceiptQuery {
+    pub format: Option<String>,
+}
+
+pub async fn get_receipt(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<ReceiptQuery>,
+) -> HttpResponse {
+    let (_, row) = match fetch_own_transaction(pool.get_ref(), &req, path.into_inner()).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    let receipt = to_receipt(&row);
+    match query.format.as_deref().unwrap_or("html") {
+        "html" => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(receipt::render_html(&receipt)),
+        "pdf" => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"receipt-{}.pdf\"", receipt.reference),
+            ))
+            .body(receipt::render_pdf(&receipt)),
+        _ => HttpResponse::BadRequest().json(ErrorResponse {
+            error: "unsupported_format".to_string(),
+            message: "Format must be html or pdf".to_string(),
+        }),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct VerifyReceiptQuery {
+    pub transaction_id: Uuid,
+    pub reference: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct ReceiptCheckRow {
+    amount: rust_decimal::Decimal,
+    status: String,
+    created_at: chrono::NaiveDateTime,
 }
 
+/// Public check that a receipt's reference number belongs to the given
+/// transaction. Details are only echoed back when the reference matches.
+pub async fn verify_receipt(
+    pool: web::Data<PgPool>,
+    query: web::Query<VerifyReceiptQuery>,
+) -> HttpResponse {
+    let row = match sqlx::query_as::<_, ReceiptCheckRow>(
+        "SELECT amount, status, created_at FROM transactions WHERE id = $1"
+    )
+    .bind(query.transaction_id)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let verified = row.filter(|r| receipt::verify_reference(&query.reference, query.transaction_id, r.amount, r.created_at));
+    HttpResponse::Ok().json(ReceiptVerification {
+        valid: verified.is_some(),
+        transaction_id: query.transaction_id.to_string(),
+        amount: verified.as_ref().map(|r| payments::decimal_to_f64(r.amount)),
+        status: verified.as_ref().map(|r| r.status.clone()),
+        created_at: verified.as_ref().map(|r| r.created_at.and_utc().to_rfc3339()),
+    })
+}
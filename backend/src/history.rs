@@ -0,0 +1,191 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+use crate::models::TransactionView;
+use crate::payments;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Query-string filters accepted by `GET /api/transactions`.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransactionFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub min_amount: Option<Decimal>,
+    pub max_amount: Option<Decimal>,
+    /// Account number or username of the other party.
+    pub counterparty: Option<String>,
+    pub status: Option<String>,
+    pub direction: Option<Direction>,
+    /// Free-text search over the description.
+    pub q: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Position after the last row of a page, ordered by `(created_at, id)` descending.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub created_at: NaiveDateTime,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let raw = format!("{}|{}", self.created_at.and_utc().timestamp_micros(), self.id);
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(value: &str) -> Option<Cursor> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(value).ok()?).ok()?;
+        let (micros, id) = raw.split_once('|')?;
+        Some(Cursor {
+            created_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?.naive_utc(),
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct HistoryRow {
+    pub id: Uuid,
+    pub from_account: String,
+    pub to_account: String,
+    pub amount: Decimal,
+    pub description: Option<String>,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub counterparty_username: Option<String>,
+}
+
+impl HistoryRow {
+    pub fn is_sent_by(&self, account: &str) -> bool {
+        self.from_account == account
+    }
+
+    pub fn into_view(self, account: &str) -> TransactionView {
+        let sent = self.is_sent_by(account);
+        let counterparty_account = if sent { self.to_account.clone() } else { self.from_account.clone() };
+        let signed = if sent { -self.amount } else { self.amount };
+        TransactionView {
+            id: self.id.to_string(),
+            direction: if sent { "sent" } else { "received" }.to_string(),
+            from_account: self.from_account,
+            to_account: self.to_account,
+            counterparty_account,
+            counterparty_username: self.counterparty_username,
+            amount: payments::decimal_to_f64(self.amount),
+            signed_amount: payments::decimal_to_f64(signed),
+            description: self.description,
+            status: self.status,
+            created_at: self.created_at.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+/// Escapes LIKE wildcards so user input is matched literally.
+fn like_pattern(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Starts a query over every transaction touching `account`, joined with the
+/// counterparty's username. Callers append further `AND` clauses.
+pub fn base_query<'a>(account: &'a str) -> QueryBuilder<'a, Postgres> {
+    let mut qb = QueryBuilder::new(
+        "SELECT t.id, t.from_account, t.to_account, t.amount, t.description, t.status, t.created_at, \
+         cp.username AS counterparty_username \
+         FROM transactions t \
+         LEFT JOIN users cp ON cp.account_number = CASE WHEN t.from_account = "
+    );
+    qb.push_bind(account)
+        .push(" THEN t.to_account ELSE t.from_account END WHERE (t.from_account = ")
+        .push_bind(account)
+        .push(" OR t.to_account = ")
+        .push_bind(account)
+        .push(")");
+    qb
+}
+
+/// Applies every filter except pagination to a query built by [`base_query`].
+pub fn push_filters<'a>(qb: &mut QueryBuilder<'a, Postgres>, account: &'a str, filter: &'a TransactionFilter) {
+    if let Some(from) = filter.from {
+        qb.push(" AND t.created_at >= ").push_bind(from.naive_utc());
+    }
+    if let Some(to) = filter.to {
+        qb.push(" AND t.created_at <= ").push_bind(to.naive_utc());
+    }
+    if let Some(min) = filter.min_amount {
+        qb.push(" AND t.amount >= ").push_bind(min);
+    }
+    if let Some(max) = filter.max_amount {
+        qb.push(" AND t.amount <= ").push_bind(max);
+    }
+    if let Some(counterparty) = filter.counterparty.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        qb.push(" AND (cp.account_number = ")
+            .push_bind(counterparty)
+            .push(" OR LOWER(cp.username) = LOWER(")
+            .push_bind(counterparty)
+            .push("))");
+    }
+    if let Some(status) = filter.status.as_deref() {
+        qb.push(" AND t.status = ").push_bind(status);
+    }
+    match filter.direction {
+        Some(Direction::Sent) => {
+            qb.push(" AND t.from_account = ").push_bind(account);
+        }
+        Some(Direction::Received) => {
+            qb.push(" AND t.to_account = ")
+                .push_bind(account)
+                .push(" AND t.from_account <> ")
+                .push_bind(account);
+        }
+        None => {}
+    }
+    if let Some(q) = filter.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        qb.push(" AND t.description ILIKE ").push_bind(like_pattern(q));
+    }
+}
+
+/// Fetches one page of history, newest first, plus the cursor for the next page.
+pub async fn fetch_page(
+    pool: &PgPool,
+    account: &str,
+    filter: &TransactionFilter,
+    cursor: Option<Cursor>,
+    limit: i64,
+) -> Result<(Vec<HistoryRow>, Option<Cursor>), sqlx::Error> {
+    let mut qb = base_query(account);
+    push_filters(&mut qb, account, filter);
+    if let Some(cursor) = cursor {
+        qb.push(" AND (t.created_at, t.id) < (")
+            .push_bind(cursor.created_at)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    // Fetch one extra row to learn whether another page exists
+    qb.push(" ORDER BY t.created_at DESC, t.id DESC LIMIT ").push_bind(limit + 1);
+
+    let mut rows = qb.build_query_as::<HistoryRow>().fetch_all(pool).await?;
+    let next = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|r| Cursor { created_at: r.created_at, id: r.id })
+    } else {
+        None
+    };
+    Ok((rows, next))
+}
@@ -0,0 +1,25 @@
+//! House accounts are the bank's own ledger accounts (interest expense, fee
+//! revenue and so on). They appear as the counterparty on system postings
+//! and may run negative. Their account numbers are not numeric, so they can
+//! never be resolved as a customer recipient.
+
+use rust_decimal::Decimal;
+use sqlx::PgConnection;
+
+pub const INTEREST_EXPENSE: &str = "interest_expense";
+pub const FEE_REVENUE: &str = "fee_revenue";
+pub const MARKETING: &str = "marketing";
+pub const DEPOSITS: &str = "deposits";
+pub const TOPUPS: &str = "card_topups";
+pub const EXTERNAL_CLEARING: &str = "external_clearing";
+
+/// Adds `delta` to a house account's balance and returns its account number.
+pub async fn adjust(conn: &mut PgConnection, code: &str, delta: Decimal) -> Result<String, sqlx::Error> {
+    sqlx::query_scalar::<_, String>(
+        "UPDATE house_accounts SET balance = balance + $2 WHERE code = $1 RETURNING account_number"
+    )
+    .bind(code)
+    .bind(delta)
+    .fetch_one(conn)
+    .await
+}
@@ -0,0 +1,428 @@
+//! Interest on savings accounts. A background job accrues interest on each
+//! day's closing balance into `interest_accruals`, then once a month posts
+//! the accrued total to the account as a transaction from the interest
+//! expense house account.
+//!
+//! Daily accruals are kept to ten decimal places. Postings are truncated to
+//! whole cents and the remainder is carried on `accounts.interest_carry` into
+//! the next posting, so no fraction of a cent is ever lost or over-paid.
+//! Closing an account accrues up to the close date and posts everything
+//! outstanding; only a final fraction of a cent is forfeited.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::env;
+use uuid::Uuid;
+use crate::{house, payments};
+use crate::payments::TransferError;
+
+pub const SAVINGS_PRODUCT: &str = "savings";
+const ACCRUAL_DP: u32 = 10;
+const DEFAULT_JOB_INTERVAL_SECS: u64 = 3600;
+
+/// Day-count convention used to turn an annual rate into a daily one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayCount {
+    /// Every year has 365 days.
+    Act365,
+    /// Every year has 360 days.
+    Act360,
+    /// The actual length of the year the day falls in.
+    ActAct,
+}
+
+impl DayCount {
+    pub fn parse(value: &str) -> Option<DayCount> {
+        match value.to_ascii_uppercase().as_str() {
+            "ACT/365" => Some(DayCount::Act365),
+            "ACT/360" => Some(DayCount::Act360),
+            "ACT/ACT" => Some(DayCount::ActAct),
+            _ => None,
+        }
+    }
+
+    fn days_in_year(&self, date: NaiveDate) -> Decimal {
+        match self {
+            DayCount::Act365 => Decimal::from(365),
+            DayCount::Act360 => Decimal::from(360),
+            DayCount::ActAct if date.leap_year() => Decimal::from(366),
+            DayCount::ActAct => Decimal::from(365),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InterestError {
+    #[error("unknown day count convention {0}")]
+    UnknownDayCount(String),
+    #[error("rate tiers for {0} mix day count conventions")]
+    MixedDayCounts(String),
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(sqlx::FromRow)]
+struct RateTierRow {
+    min_balance: Decimal,
+    annual_rate: Decimal,
+    day_count: String,
+}
+
+/// Marginal rate tiers for a product: each rate applies only to the part of
+/// the balance between its own threshold and the next one.
+#[derive(Debug, Clone)]
+pub struct RateTable {
+    /// `(min_balance, annual_rate)`, ascending by threshold.
+    tiers: Vec<(Decimal, Decimal)>,
+    day_count: DayCount,
+}
+
+impl RateTable {
+    pub async fn load<'e>(executor: impl PgExecutor<'e>, product: &str) -> Result<Option<RateTable>, InterestError> {
+        let rows = sqlx::query_as::<_, RateTierRow>(
+            "SELECT min_balance, annual_rate, day_count FROM interest_rate_tiers WHERE product = $1 ORDER BY min_balance"
+        )
+        .bind(product)
+        .fetch_all(executor)
+        .await?;
+        Self::from_rows(product, rows)
+    }
+
+    /// The tiers must share one day-count convention; the daily rate of a
+    /// balance spanning tiers would otherwise be ambiguous.
+    fn from_rows(product: &str, rows: Vec<RateTierRow>) -> Result<Option<RateTable>, InterestError> {
+        let Some(first) = rows.first() else {
+            return Ok(None);
+        };
+        let day_count = DayCount::parse(&first.day_count).ok_or_else(|| InterestError::UnknownDayCount(first.day_count.clone()))?;
+        if rows.iter().any(|r| DayCount::parse(&r.day_count) != Some(day_count)) {
+            return Err(InterestError::MixedDayCounts(product.to_string()));
+        }
+        Ok(Some(RateTable {
+            tiers: rows.into_iter().map(|r| (r.min_balance, r.annual_rate)).collect(),
+            day_count,
+        }))
+    }
+
+    /// Interest earned over `date` by a closing balance of `balance`.
+    pub fn daily_interest(&self, balance: Decimal, date: NaiveDate) -> Decimal {
+        let mut annual = Decimal::ZERO;
+        for (idx, (min, rate)) in self.tiers.iter().enumerate() {
+            if balance <= *min {
+                break;
+            }
+            let top = match self.tiers.get(idx + 1) {
+                Some((next_min, _)) => balance.min(*next_min),
+                None => balance,
+            };
+            annual += (top - min) * rate;
+        }
+        (annual / self.day_count.days_in_year(date))
+            .round_dp_with_strategy(ACCRUAL_DP, RoundingStrategy::MidpointNearestEven)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SavingsAccountRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    last_accrual: Option<NaiveDate>,
+}
+
+/// Balance of an additional account at the end of `day`, read in a single
+/// statement so it is consistent with concurrent transfers.
+async fn end_of_day_balance<'e>(executor: impl PgExecutor<'e>, account_id: Uuid, day: NaiveDate) -> Result<Decimal, sqlx::Error> {
+    let next_midnight = (day + Duration::days(1)).and_hms_opt(0, 0, 0).expect("midnight is valid");
+    sqlx::query_scalar::<_, Decimal>(
+        "SELECT a.balance - COALESCE(SUM(CASE WHEN t.from_account = t.to_account THEN 0 \
+                                              WHEN t.from_account = a.account_number THEN -t.amount ELSE t.amount END), 0) \
+         FROM accounts a \
+         LEFT JOIN transactions t ON (t.from_account = a.account_number OR t.to_account = a.account_number) \
+              AND t.status = 'completed' AND t.created_at >= $2 \
+         WHERE a.id = $1 \
+         GROUP BY a.balance"
+    )
+    .bind(account_id)
+    .bind(next_midnight)
+    .fetch_one(executor)
+    .await
+}
+
+/// Accrues `account_id` for each day from `from` through `through`.
+async fn accrue_days(
+    conn: &mut PgConnection,
+    rates: &RateTable,
+    account_id: Uuid,
+    from: NaiveDate,
+    through: NaiveDate,
+) -> Result<usize, sqlx::Error> {
+    let mut accrued = 0;
+    let mut day = from;
+    while day <= through {
+        let balance = end_of_day_balance(&mut *conn, account_id, day).await?;
+        sqlx::query(
+            "INSERT INTO interest_accruals (id, account_id, accrual_date, balance, amount) VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (account_id, accrual_date) DO NOTHING"
+        )
+        .bind(Uuid::new_v4())
+        .bind(account_id)
+        .bind(day)
+        .bind(balance)
+        .bind(rates.daily_interest(balance, day))
+        .execute(&mut *conn)
+        .await?;
+        accrued += 1;
+        day += Duration::days(1);
+    }
+    Ok(accrued)
+}
+
+/// Accrues every completed day not yet accrued for open savings accounts.
+/// Returns the number of account-days accrued.
+pub async fn accrue_due(pool: &PgPool, rates: &RateTable) -> Result<usize, sqlx::Error> {
+    let today = Utc::now().date_naive();
+    let accounts = sqlx::query_as::<_, SavingsAccountRow>(
+        "SELECT a.id, a.created_at, MAX(i.accrual_date) AS last_accrual \
+         FROM accounts a LEFT JOIN interest_accruals i ON i.account_id = a.id \
+         WHERE a.kind = $1 AND a.closed_at IS NULL \
+         GROUP BY a.id, a.created_at"
+    )
+    .bind(SAVINGS_PRODUCT)
+    .fetch_all(pool)
+    .await?;
+
+    let mut conn = pool.acquire().await?;
+    let mut accrued = 0;
+    for account in accounts {
+        let from = account.last_accrual.map(|d| d + Duration::days(1)).unwrap_or(account.created_at.date());
+        accrued += accrue_days(&mut conn, rates, account.id, from, today - Duration::days(1)).await?;
+    }
+    Ok(accrued)
+}
+
+#[derive(sqlx::FromRow)]
+struct PostingAccountRow {
+    account_number: String,
+    interest_carry: Decimal,
+}
+
+/// Splits accrued interest into whole cents to pay and the remainder to
+/// carry.
+fn split_payout(total: Decimal) -> (Decimal, Decimal) {
+    let payout = total.round_dp_with_strategy(2, RoundingStrategy::ToZero);
+    (payout, total - payout)
+}
+
+/// Pays unposted accruals dated before `before`, plus the carry, into one
+/// locked account. The sub-cent remainder is carried forward, or forfeited
+/// when `final_posting` closes the account out. Returns the amount paid.
+async fn post_accruals(
+    conn: &mut PgConnection,
+    account_id: Uuid,
+    before: NaiveDate,
+    description: &str,
+    final_posting: bool,
+) -> Result<Decimal, TransferError> {
+    let account = sqlx::query_as::<_, PostingAccountRow>(
+        "SELECT account_number, interest_carry FROM accounts WHERE id = $1 FOR UPDATE"
+    )
+    .bind(account_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let accrued = sqlx::query_scalar::<_, Decimal>(
+        "SELECT COALESCE(SUM(amount), 0) FROM interest_accruals WHERE account_id = $1 AND posted_at IS NULL AND accrual_date < $2"
+    )
+    .bind(account_id)
+    .bind(before)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let (payout, carry) = split_payout(accrued + account.interest_carry);
+    let carry = if final_posting { Decimal::ZERO } else { carry };
+
+    let transaction_id = if payout > Decimal::ZERO {
+        let house_account = house::adjust(&mut *conn, house::INTEREST_EXPENSE, -payout).await?;
+        sqlx::query("UPDATE accounts SET balance = balance + $2 WHERE id = $1")
+            .bind(account_id)
+            .bind(payout)
+            .execute(&mut *conn)
+            .await?;
+        let (id, _) =
+            payments::record_transaction(&mut *conn, &house_account, &account.account_number, payout, Some(description)).await?;
+        Some(id)
+    } else {
+        None
+    };
+
+    sqlx::query("UPDATE accounts SET interest_carry = $2 WHERE id = $1")
+        .bind(account_id)
+        .bind(carry)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        "UPDATE interest_accruals SET posted_at = NOW(), transaction_id = $3 \
+         WHERE account_id = $1 AND posted_at IS NULL AND accrual_date < $2"
+    )
+    .bind(account_id)
+    .bind(before)
+    .bind(transaction_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(payout)
+}
+
+/// Posts accruals from months before `month_start` to one account.
+async fn post_account(pool: &PgPool, account_id: Uuid, month_start: NaiveDate) -> Result<(), TransferError> {
+    let mut tx = pool.begin().await?;
+    let description = format!("Interest {}", (month_start - Duration::days(1)).format("%B %Y"));
+    post_accruals(&mut tx, account_id, month_start, &description, false).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Accrues a savings account up to and including `close_date` and pays out
+/// everything outstanding, ahead of the balance being swept on closure.
+/// Runs on the caller's transaction, which must hold the account's lock.
+/// Returns the interest paid.
+pub async fn close_out(conn: &mut PgConnection, account_id: Uuid, close_date: NaiveDate) -> Result<Decimal, InterestError> {
+    if let Some(rates) = RateTable::load(&mut *conn, SAVINGS_PRODUCT).await? {
+        let last_accrual = sqlx::query_scalar::<_, Option<NaiveDate>>(
+            "SELECT MAX(accrual_date) FROM interest_accruals WHERE account_id = $1"
+        )
+        .bind(account_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        let created = sqlx::query_scalar::<_, NaiveDateTime>("SELECT created_at FROM accounts WHERE id = $1")
+            .bind(account_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        let from = last_accrual.map(|d| d + Duration::days(1)).unwrap_or(created.date());
+        accrue_days(conn, &rates, account_id, from, close_date).await?;
+    }
+    let payout = post_accruals(conn, account_id, close_date + Duration::days(1), "Interest on closure", true).await?;
+    Ok(payout)
+}
+
+/// Posts all accruals from completed months. Returns the number of accounts
+/// posted to.
+pub async fn post_due(pool: &PgPool) -> Result<usize, TransferError> {
+    let today = Utc::now().date_naive();
+    let month_start = today.with_day(1).expect("day 1 exists in every month");
+
+    let due = sqlx::query_scalar::<_, Uuid>(
+        "SELECT DISTINCT i.account_id FROM interest_accruals i JOIN accounts a ON a.id = i.account_id \
+         WHERE i.posted_at IS NULL AND i.accrual_date < $1 AND a.closed_at IS NULL"
+    )
+    .bind(month_start)
+    .fetch_all(pool)
+    .await?;
+
+    for account_id in &due {
+        post_account(pool, *account_id, month_start).await?;
+    }
+    Ok(due.len())
+}
+
+/// Runs accrual and monthly posting every `INTEREST_JOB_INTERVAL_SECS`.
+/// Both steps are idempotent, so a missed run is caught up on the next one.
+pub fn spawn_job(pool: PgPool) {
+    let every = env::var("INTEREST_JOB_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_JOB_INTERVAL_SECS);
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(every));
+        loop {
+            interval.tick().await;
+            let rates = match RateTable::load(&pool, SAVINGS_PRODUCT).await {
+                Ok(Some(rates)) => rates,
+                Ok(None) => {
+                    log::warn!("no interest rate tiers configured for {}", SAVINGS_PRODUCT);
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("failed to load interest rates: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = accrue_due(&pool, &rates).await {
+                log::error!("interest accrual failed: {}", e);
+                continue;
+            }
+            match post_due(&pool).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("posted interest to {} account(s)", n),
+                Err(e) => log::error!("interest posting failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    fn table(day_count: DayCount) -> RateTable {
+        RateTable {
+            tiers: vec![(dec("0"), dec("0.02")), (dec("10000"), dec("0.03"))],
+            day_count,
+        }
+    }
+
+    fn row(min_balance: &str, day_count: &str) -> RateTierRow {
+        RateTierRow { min_balance: dec(min_balance), annual_rate: dec("0.02"), day_count: day_count.to_string() }
+    }
+
+    #[test]
+    fn tiers_apply_marginally() {
+        let day = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let rates = table(DayCount::Act365);
+        // 10000 at 2% and 5000 at 3%, a year's worth of 350 over 365 days
+        assert_eq!(rates.daily_interest(dec("15000"), day), (dec("350") / dec("365")).round_dp(ACCRUAL_DP));
+        assert_eq!(rates.daily_interest(dec("10000"), day), (dec("200") / dec("365")).round_dp(ACCRUAL_DP));
+        assert_eq!(rates.daily_interest(dec("0"), day), Decimal::ZERO);
+    }
+
+    #[test]
+    fn day_count_conventions() {
+        let leap = NaiveDate::from_ymd_opt(2028, 2, 29).unwrap();
+        let common = NaiveDate::from_ymd_opt(2027, 2, 28).unwrap();
+        let balance = dec("3600");
+        assert_eq!(table(DayCount::Act360).daily_interest(balance, leap), dec("0.2"));
+        assert_eq!(table(DayCount::Act360).daily_interest(balance, common), dec("0.2"));
+        assert_eq!(table(DayCount::ActAct).daily_interest(balance, leap), (dec("72") / dec("366")).round_dp(ACCRUAL_DP));
+        assert_eq!(table(DayCount::ActAct).daily_interest(balance, common), (dec("72") / dec("365")).round_dp(ACCRUAL_DP));
+    }
+
+    #[test]
+    fn sub_cent_remainder_is_carried() {
+        let daily = table(DayCount::Act365).daily_interest(dec("100"), NaiveDate::from_ymd_opt(2026, 1, 1).unwrap());
+        let (payout, carry) = split_payout(daily * Decimal::from(31));
+        assert_eq!(payout, dec("0.16"));
+        assert_eq!(payout + carry, daily * Decimal::from(31));
+        let (payout, carry) = split_payout(dec("0.0099999999"));
+        assert_eq!(payout, Decimal::ZERO);
+        assert_eq!(carry, dec("0.0099999999"));
+    }
+
+    #[test]
+    fn rejects_mixed_day_counts() {
+        assert!(matches!(
+            RateTable::from_rows("savings", vec![row("0", "ACT/365"), row("10000", "ACT/360")]),
+            Err(InterestError::MixedDayCounts(_))
+        ));
+        assert!(matches!(RateTable::from_rows("savings", vec![row("0", "30/360")]), Err(InterestError::UnknownDayCount(_))));
+        assert!(matches!(RateTable::from_rows("savings", Vec::new()), Ok(None)));
+        assert!(RateTable::from_rows("savings", vec![row("0", "act/act"), row("10000", "ACT/ACT")]).unwrap().is_some());
+    }
+}
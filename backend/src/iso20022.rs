@@ -0,0 +1,614 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{NaiveDateTime, Utc};
+use quick_xml::events::Event;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::fees;
+use crate::handlers::get_user_id_from_req;
+use crate::payments::{self, TransferError};
+use crate::statements::{escape_xml, Statement};
+
+const CAMT053_NS: &str = "urn:iso:std:iso:20022:tech:xsd:camt.053.001.02";
+const PAIN002_NS: &str = "urn:iso:std:iso:20022:tech:xsd:pain.002.001.03";
+const PAIN001_MSG_NAME: &str = "pain.001.001.03";
+/// Namespace prefix shared by every pain.001 version.
+const PAIN001_NS_PREFIX: &str = "urn:iso:std:iso:20022:tech:xsd:pain.001.001.";
+/// Max35Text, the length limit of most ISO 20022 identifiers.
+const MAX_ID_LEN: usize = 35;
+
+fn iso_datetime(value: NaiveDateTime) -> String {
+    value.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+fn credit_debit(amount: Decimal) -> &'static str {
+    if amount < Decimal::ZERO { "DBIT" } else { "CRDT" }
+}
+
+fn balance_xml(code: &str, amount: Decimal, currency: &str, date: NaiveDateTime) -> String {
+    format!(
+        "<Bal><Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp><Amt Ccy=\"{}\">{:.2}</Amt><CdtDbtInd>{}</CdtDbtInd><Dt><Dt>{}</Dt></Dt></Bal>\n",
+        code,
+        currency,
+        amount.abs(),
+        credit_debit(amount),
+        date.format("%Y-%m-%d"),
+    )
+}
+
+/// Renders a statement as a camt.053.001.02 bank-to-customer statement.
+pub fn render_camt053(statement: &Statement) -> String {
+    let created = Utc::now().naive_utc();
+    let currency = escape_xml(&statement.currency);
+    let account = escape_xml(&statement.account_number);
+    let statement_id = format!("{}-{}", statement.account_number, statement.to.format("%Y%m%d"));
+
+    let credits: Vec<Decimal> = statement.lines.iter().map(|l| l.amount).filter(|a| *a > Decimal::ZERO).collect();
+    let debits: Vec<Decimal> = statement.lines.iter().map(|l| l.amount).filter(|a| *a < Decimal::ZERO).collect();
+    let credit_sum: Decimal = credits.iter().sum();
+    let debit_sum: Decimal = debits.iter().map(|a| a.abs()).sum();
+    let net = credit_sum - debit_sum;
+
+    let entries: String = statement
+        .lines
+        .iter()
+        .map(|line| {
+            let (party, party_account) = if line.amount < Decimal::ZERO { ("Cdtr", "CdtrAcct") } else { ("Dbtr", "DbtrAcct") };
+            let name = line
+                .counterparty_name
+                .as_deref()
+                .map(|n| format!("<{0}><Nm>{1}</Nm></{0}>", party, escape_xml(n)))
+                .unwrap_or_default();
+            let remittance = line
+                .description
+                .as_deref()
+                .map(|d| format!("<RmtInf><Ustrd>{}</Ustrd></RmtInf>", escape_xml(&d.chars().take(140).collect::<String>())))
+                .unwrap_or_default();
+            format!(
+                "<Ntry><NtryRef>{id}</NtryRef><Amt Ccy=\"{ccy}\">{amt:.2}</Amt><CdtDbtInd>{ind}</CdtDbtInd><Sts>BOOK</Sts>\
+                 <BookgDt><DtTm>{booked}</DtTm></BookgDt><ValDt><Dt>{value}</Dt></ValDt><AcctSvcrRef>{id}</AcctSvcrRef>\
+                 <BkTxCd><Prtry><Cd>TRANSFER</Cd><Issr>DELTAUP</Issr></Prtry></BkTxCd>\
+                 <NtryDtls><TxDtls><Refs><AcctSvcrRef>{id}</AcctSvcrRef></Refs>\
+                 <RltdPties>{name}<{acct}><Id><Othr><Id>{cp}</Id></Othr></Id></{acct}></RltdPties>{rmt}</TxDtls></NtryDtls></Ntry>\n",
+                id = line.transaction_id,
+                ccy = currency,
+                amt = line.amount.abs(),
+                ind = credit_debit(line.amount),
+                booked = iso_datetime(line.posted_at),
+                value = line.posted_at.format("%Y-%m-%d"),
+                name = name,
+                acct = party_account,
+                cp = escape_xml(&line.counterparty_account),
+                rmt = remittance,
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="{ns}">
+<BkToCstmrStmt>
+<GrpHdr><MsgId>{msg_id}</MsgId><CreDtTm>{created}</CreDtTm></GrpHdr>
+<Stmt>
+<Id>{stmt_id}</Id><CreDtTm>{created}</CreDtTm>
+<FrToDt><FrDtTm>{from}</FrDtTm><ToDtTm>{to}</ToDtTm></FrToDt>
+<Acct><Id><Othr><Id>{account}</Id></Othr></Id><Ccy>{ccy}</Ccy><Svcr><FinInstnId><Nm>DeltaUp</Nm></FinInstnId></Svcr></Acct>
+{opening}{closing}<TxsSummry><TtlNtries><NbOfNtries>{count}</NbOfNtries><Sum>{total:.2}</Sum><TtlNetNtryAmt>{net:.2}</TtlNetNtryAmt><CdtDbtInd>{net_ind}</CdtDbtInd></TtlNtries><TtlCdtNtries><NbOfNtries>{credit_count}</NbOfNtries><Sum>{credit_sum:.2}</Sum></TtlCdtNtries><TtlDbtNtries><NbOfNtries>{debit_count}</NbOfNtries><Sum>{debit_sum:.2}</Sum></TtlDbtNtries></TxsSummry>
+{entries}</Stmt>
+</BkToCstmrStmt>
+</Document>
+"#,
+        ns = CAMT053_NS,
+        msg_id = Uuid::new_v4().simple(),
+        created = iso_datetime(created),
+        stmt_id = escape_xml(&statement_id),
+        from = iso_datetime(statement.from),
+        to = iso_datetime(statement.to),
+        account = account,
+        ccy = currency,
+        opening = balance_xml("OPBD", statement.opening_balance, &currency, statement.from),
+        closing = balance_xml("CLBD", statement.closing_balance, &currency, statement.to),
+        count = statement.lines.len(),
+        total = credit_sum + debit_sum,
+        net = net.abs(),
+        net_ind = credit_debit(net),
+        credit_count = credits.len(),
+        credit_sum = credit_sum,
+        debit_count = debits.len(),
+        debit_sum = debit_sum,
+        entries = entries,
+    )
+}
+
+// pain.001.001.03 subset. Unknown elements are ignored; the fields below are
+// the ones DeltaUp needs and are mandatory in the schema unless optional here.
+
+#[derive(Debug, Deserialize)]
+struct Pain001Document {
+    #[serde(rename = "CstmrCdtTrfInitn")]
+    initiation: CustomerCreditTransferInitiation,
+}
+
+#[derive(Debug, Deserialize)]
+struct CustomerCreditTransferInitiation {
+    #[serde(rename = "GrpHdr")]
+    group_header: GroupHeader,
+    #[serde(rename = "PmtInf", default)]
+    payment_infos: Vec<PaymentInformation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupHeader {
+    #[serde(rename = "MsgId")]
+    message_id: String,
+    #[serde(rename = "CreDtTm")]
+    created_at: String,
+    #[serde(rename = "NbOfTxs")]
+    number_of_transactions: String,
+    #[serde(rename = "CtrlSum")]
+    control_sum: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentInformation {
+    #[serde(rename = "PmtInfId")]
+    id: String,
+    #[serde(rename = "PmtMtd")]
+    method: String,
+    #[serde(rename = "NbOfTxs")]
+    number_of_transactions: Option<String>,
+    #[serde(rename = "CtrlSum")]
+    control_sum: Option<String>,
+    #[serde(rename = "DbtrAcct")]
+    debtor_account: CashAccount,
+    #[serde(rename = "CdtTrfTxInf", default)]
+    transfers: Vec<CreditTransferTransaction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CashAccount {
+    #[serde(rename = "Id")]
+    id: AccountIdentification,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountIdentification {
+    #[serde(rename = "IBAN")]
+    iban: Option<String>,
+    #[serde(rename = "Othr")]
+    other: Option<GenericIdentification>,
+}
+
+impl AccountIdentification {
+    fn value(&self) -> Option<&str> {
+        self.other.as_ref().map(|o| o.id.as_str()).or(self.iban.as_deref()).map(str::trim)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GenericIdentification {
+    #[serde(rename = "Id")]
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreditTransferTransaction {
+    #[serde(rename = "PmtId")]
+    payment_id: PaymentIdentification,
+    #[serde(rename = "Amt")]
+    amount: AmountType,
+    #[serde(rename = "CdtrAcct")]
+    creditor_account: Option<CashAccount>,
+    #[serde(rename = "RmtInf")]
+    remittance: Option<RemittanceInformation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentIdentification {
+    #[serde(rename = "EndToEndId")]
+    end_to_end_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AmountType {
+    #[serde(rename = "InstdAmt")]
+    instructed: InstructedAmount,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstructedAmount {
+    #[serde(rename = "@Ccy")]
+    currency: String,
+    #[serde(rename = "$text")]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RemittanceInformation {
+    #[serde(rename = "Ustrd")]
+    unstructured: Option<String>,
+}
+
+/// Outcome of one credit transfer instruction, reported back in pain.002.
+struct InstructionStatus {
+    payment_info_id: String,
+    end_to_end_id: String,
+    transaction_id: Option<Uuid>,
+    /// Accepted but held by screening until a reviewer releases it.
+    held: bool,
+    /// ISO 20022 ExternalStatusReason1Code and free text, when rejected.
+    rejection: Option<(&'static str, String)>,
+}
+
+struct StatusReport {
+    original_message_id: String,
+    group_status: &'static str,
+    group_reason: Option<(&'static str, String)>,
+    instructions: Vec<InstructionStatus>,
+}
+
+fn reason_xml(reason: &Option<(&'static str, String)>) -> String {
+    match reason {
+        Some((code, info)) => format!(
+            "<StsRsnInf><Rsn><Cd>{}</Cd></Rsn><AddtlInf>{}</AddtlInf></StsRsnInf>",
+            code,
+            escape_xml(&info.chars().take(105).collect::<String>())
+        ),
+        None => String::new(),
+    }
+}
+
+/// Renders a pain.002.001.03 customer payment status report.
+fn render_pain002(report: &StatusReport) -> String {
+    let mut payment_infos: Vec<&str> = Vec::new();
+    for instruction in &report.instructions {
+        if !payment_infos.contains(&instruction.payment_info_id.as_str()) {
+            payment_infos.push(&instruction.payment_info_id);
+        }
+    }
+
+    let blocks: String = payment_infos
+        .iter()
+        .map(|pmt_inf_id| {
+            let txs: String = report
+                .instructions
+                .iter()
+                .filter(|i| i.payment_info_id == *pmt_inf_id)
+                .map(|i| {
+                    format!(
+                        "<TxInfAndSts><StsId>{}</StsId><OrgnlEndToEndId>{}</OrgnlEndToEndId><TxSts>{}</TxSts>{}</TxInfAndSts>\n",
+                        i.transaction_id.map(|id| id.simple().to_string()).unwrap_or_else(|| "NOTPROVIDED".to_string()),
+                        escape_xml(&i.end_to_end_id),
+                        match (&i.rejection, i.held) {
+                            (Some(_), _) => "RJCT",
+                            (None, true) => "PDNG",
+                            (None, false) => "ACSC",
+                        },
+                        reason_xml(&i.rejection),
+                    )
+                })
+                .collect();
+            format!("<OrgnlPmtInfAndSts><OrgnlPmtInfId>{}</OrgnlPmtInfId>\n{}</OrgnlPmtInfAndSts>\n", escape_xml(pmt_inf_id), txs)
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="{ns}">
+<CstmrPmtStsRpt>
+<GrpHdr><MsgId>{msg_id}</MsgId><CreDtTm>{created}</CreDtTm><InitgPty><Nm>DeltaUp</Nm></InitgPty></GrpHdr>
+<OrgnlGrpInfAndSts><OrgnlMsgId>{orig}</OrgnlMsgId><OrgnlMsgNmId>{name}</OrgnlMsgNmId><OrgnlNbOfTxs>{count}</OrgnlNbOfTxs><GrpSts>{status}</GrpSts>{reason}</OrgnlGrpInfAndSts>
+{blocks}</CstmrPmtStsRpt>
+</Document>
+"#,
+        ns = PAIN002_NS,
+        msg_id = Uuid::new_v4().simple(),
+        created = iso_datetime(Utc::now().naive_utc()),
+        orig = escape_xml(&report.original_message_id),
+        name = PAIN001_MSG_NAME,
+        count = report.instructions.len(),
+        status = report.group_status,
+        reason = reason_xml(&report.group_reason),
+        blocks = blocks,
+    )
+}
+
+fn rejected_file(message_id: &str, code: &'static str, info: String) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type("application/xml")
+        .body(render_pain002(&StatusReport {
+            original_message_id: message_id.to_string(),
+            group_status: "RJCT",
+            group_reason: Some((code, info)),
+            instructions: Vec::new(),
+        }))
+}
+
+fn transfer_reason(error: &TransferError) -> &'static str {
+    match error {
+        TransferError::InvalidAmount => "AM12",
+        TransferError::InsufficientFunds => "AM04",
+        TransferError::RecipientNotFound | TransferError::InvalidAccountNumber | TransferError::AccountNotFound => "AC01",
+        TransferError::PeriodClosed => "DT01",
+        TransferError::LimitExceeded(_) => "AM02",
+        TransferError::Blocked => "AG01",
+        TransferError::NotHeld | TransferError::Database(_) => "NARR",
+    }
+}
+
+/// Rejects an instruction that failed for reasons of our own. The detail is
+/// logged rather than reported, so database errors never reach the client.
+fn internal_rejection(import_id: Uuid, end_to_end_id: &str, error: &dyn std::fmt::Display) -> (&'static str, String) {
+    log::error!("pain.001 import {} instruction {} failed: {}", import_id, end_to_end_id, error);
+    ("NARR", "Internal error".to_string())
+}
+
+/// Checks that the root element is a pain.001 `Document`, before the body is
+/// read field by field.
+fn validate_root(xml: &str) -> Result<(), String> {
+    let mut reader = NsReader::from_str(xml);
+    loop {
+        match reader.read_resolved_event() {
+            Ok((namespace, Event::Start(element) | Event::Empty(element))) => {
+                if element.local_name().as_ref() != b"Document" {
+                    return Err("Root element must be Document".to_string());
+                }
+                return match namespace {
+                    ResolveResult::Bound(Namespace(ns)) if ns.starts_with(PAIN001_NS_PREFIX.as_bytes()) => Ok(()),
+                    _ => Err(format!("Document must be in the {}* namespace", PAIN001_NS_PREFIX)),
+                };
+            }
+            Ok((_, Event::Eof)) => return Err("File has no root element".to_string()),
+            Ok(_) => {}
+            Err(e) => return Err(format!("File is not well-formed XML: {}", e)),
+        }
+    }
+}
+
+/// Checks `CtrlSum` against the instructed amounts.
+fn check_control_sum(control_sum: &str, transfers: &[&CreditTransferTransaction], element: &str) -> Result<(), String> {
+    let expected = Decimal::from_str(control_sum.trim()).map_err(|_| format!("{} CtrlSum is not a decimal", element))?;
+    let actual: Decimal = transfers
+        .iter()
+        .filter_map(|t| Decimal::from_str(t.amount.instructed.value.trim()).ok())
+        .sum();
+    if expected != actual {
+        return Err(format!("{} CtrlSum {} does not match the instructed total {}", element, expected, actual));
+    }
+    Ok(())
+}
+
+/// Checks the group header against the instructions it announces.
+fn validate_group_header(doc: &CustomerCreditTransferInitiation) -> Result<(), String> {
+    let header = &doc.group_header;
+    if header.message_id.trim().is_empty() || header.message_id.len() > MAX_ID_LEN {
+        return Err(format!("MsgId must be 1 to {} characters", MAX_ID_LEN));
+    }
+    if NaiveDateTime::parse_from_str(header.created_at.trim(), "%Y-%m-%dT%H:%M:%S%.f").is_err()
+        && chrono::DateTime::parse_from_rfc3339(header.created_at.trim()).is_err()
+    {
+        return Err("CreDtTm is not a valid ISODateTime".to_string());
+    }
+    if doc.payment_infos.is_empty() {
+        return Err("At least one PmtInf block is required".to_string());
+    }
+
+    let instructions: Vec<&CreditTransferTransaction> = doc.payment_infos.iter().flat_map(|p| p.transfers.iter()).collect();
+    if header.number_of_transactions.trim().parse::<usize>().ok() != Some(instructions.len()) {
+        return Err(format!("NbOfTxs does not match the {} instructions in the file", instructions.len()));
+    }
+    if let Some(control_sum) = &header.control_sum {
+        check_control_sum(control_sum, &instructions, "GrpHdr")?;
+    }
+    for info in &doc.payment_infos {
+        if info.id.trim().is_empty() || info.id.len() > MAX_ID_LEN {
+            return Err(format!("PmtInfId must be 1 to {} characters", MAX_ID_LEN));
+        }
+        if info.transfers.is_empty() {
+            return Err(format!("PmtInf {} has no CdtTrfTxInf", info.id));
+        }
+        if let Some(count) = &info.number_of_transactions {
+            if count.trim().parse::<usize>().ok() != Some(info.transfers.len()) {
+                return Err(format!("PmtInf {} NbOfTxs does not match its {} instructions", info.id, info.transfers.len()));
+            }
+        }
+        if let Some(control_sum) = &info.control_sum {
+            let transfers: Vec<&CreditTransferTransaction> = info.transfers.iter().collect();
+            check_control_sum(control_sum, &transfers, &format!("PmtInf {}", info.id))?;
+        }
+    }
+    Ok(())
+}
+
+/// Imports a pain.001 customer credit transfer initiation. Each instruction
+/// becomes its own DeltaUp transfer and the response is a pain.002 status
+/// report with per-instruction acceptance or rejection reasons.
+pub async fn import_pain001(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let xml = match std::str::from_utf8(&body) {
+        Ok(x) => x,
+        Err(_) => return rejected_file("UNKNOWN", "FF01", "File is not valid UTF-8".to_string()),
+    };
+
+    if let Err(message) = validate_root(xml) {
+        return rejected_file("UNKNOWN", "FF01", message);
+    }
+    let document: Pain001Document = match quick_xml::de::from_str(xml) {
+        Ok(d) => d,
+        Err(e) => return rejected_file("UNKNOWN", "FF01", format!("File is not a pain.001 CstmrCdtTrfInitn: {}", e)),
+    };
+    let initiation = document.initiation;
+    let message_id = initiation.group_header.message_id.trim().to_string();
+
+    if let Err(message) = validate_group_header(&initiation) {
+        return rejected_file(&message_id, "FF01", message);
+    }
+
+    let account_number = match sqlx::query_scalar::<_, String>("SELECT account_number FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool.get_ref())
+        .await {
+        Ok(a) => a,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    // Claim the message id first so a re-submitted file is never executed twice
+    let import_id = Uuid::new_v4();
+    match sqlx::query(
+        "INSERT INTO payment_initiations (id, user_id, message_id, status) VALUES ($1, $2, $3, 'processing') ON CONFLICT (user_id, message_id) DO NOTHING"
+    )
+    .bind(import_id)
+    .bind(user_id)
+    .bind(&message_id)
+    .execute(pool.get_ref())
+    .await {
+        Ok(r) if r.rows_affected() == 0 => {
+            return rejected_file(&message_id, "DU01", "A file with this MsgId was already imported".to_string());
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let mut seen_end_to_end = HashSet::new();
+    let mut instructions = Vec::new();
+
+    for info in &initiation.payment_infos {
+        let debtor_matches = info.debtor_account.id.value() == Some(account_number.as_str());
+        for tx in &info.transfers {
+            let end_to_end_id = tx.payment_id.end_to_end_id.trim().to_string();
+            let mut status = InstructionStatus {
+                payment_info_id: info.id.clone(),
+                end_to_end_id: end_to_end_id.clone(),
+                transaction_id: None,
+                held: false,
+                rejection: None,
+            };
+
+            let amount = Decimal::from_str(tx.amount.instructed.value.trim()).ok();
+            let creditor = tx.creditor_account.as_ref().and_then(|a| a.id.value()).filter(|a| !a.is_empty());
+
+            status.rejection = if info.method.trim() != "TRF" {
+                Some(("FF01", "PmtMtd must be TRF".to_string()))
+            } else if !debtor_matches {
+                Some(("AC01", "DbtrAcct is not the authenticated account".to_string()))
+            } else if end_to_end_id.is_empty() || !seen_end_to_end.insert(end_to_end_id.clone()) {
+                Some(("AM05", "EndToEndId is missing or duplicated".to_string()))
+            } else if tx.amount.instructed.currency.trim() != "USD" {
+                Some(("AM03", format!("Currency {} is not supported", tx.amount.instructed.currency)))
+            } else if !amount.is_some_and(|a| a > Decimal::ZERO && a.scale() <= 2) {
+                Some(("AM12", "InstdAmt must be positive with at most 2 decimals".to_string()))
+            } else if creditor.is_none() {
+                Some(("AC01", "CdtrAcct is missing".to_string()))
+            } else {
+                None
+            };
+
+            if status.rejection.is_none() {
+                let description = tx
+                    .remittance
+                    .as_ref()
+                    .and_then(|r| r.unstructured.clone())
+                    .unwrap_or_else(|| end_to_end_id.clone());
+                status.rejection = match pool.begin().await {
+                    Ok(mut db_tx) => match payments::transfer(
+                        &mut db_tx,
+                        user_id,
+                        creditor.unwrap_or_default(),
+                        amount.unwrap_or_default(),
+                        Some(&description),
+                        fees::PAIN001,
+                    ).await {
+                        Ok(outcome) => match db_tx.commit().await {
+                            Ok(_) => {
+                                status.transaction_id = Some(outcome.transaction_id);
+                                status.held = outcome.held_for_review;
+                                None
+                            }
+                            Err(e) => Some(internal_rejection(import_id, &end_to_end_id, &e)),
+                        },
+                        Err(e @ TransferError::Database(_)) => Some(internal_rejection(import_id, &end_to_end_id, &e)),
+                        Err(e) => Some((transfer_reason(&e), e.to_string())),
+                    },
+                    Err(e) => Some(internal_rejection(import_id, &end_to_end_id, &e)),
+                };
+            }
+
+            instructions.push(status);
+        }
+    }
+
+    let accepted = instructions.iter().filter(|i| i.rejection.is_none()).count();
+    let rejected = instructions.len() - accepted;
+    let group_status = match accepted {
+        0 => "RJCT",
+        n if n == instructions.len() && instructions.iter().any(|i| i.held) => "ACSP",
+        n if n == instructions.len() => "ACSC",
+        _ => "PART",
+    };
+    let report = render_pain002(&StatusReport {
+        original_message_id: message_id,
+        group_status,
+        group_reason: None,
+        instructions,
+    });
+
+    if sqlx::query(
+        "UPDATE payment_initiations SET status = $1, accepted = $2, rejected = $3, report = $4, completed_at = NOW() WHERE id = $5"
+    )
+    .bind(group_status)
+    .bind(accepted as i32)
+    .bind(rejected as i32)
+    .bind(&report)
+    .bind(import_id)
+    .execute(pool.get_ref())
+    .await
+    .is_err()
+    {
+        log::error!("failed to store pain.002 report for import {}", import_id);
+    }
+
+    HttpResponse::Ok().content_type("application/xml").body(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_pain001_documents() {
+        let xml = r#"<?xml version="1.0"?><Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.001.001.03"><CstmrCdtTrfInitn/></Document>"#;
+        assert!(validate_root(xml).is_ok());
+        let prefixed = r#"<p:Document xmlns:p="urn:iso:std:iso:20022:tech:xsd:pain.001.001.09"><p:CstmrCdtTrfInitn/></p:Document>"#;
+        assert!(validate_root(prefixed).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_roots_and_namespaces() {
+        assert!(validate_root(r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02"/>"#).is_err());
+        assert!(validate_root(r#"<Document><CstmrCdtTrfInitn/></Document>"#).is_err());
+        assert!(validate_root(r#"<Envelope xmlns="urn:iso:std:iso:20022:tech:xsd:pain.001.001.03"/>"#).is_err());
+        assert!(validate_root("").is_err());
+    }
+
+    #[test]
+    fn internal_failures_are_not_reported_in_detail() {
+        let error = TransferError::Database(sqlx::Error::Protocol("relation \"users\" is locked".to_string()));
+        let (code, info) = internal_rejection(Uuid::nil(), "E2E-1", &error);
+        assert_eq!(code, "NARR");
+        assert_eq!(info, "Internal error");
+        assert_eq!(transfer_reason(&TransferError::InsufficientFunds), "AM04");
+    }
+}
@@ -0,0 +1,544 @@
+//! Know-your-customer tiers. Every user starts `unverified`; submitting
+//! identity details (and, for `full`, documents) asks a reviewer to raise the
+//! tier. The tier selects the user's transaction limits and fee schedule.
+//!
+//! Documents are uploaded one at a time as the raw request body and stored
+//! under `KYC_STORAGE_DIR`, then referenced by id from a submission.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc};
+use sha2::{Digest, Sha256};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::env;
+use std::path::PathBuf;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::{notifications, reviews, sanctions};
+
+/// Largest accepted document upload.
+pub const MAX_DOCUMENT_BYTES: usize = 5 * 1024 * 1024;
+const DEFAULT_STORAGE_DIR: &str = "data/kyc";
+const MIN_AGE_YEARS: i32 = 18;
+const MAX_NAME_LEN: usize = 255;
+const MAX_ADDRESS_LEN: usize = 500;
+const MAX_REASON_LEN: usize = 500;
+
+const IDENTITY_DOCUMENTS: [&str; 3] = ["passport", "id_card", "driving_licence"];
+const DOCUMENT_KINDS: [&str; 4] = ["passport", "id_card", "driving_licence", "proof_of_address"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KycTier {
+    Unverified,
+    Basic,
+    Full,
+}
+
+impl KycTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KycTier::Unverified => "unverified",
+            KycTier::Basic => "basic",
+            KycTier::Full => "full",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<KycTier> {
+        match value {
+            "unverified" => Some(KycTier::Unverified),
+            "basic" => Some(KycTier::Basic),
+            "full" => Some(KycTier::Full),
+            _ => None,
+        }
+    }
+}
+
+/// The user's current tier, used to pick their limits and fees.
+pub async fn tier_of<'e, E: PgExecutor<'e>>(executor: E, user_id: Uuid) -> Result<KycTier, sqlx::Error> {
+    let tier = sqlx::query_scalar::<_, String>("SELECT kyc_tier FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(executor)
+        .await?;
+    Ok(KycTier::parse(&tier).unwrap_or(KycTier::Unverified))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KycError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("A verification request is already waiting for review")]
+    AlreadyPending,
+    #[error("Only reviewers can do this")]
+    NotReviewer,
+    #[error("No pending verification request with this id")]
+    NotFound,
+    #[error("Reviewers cannot decide their own verification")]
+    OwnSubmission,
+    #[error("could not store document: {0}")]
+    Storage(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl KycError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            KycError::Invalid(_) => "invalid_kyc_submission",
+            KycError::AlreadyPending => "kyc_already_pending",
+            KycError::NotReviewer => "forbidden",
+            KycError::NotFound => "kyc_submission_not_found",
+            KycError::OwnSubmission => "four_eyes_required",
+            KycError::Storage(_) | KycError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let body = ErrorResponse {
+            error: self.code().to_string(),
+            message: self.to_string(),
+        };
+        match self {
+            KycError::Storage(e) => {
+                log::error!("kyc document storage failed: {}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+            KycError::Database(_) => HttpResponse::InternalServerError().finish(),
+            KycError::Invalid(_) => HttpResponse::BadRequest().json(body),
+            KycError::AlreadyPending => HttpResponse::Conflict().json(body),
+            KycError::NotReviewer | KycError::OwnSubmission => HttpResponse::Forbidden().json(body),
+            KycError::NotFound => HttpResponse::NotFound().json(body),
+        }
+    }
+}
+
+fn storage_dir() -> PathBuf {
+    PathBuf::from(env::var("KYC_STORAGE_DIR").unwrap_or_else(|_| DEFAULT_STORAGE_DIR.to_string()))
+}
+
+fn extension_for(content_type: &str) -> Option<&'static str> {
+    match content_type {
+        "image/jpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "application/pdf" => Some("pdf"),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    pub kind: String,
+}
+
+async fn store_document(
+    pool: &PgPool,
+    user_id: Uuid,
+    kind: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<KycDocumentView, KycError> {
+    if !DOCUMENT_KINDS.contains(&kind) {
+        return Err(KycError::Invalid(format!("kind must be one of {}", DOCUMENT_KINDS.join(", "))));
+    }
+    let extension = extension_for(content_type)
+        .ok_or_else(|| KycError::Invalid("Documents must be JPEG, PNG or PDF".to_string()))?;
+    if body.is_empty() {
+        return Err(KycError::Invalid("Document is empty".to_string()));
+    }
+
+    let document_id = Uuid::new_v4();
+    let dir = storage_dir().join(user_id.to_string());
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = dir.join(format!("{}.{}", document_id, extension));
+    tokio::fs::write(&path, body).await?;
+
+    let sha256 = format!("{:x}", Sha256::digest(body));
+    let created_at = sqlx::query_scalar::<_, NaiveDateTime>(
+        "INSERT INTO kyc_documents (id, user_id, kind, content_type, size_bytes, sha256, storage_path) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING created_at"
+    )
+    .bind(document_id)
+    .bind(user_id)
+    .bind(kind)
+    .bind(content_type)
+    .bind(body.len() as i64)
+    .bind(&sha256)
+    .bind(path.to_string_lossy().as_ref())
+    .fetch_one(pool)
+    .await;
+    let created_at = match created_at {
+        Ok(t) => t,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e.into());
+        }
+    };
+
+    Ok(KycDocumentView {
+        id: document_id.to_string(),
+        kind: kind.to_string(),
+        content_type: content_type.to_string(),
+        size_bytes: body.len() as i64,
+        sha256,
+        created_at: created_at.and_utc().to_rfc3339(),
+    })
+}
+
+/// `POST /api/kyc/documents?kind=` stores the request body as an identity
+/// document. The body's `Content-Type` must be JPEG, PNG or PDF.
+pub async fn upload_document(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    query: web::Query<UploadQuery>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    match store_document(pool.get_ref(), user_id, &query.kind, &content_type, &body).await {
+        Ok(document) => HttpResponse::Created().json(document),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// Checks a submission, returning the cleaned name and address.
+fn validate_submission(body: &KycSubmissionRequest, today: NaiveDate) -> Result<(KycTier, String, String), KycError> {
+    let tier = KycTier::parse(&body.requested_tier)
+        .filter(|t| *t != KycTier::Unverified)
+        .ok_or_else(|| KycError::Invalid("requested_tier must be basic or full".to_string()))?;
+
+    let legal_name = body.legal_name.trim();
+    if legal_name.is_empty() || legal_name.chars().count() > MAX_NAME_LEN {
+        return Err(KycError::Invalid(format!("legal_name must be 1 to {} characters", MAX_NAME_LEN)));
+    }
+    let address = body.address.trim();
+    if address.is_empty() || address.chars().count() > MAX_ADDRESS_LEN {
+        return Err(KycError::Invalid(format!("address must be 1 to {} characters", MAX_ADDRESS_LEN)));
+    }
+
+    let born = body.date_of_birth;
+    let mut age = today.year() - born.year();
+    if (today.month(), today.day()) < (born.month(), born.day()) {
+        age -= 1;
+    }
+    if born > today || age < MIN_AGE_YEARS {
+        return Err(KycError::Invalid(format!("You must be at least {} years old", MIN_AGE_YEARS)));
+    }
+
+    Ok((tier, legal_name.to_string(), address.to_string()))
+}
+
+async fn submit(pool: &PgPool, user_id: Uuid, body: &KycSubmissionRequest) -> Result<KycSubmissionView, KycError> {
+    let (tier, legal_name, address) = validate_submission(body, Utc::now().date_naive())?;
+
+    let mut tx = pool.begin().await?;
+
+    if tier_of(&mut *tx, user_id).await? >= tier {
+        return Err(KycError::Invalid(format!("Your account is already at the {} tier or above", tier.as_str())));
+    }
+
+    // Documents must be the caller's and not already used by a submission
+    let kinds = sqlx::query_scalar::<_, String>(
+        "SELECT kind FROM kyc_documents WHERE id = ANY($1) AND user_id = $2 AND submission_id IS NULL FOR UPDATE"
+    )
+    .bind(&body.document_ids)
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    if kinds.len() != body.document_ids.len() {
+        return Err(KycError::Invalid("Unknown or already submitted document".to_string()));
+    }
+    if tier == KycTier::Full {
+        let has = |wanted: &[&str]| kinds.iter().any(|k| wanted.contains(&k.as_str()));
+        if !has(&IDENTITY_DOCUMENTS) || !has(&["proof_of_address"]) {
+            return Err(KycError::Invalid(
+                "Full verification needs an identity document and a proof of address".to_string(),
+            ));
+        }
+    }
+
+    let submission_id = Uuid::new_v4();
+    let created_at = sqlx::query_scalar::<_, NaiveDateTime>(
+        "INSERT INTO kyc_submissions (id, user_id, requested_tier, legal_name, date_of_birth, address) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING created_at"
+    )
+    .bind(submission_id)
+    .bind(user_id)
+    .bind(tier.as_str())
+    .bind(&legal_name)
+    .bind(body.date_of_birth)
+    .bind(&address)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db) if db.constraint() == Some("idx_kyc_submissions_one_pending") => KycError::AlreadyPending,
+        _ => KycError::Database(e),
+    })?;
+
+    sqlx::query("UPDATE kyc_documents SET submission_id = $1 WHERE id = ANY($2)")
+        .bind(submission_id)
+        .bind(&body.document_ids)
+        .execute(&mut *tx)
+        .await?;
+
+    let hits = sanctions::screen_name(&legal_name);
+    if !hits.is_empty() {
+        sanctions::record_matches(&mut tx, &sanctions::Subject::Kyc { submission_id }, &legal_name, &hits).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(KycSubmissionView {
+        id: submission_id.to_string(),
+        requested_tier: tier.as_str().to_string(),
+        status: "pending".to_string(),
+        reason: None,
+        created_at: created_at.and_utc().to_rfc3339(),
+        decided_at: None,
+    })
+}
+
+/// `POST /api/kyc/submissions` asks for a higher tier.
+pub async fn create_submission(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<KycSubmissionRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match submit(pool.get_ref(), user_id, &body).await {
+        Ok(submission) => HttpResponse::Created().json(submission),
+        Err(e) => e.to_response(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct SubmissionRow {
+    id: Uuid,
+    requested_tier: String,
+    status: String,
+    reason: Option<String>,
+    created_at: NaiveDateTime,
+    decided_at: Option<NaiveDateTime>,
+}
+
+impl From<SubmissionRow> for KycSubmissionView {
+    fn from(row: SubmissionRow) -> Self {
+        KycSubmissionView {
+            id: row.id.to_string(),
+            requested_tier: row.requested_tier,
+            status: row.status,
+            reason: row.reason,
+            created_at: row.created_at.and_utc().to_rfc3339(),
+            decided_at: row.decided_at.map(|t| t.and_utc().to_rfc3339()),
+        }
+    }
+}
+
+/// `GET /api/kyc` shows the caller's tier and latest submission.
+pub async fn get_status(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let tier = match tier_of(pool.get_ref(), user_id).await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match sqlx::query_as::<_, SubmissionRow>(
+        "SELECT id, requested_tier, status, reason, created_at, decided_at FROM kyc_submissions \
+         WHERE user_id = $1 ORDER BY created_at DESC LIMIT 1"
+    )
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(latest) => HttpResponse::Ok().json(KycStatusResponse {
+            tier: tier.as_str().to_string(),
+            latest_submission: latest.map(KycSubmissionView::from),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PendingSubmissionRow {
+    id: Uuid,
+    user_id: Uuid,
+    username: String,
+    current_tier: String,
+    requested_tier: String,
+    legal_name: String,
+    date_of_birth: NaiveDate,
+    address: String,
+    created_at: NaiveDateTime,
+    document_ids: Vec<Uuid>,
+    sanctions_matches: i64,
+}
+
+async fn require_reviewer(pool: &PgPool, req: &HttpRequest) -> Result<Uuid, KycError> {
+    let reviewer_id = get_user_id_from_req(req).await.ok_or(KycError::NotReviewer)?;
+    if !reviews::is_reviewer(pool, reviewer_id).await? {
+        return Err(KycError::NotReviewer);
+    }
+    Ok(reviewer_id)
+}
+
+/// `GET /api/admin/kyc/submissions` lists submissions awaiting review.
+pub async fn list_pending(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    if get_user_id_from_req(&req).await.is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    if let Err(e) = require_reviewer(pool.get_ref(), &req).await {
+        return e.to_response();
+    }
+
+    match sqlx::query_as::<_, PendingSubmissionRow>(
+        "SELECT s.id, s.user_id, u.username, u.kyc_tier AS current_tier, s.requested_tier, s.legal_name, \
+                s.date_of_birth, s.address, s.created_at, \
+                ARRAY(SELECT d.id FROM kyc_documents d WHERE d.submission_id = s.id ORDER BY d.created_at) AS document_ids, \
+                (SELECT COUNT(*) FROM sanctions_matches m WHERE m.kyc_submission_id = s.id) AS sanctions_matches \
+         FROM kyc_submissions s JOIN users u ON u.id = s.user_id \
+         WHERE s.status = 'pending' ORDER BY s.created_at"
+    )
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => HttpResponse::Ok().json(
+            rows.into_iter()
+                .map(|row| PendingKycView {
+                    id: row.id.to_string(),
+                    user_id: row.user_id.to_string(),
+                    username: row.username,
+                    current_tier: row.current_tier,
+                    requested_tier: row.requested_tier,
+                    legal_name: row.legal_name,
+                    date_of_birth: row.date_of_birth.to_string(),
+                    address: row.address,
+                    document_ids: row.document_ids.iter().map(Uuid::to_string).collect(),
+                    sanctions_matches: row.sanctions_matches,
+                    created_at: row.created_at.and_utc().to_rfc3339(),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct DecisionRow {
+    user_id: Uuid,
+    requested_tier: String,
+    legal_name: String,
+}
+
+async fn decide_submission(
+    conn: &mut PgConnection,
+    reviewer_id: Uuid,
+    submission_id: Uuid,
+    approve: bool,
+    reason: &str,
+) -> Result<SubmissionRow, KycError> {
+    let submission = sqlx::query_as::<_, DecisionRow>(
+        "SELECT user_id, requested_tier, legal_name FROM kyc_submissions WHERE id = $1 AND status = 'pending' FOR UPDATE"
+    )
+    .bind(submission_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(KycError::NotFound)?;
+    if submission.user_id == reviewer_id {
+        return Err(KycError::OwnSubmission);
+    }
+
+    if approve {
+        sqlx::query("UPDATE users SET kyc_tier = $2, full_name = $3 WHERE id = $1")
+            .bind(submission.user_id)
+            .bind(&submission.requested_tier)
+            .bind(&submission.legal_name)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let row = sqlx::query_as::<_, SubmissionRow>(
+        "UPDATE kyc_submissions SET status = $2, reviewed_by = $3, reason = $4, decided_at = NOW() WHERE id = $1 \
+         RETURNING id, requested_tier, status, reason, created_at, decided_at"
+    )
+    .bind(submission_id)
+    .bind(if approve { "approved" } else { "rejected" })
+    .bind(reviewer_id)
+    .bind(reason)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let message = if approve {
+        format!("Your identity is verified. Your account is now at the {} tier.", submission.requested_tier)
+    } else {
+        format!("We could not verify your identity: {}", reason)
+    };
+    notifications::notify(&mut *conn, submission.user_id, &format!("kyc_{}", row.status), &message, None).await?;
+    Ok(row)
+}
+
+async fn decide(
+    pool: &PgPool,
+    req: &HttpRequest,
+    submission_id: Uuid,
+    body: &ReviewDecisionRequest,
+    approve: bool,
+) -> Result<KycSubmissionView, KycError> {
+    let reviewer_id = require_reviewer(pool, req).await?;
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        return Err(KycError::Invalid(format!("A reason of up to {} characters is required", MAX_REASON_LEN)));
+    }
+
+    let mut tx = pool.begin().await?;
+    let row = decide_submission(&mut tx, reviewer_id, submission_id, approve, reason).await?;
+    tx.commit().await?;
+
+    log::info!("kyc submission {} {} by {}", submission_id, row.status, reviewer_id);
+    Ok(row.into())
+}
+
+/// `POST /api/admin/kyc/submissions/{id}/approve` raises the user's tier.
+pub async fn approve(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<ReviewDecisionRequest>,
+) -> HttpResponse {
+    if get_user_id_from_req(&req).await.is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match decide(pool.get_ref(), &req, path.into_inner(), &body, true).await {
+        Ok(submission) => HttpResponse::Ok().json(submission),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// `POST /api/admin/kyc/submissions/{id}/reject` turns a submission down.
+pub async fn reject(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<ReviewDecisionRequest>,
+) -> HttpResponse {
+    if get_user_id_from_req(&req).await.is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match decide(pool.get_ref(), &req, path.into_inner(), &body, false).await {
+        Ok(submission) => HttpResponse::Ok().json(submission),
+        Err(e) => e.to_response(),
+    }
+}
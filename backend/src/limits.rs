@@ -0,0 +1,303 @@
+//! Transaction limits and velocity controls. Limits come from
+//! `transaction_limits`: a row per KYC tier, falling back to the `default`
+//! row, optionally overridden field by field by a row for a single user. A
+//! missing value means no limit.
+//!
+//! Only payments leaving the customer count: moves between their own
+//! accounts and fee lines are ignored. The check runs after the sender's row
+//! has been locked by the transfer, so concurrent payments from the same
+//! sender see each other's usage.
+//!
+//! Money coming in from outside the bank, external deposits and card
+//! top-ups, is counted separately by [`check_inbound`] against the
+//! `inbound_daily` and `inbound_monthly` limits, which fall back to the
+//! outgoing daily and monthly limits when unset. The per-transaction limit
+//! applies to both directions.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::fmt;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::house;
+use crate::models::*;
+use crate::payments::{self, TransferError};
+
+/// Which limit a payment would break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    PerTransaction,
+    Daily,
+    Monthly,
+    HourlyCount,
+    InboundDaily,
+    InboundMonthly,
+}
+
+impl LimitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKind::PerTransaction => "per_transaction",
+            LimitKind::Daily => "daily",
+            LimitKind::Monthly => "monthly",
+            LimitKind::HourlyCount => "hourly_count",
+            LimitKind::InboundDaily => "inbound_daily",
+            LimitKind::InboundMonthly => "inbound_monthly",
+        }
+    }
+}
+
+/// A refused payment: the limit it hit and what is still allowed. For
+/// [`LimitKind::HourlyCount`] both are numbers of payments.
+#[derive(Debug, Clone)]
+pub struct LimitBreach {
+    pub kind: LimitKind,
+    pub limit: Decimal,
+    pub remaining: Decimal,
+}
+
+impl fmt::Display for LimitBreach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            LimitKind::PerTransaction => write!(f, "Amount exceeds the per-transaction limit of {}", self.limit),
+            LimitKind::Daily => write!(f, "Daily limit of {} reached, {} remaining today", self.limit, self.remaining),
+            LimitKind::Monthly => {
+                write!(f, "Monthly limit of {} reached, {} remaining this month", self.limit, self.remaining)
+            }
+            LimitKind::HourlyCount => write!(f, "No more than {} payments are allowed per hour", self.limit),
+            LimitKind::InboundDaily => {
+                write!(f, "Daily deposit limit of {} reached, {} remaining today", self.limit, self.remaining)
+            }
+            LimitKind::InboundMonthly => {
+                write!(f, "Monthly deposit limit of {} reached, {} remaining this month", self.limit, self.remaining)
+            }
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct LimitsRow {
+    tier: String,
+    per_transaction: Option<Decimal>,
+    daily: Option<Decimal>,
+    monthly: Option<Decimal>,
+    hourly_count: Option<i32>,
+    inbound_daily: Option<Decimal>,
+    inbound_monthly: Option<Decimal>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct InboundUsageRow {
+    daily: Decimal,
+    monthly: Decimal,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct UsageRow {
+    daily: Decimal,
+    monthly: Decimal,
+    hourly_count: i64,
+}
+
+async fn load_limits<'e, E: PgExecutor<'e>>(executor: E, user_id: Uuid) -> Result<LimitsRow, sqlx::Error> {
+    sqlx::query_as::<_, LimitsRow>(
+        "SELECT usr.kyc_tier AS tier, \
+                COALESCE(u.per_transaction, t.per_transaction) AS per_transaction, \
+                COALESCE(u.daily, t.daily) AS daily, \
+                COALESCE(u.monthly, t.monthly) AS monthly, \
+                COALESCE(u.hourly_count, t.hourly_count) AS hourly_count, \
+                COALESCE(u.inbound_daily, t.inbound_daily, u.daily, t.daily) AS inbound_daily, \
+                COALESCE(u.inbound_monthly, t.inbound_monthly, u.monthly, t.monthly) AS inbound_monthly \
+         FROM users usr \
+         LEFT JOIN transaction_limits u ON u.user_id = usr.id \
+         LEFT JOIN LATERAL (\
+             SELECT * FROM transaction_limits WHERE tier IN (usr.kyc_tier, 'default') \
+             ORDER BY (tier = usr.kyc_tier) DESC LIMIT 1\
+         ) t ON TRUE \
+         WHERE usr.id = $1"
+    )
+    .bind(user_id)
+    .fetch_one(executor)
+    .await
+}
+
+/// Outgoing totals for the current day and month and the payment count over
+/// the last hour, as of `now`.
+async fn load_usage<'e, E: PgExecutor<'e>>(
+    executor: E,
+    user_id: Uuid,
+    account_number: &str,
+    now: NaiveDateTime,
+) -> Result<UsageRow, sqlx::Error> {
+    sqlx::query_as::<_, UsageRow>(
+        "SELECT COALESCE(SUM(amount) FILTER (WHERE created_at >= date_trunc('day', $3)), 0) AS daily, \
+                COALESCE(SUM(amount) FILTER (WHERE created_at >= date_trunc('month', $3)), 0) AS monthly, \
+                COUNT(*) FILTER (WHERE created_at >= $3 - INTERVAL '1 hour') AS hourly_count \
+         FROM transactions \
+         WHERE from_account = $1 AND status IN ('completed', 'pending_review') AND parent_transaction_id IS NULL \
+           AND created_at >= LEAST(date_trunc('month', $3), $3 - INTERVAL '1 hour') \
+           AND to_account NOT IN (SELECT account_number FROM accounts WHERE user_id = $2)"
+    )
+    .bind(account_number)
+    .bind(user_id)
+    .bind(now)
+    .fetch_one(executor)
+    .await
+}
+
+/// Money received from outside the bank in the current day and month as of
+/// `now`: external deposits that are pending or settled, and card top-ups.
+async fn load_inbound_usage<'e, E: PgExecutor<'e>>(
+    executor: E,
+    user_id: Uuid,
+    account_number: &str,
+    now: NaiveDateTime,
+) -> Result<InboundUsageRow, sqlx::Error> {
+    sqlx::query_as::<_, InboundUsageRow>(
+        "SELECT COALESCE(SUM(amount) FILTER (WHERE created_at >= date_trunc('day', $3)), 0) AS daily, \
+                COALESCE(SUM(amount), 0) AS monthly \
+         FROM (\
+             SELECT amount, created_at FROM external_transfers \
+             WHERE user_id = $1 AND direction = 'deposit' AND status IN ('pending', 'settled') \
+               AND created_at >= date_trunc('month', $3) \
+             UNION ALL \
+             SELECT t.amount, t.created_at FROM transactions t \
+             JOIN house_accounts h ON h.account_number = t.from_account AND h.code = $4 \
+             WHERE t.to_account = $2 AND t.status = 'completed' AND t.created_at >= date_trunc('month', $3)\
+         ) inbound"
+    )
+    .bind(user_id)
+    .bind(account_number)
+    .bind(now)
+    .bind(house::TOPUPS)
+    .fetch_one(executor)
+    .await
+}
+
+fn check_per_transaction(limits: &LimitsRow, amount: Decimal) -> Result<(), TransferError> {
+    if let Some(limit) = limits.per_transaction {
+        if amount > limit {
+            return Err(TransferError::LimitExceeded(LimitBreach {
+                kind: LimitKind::PerTransaction,
+                limit,
+                remaining: limit,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Refuses `amount` on top of `used` when it would go over `limit`.
+fn check_total(kind: LimitKind, limit: Option<Decimal>, used: Decimal, amount: Decimal) -> Result<(), TransferError> {
+    match limit {
+        Some(limit) if used + amount > limit => Err(TransferError::LimitExceeded(LimitBreach {
+            kind,
+            limit,
+            remaining: (limit - used).max(Decimal::ZERO),
+        })),
+        _ => Ok(()),
+    }
+}
+
+/// Refuses a payment of `amount` from `account_number` that would break one
+/// of the sender's limits. Must run on the transfer's connection after the
+/// sender's row is locked.
+pub async fn check(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_number: &str,
+    amount: Decimal,
+) -> Result<(), TransferError> {
+    let limits = load_limits(&mut *conn, user_id).await?;
+    check_per_transaction(&limits, amount)?;
+    if limits.daily.is_none() && limits.monthly.is_none() && limits.hourly_count.is_none() {
+        return Ok(());
+    }
+
+    let usage = load_usage(&mut *conn, user_id, account_number, chrono::Utc::now().naive_utc()).await?;
+    check_total(LimitKind::Daily, limits.daily, usage.daily, amount)?;
+    check_total(LimitKind::Monthly, limits.monthly, usage.monthly, amount)?;
+    if let Some(limit) = limits.hourly_count {
+        if usage.hourly_count >= i64::from(limit) {
+            return Err(TransferError::LimitExceeded(LimitBreach {
+                kind: LimitKind::HourlyCount,
+                limit: Decimal::from(limit),
+                remaining: Decimal::ZERO,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Refuses `amount` arriving from outside the bank into `account_number`,
+/// an external deposit or a card top-up, when it would break the customer's
+/// inbound limits. Must run after the customer's row is locked and before
+/// the deposit or top-up is recorded.
+pub async fn check_inbound(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    account_number: &str,
+    amount: Decimal,
+) -> Result<(), TransferError> {
+    let limits = load_limits(&mut *conn, user_id).await?;
+    check_per_transaction(&limits, amount)?;
+    if limits.inbound_daily.is_none() && limits.inbound_monthly.is_none() {
+        return Ok(());
+    }
+
+    let usage = load_inbound_usage(&mut *conn, user_id, account_number, chrono::Utc::now().naive_utc()).await?;
+    check_total(LimitKind::InboundDaily, limits.inbound_daily, usage.daily, amount)?;
+    check_total(LimitKind::InboundMonthly, limits.inbound_monthly, usage.monthly, amount)
+}
+
+/// `GET /api/limits` shows the caller's limits and what is left of them.
+pub async fn get_limits(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let account_number = match sqlx::query_scalar::<_, String>("SELECT account_number FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(pool.get_ref())
+        .await
+    {
+        Ok(Some(a)) => a,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let limits = match load_limits(pool.get_ref(), user_id).await {
+        Ok(l) => l,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let now = chrono::Utc::now().naive_utc();
+    let usage = match load_usage(pool.get_ref(), user_id, &account_number, now).await {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let inbound = match load_inbound_usage(pool.get_ref(), user_id, &account_number, now).await {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let remaining = |limit: Option<Decimal>, used: Decimal| {
+        limit.map(|l| payments::decimal_to_f64((l - used).max(Decimal::ZERO)))
+    };
+    HttpResponse::Ok().json(LimitsResponse {
+        tier: limits.tier,
+        per_transaction: limits.per_transaction.map(payments::decimal_to_f64),
+        daily: limits.daily.map(payments::decimal_to_f64),
+        daily_remaining: remaining(limits.daily, usage.daily),
+        monthly: limits.monthly.map(payments::decimal_to_f64),
+        monthly_remaining: remaining(limits.monthly, usage.monthly),
+        hourly_count: limits.hourly_count,
+        hourly_remaining: limits.hourly_count.map(|l| (i64::from(l) - usage.hourly_count).max(0)),
+        inbound_daily: limits.inbound_daily.map(payments::decimal_to_f64),
+        inbound_daily_remaining: remaining(limits.inbound_daily, inbound.daily),
+        inbound_monthly: limits.inbound_monthly.map(payments::decimal_to_f64),
+        inbound_monthly_remaining: remaining(limits.inbound_monthly, inbound.monthly),
+    })
+}
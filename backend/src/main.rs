@@ -3,6 +3,33 @@ mod handlers;
 mod db;
 mod auth;
 mod oauth;
+mod payments;
+mod batch;
+mod payment_requests;
+mod qr;
+mod emvco;
+mod qr_image;
+mod history;
+mod receipt;
+mod statements;
+mod iso20022;
+mod period_close;
+mod payees;
+mod recipients;
+mod account_numbers;
+mod accounts;
+mod house;
+mod interest;
+mod fees;
+mod limits;
+mod fraud;
+mod notifications;
+mod reviews;
+mod sanctions;
+mod kyc;
+mod funding;
+mod rails;
+mod ach;
 
 use actix_web::{web, App, HttpServer, middleware::Logger};
 use actix_cors::Cors;
@@ -26,6 +53,31 @@ async fn main() -> std::io::Result<()> {
     let pool = db::init_db(&database_url).await.expect("Failed to initialize database");
     println!("📊 Database connected successfully");
 
+    let rule_count = fraud::init().expect("Failed to load fraud rules");
+    println!("🛡️  Loaded {} fraud rules", rule_count);
+    if let Some(entries) = sanctions::reload().expect("Failed to load sanctions list") {
+        println!("🛡️  Loaded {} sanctions list entries", entries);
+    }
+    if let Some(rail) = rails::init().expect("Invalid payment rail configuration") {
+        println!("🏦 Payment rail: {}", rail);
+    }
+    let simulated_topups = funding::simulated_topups_enabled();
+    if simulated_topups {
+        println!("💳 Simulated card top-ups enabled");
+    }
+
+    match batch::resume_processing(&pool).await {
+        Ok(0) => {}
+        Ok(n) => println!("📦 Resumed {} transfer batch(es)", n),
+        Err(e) => log::error!("could not resume transfer batches: {}", e),
+    }
+
+    period_close::spawn_job(pool.clone());
+    interest::spawn_job(pool.clone());
+    sanctions::spawn_job();
+    rails::spawn_job(pool.clone());
+    ach::spawn_job(pool.clone());
+
     // Parse allowed origins
     let origins: Vec<String> = allowed_origins
         .split(',')
@@ -63,9 +115,68 @@ async fn main() -> std::io::Result<()> {
             
             // API endpoints
             .route("/api/transfer", web::post().to(handlers::transfer))
+            .route("/api/transfers/batch", web::post().to(batch::create_batch))
+            .route("/api/transfers/batch/{id}", web::get().to(batch::get_batch))
+            .route("/api/payment-requests", web::post().to(payment_requests::create_request))
+            .route("/api/payment-requests/incoming", web::get().to(payment_requests::list_incoming))
+            .route("/api/payment-requests/outgoing", web::get().to(payment_requests::list_outgoing))
+            .route("/api/payment-requests/{id}/accept", web::post().to(payment_requests::accept_request))
+            .route("/api/payment-requests/{id}/decline", web::post().to(payment_requests::decline_request))
+            .route("/api/payment-requests/{id}/cancel", web::post().to(payment_requests::cancel_request))
+            .route("/api/payees", web::get().to(payees::list_payees))
+            .route("/api/payees", web::post().to(payees::create_payee))
+            .route("/api/payees/{id}", web::get().to(payees::get_payee))
+            .route("/api/payees/{id}", web::put().to(payees::update_payee))
+            .route("/api/payees/{id}", web::delete().to(payees::delete_payee))
+            .route("/api/recipients/lookup", web::get().to(recipients::lookup))
+            .route("/api/aliases", web::get().to(recipients::list_aliases))
+            .route("/api/aliases", web::post().to(recipients::create_alias))
+            .route("/api/aliases/{id}", web::delete().to(recipients::delete_alias))
+            .route("/api/accounts", web::get().to(accounts::list_accounts))
+            .route("/api/accounts", web::post().to(accounts::open_account))
+            .route("/api/accounts/transfer", web::post().to(accounts::internal_transfer))
+            .route("/api/accounts/{account_number}", web::put().to(accounts::rename_account))
+            .route("/api/accounts/{account_number}", web::delete().to(accounts::close_account))
+            .route("/api/notifications", web::get().to(notifications::list_notifications))
+            .route("/api/notifications/{id}/read", web::post().to(notifications::mark_read))
+            .route("/api/kyc", web::get().to(kyc::get_status))
+            .service(
+                web::resource("/api/kyc/documents")
+                    .app_data(web::PayloadConfig::new(kyc::MAX_DOCUMENT_BYTES))
+                    .route(web::post().to(kyc::upload_document)),
+            )
+            .route("/api/kyc/submissions", web::post().to(kyc::create_submission))
+            .route("/api/admin/kyc/submissions", web::get().to(kyc::list_pending))
+            .route("/api/admin/kyc/submissions/{id}/approve", web::post().to(kyc::approve))
+            .route("/api/admin/kyc/submissions/{id}/reject", web::post().to(kyc::reject))
+            .configure(|cfg| {
+                if simulated_topups {
+                    cfg.route("/api/topups", web::post().to(funding::create_topup));
+                }
+            })
+            .route("/api/deposits", web::post().to(rails::create_deposit))
+            .route("/api/withdrawals", web::post().to(rails::create_withdrawal))
+            .route("/api/external-transfers", web::get().to(rails::list_transfers))
+            .route("/api/rails/{rail}/events", web::post().to(rails::receive_event))
+            .route("/api/admin/deposits", web::post().to(funding::admin_deposit))
+            .route("/api/admin/sanctions/reload", web::post().to(sanctions::reload_list))
+            .route("/api/admin/reviews", web::get().to(reviews::list_pending))
+            .route("/api/admin/reviews/{transaction_id}/approve", web::post().to(reviews::approve))
+            .route("/api/admin/reviews/{transaction_id}/reject", web::post().to(reviews::reject))
+            .route("/api/limits", web::get().to(limits::get_limits))
+            .route("/api/fees/quote", web::get().to(fees::get_quote))
             .route("/api/balance", web::get().to(handlers::get_balance))
             .route("/api/qr-payment", web::post().to(handlers::qr_payment))
+            .route("/api/qr/generate", web::post().to(qr::generate))
+            .route("/api/qr/emvco", web::post().to(qr::generate_emvco))
+            .route("/api/qr/{id}.{format:png|svg}", web::get().to(qr_image::render))
             .route("/api/transactions", web::get().to(handlers::get_transactions))
+            .route("/api/transactions/{id}", web::get().to(handlers::get_transaction))
+            .route("/api/transactions/{id}/receipt", web::get().to(handlers::get_receipt))
+            .route("/api/receipts/verify", web::get().to(handlers::verify_receipt))
+            .route("/api/statements", web::get().to(statements::export_statement))
+            .route("/api/statements/{period}", web::get().to(period_close::get_period_statement))
+            .route("/api/payments/pain001", web::post().to(iso20022::import_pain001))
             .route("/api/health", web::get().to(handlers::health))
     })
     .bind("0.0.0.0:8000")?
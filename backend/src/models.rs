@@ -12,7 +12,10 @@ pub struct User {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TransferRequest {
-    pub recipient_account: String,
+    /// Account number, username, email, `+phone` or `$tag` alias.
+    pub recipient_account: Option<String>,
+    /// Saved payee to pay instead of giving `recipient_account`.
+    pub payee_id: Option<uuid::Uuid>,
     pub amount: f64,
     pub description: Option<String>,
 }
@@ -22,6 +25,10 @@ pub struct TransferResponse {
     pub transaction_id: String,
     pub status: String,
     pub amount: f64,
+    pub fee: f64,
+    /// Amount plus fee.
+    pub total_debited: f64,
+    pub fee_transaction_id: Option<String>,
     pub timestamp: String,
 }
 
@@ -30,11 +37,59 @@ pub struct BalanceResponse {
     pub account_number: String,
     pub balance: f64,
     pub currency: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<String>,
+    /// Funds reserved by payments awaiting review, included in `balance`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held_balance: Option<f64>,
+    /// Every open account of the user, main account first.
+    pub accounts: Vec<AccountView>,
+    pub total_balance: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BalanceQuery {
+    pub as_of: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QRPaymentRequest {
     pub qr_data: String,
+    /// Required when paying a static code that does not carry a fixed amount.
+    pub amount: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QRGenerateRequest {
+    pub amount: Option<f64>,
+    pub reference: Option<String>,
+    pub expires_in_minutes: Option<i64>,
+    pub one_time: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmvQRGenerateRequest {
+    pub amount: Option<f64>,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmvQRResponse {
+    pub qr_data: String,
+    pub account_number: String,
+    pub amount: Option<f64>,
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QRCodeResponse {
+    pub id: String,
+    pub qr_data: String,
+    pub account_number: String,
+    pub amount: Option<f64>,
+    pub reference: Option<String>,
+    pub one_time: bool,
+    pub expires_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -69,3 +124,413 @@ pub struct ErrorResponse {
     pub message: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LimitExceededResponse {
+    pub error: String,
+    pub message: String,
+    /// `per_transaction`, `daily`, `monthly` or `hourly_count`.
+    pub limit_type: String,
+    pub limit: f64,
+    pub remaining: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldErrorResponse {
+    pub error: String,
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    #[default]
+    AllOrNothing,
+    BestEffort,
+}
+
+impl BatchMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchMode::AllOrNothing => "all_or_nothing",
+            BatchMode::BestEffort => "best_effort",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<BatchMode> {
+        match value {
+            "all_or_nothing" => Some(BatchMode::AllOrNothing),
+            "best_effort" => Some(BatchMode::BestEffort),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchTransferRequest {
+    pub mode: Option<BatchMode>,
+    pub items: Vec<TransferRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchItemResponse {
+    pub line_number: i32,
+    pub recipient_account: String,
+    pub amount: Option<f64>,
+    pub description: Option<String>,
+    pub status: String,
+    pub transaction_id: Option<String>,
+    pub error: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchResponse {
+    pub batch_id: String,
+    pub mode: String,
+    pub status: String,
+    pub total_items: i32,
+    pub succeeded: i32,
+    pub failed: i32,
+    pub total_amount: f64,
+    pub created_at: String,
+    pub completed_at: Option<String>,
+    pub items: Vec<BatchItemResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePaymentRequest {
+    /// Account number, username, email or alias of the user being asked to pay.
+    pub target: String,
+    pub amount: f64,
+    pub note: Option<String>,
+    pub expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentRequestView {
+    pub id: String,
+    pub requester_username: String,
+    pub requester_account: String,
+    pub payee_username: String,
+    pub payee_account: String,
+    pub amount: f64,
+    pub note: Option<String>,
+    pub status: String,
+    pub transaction_id: Option<String>,
+    pub expires_at: String,
+    pub created_at: String,
+    pub responded_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionView {
+    pub id: String,
+    /// `sent` or `received`, from the caller's point of view.
+    pub direction: String,
+    pub from_account: String,
+    pub to_account: String,
+    pub counterparty_account: String,
+    pub counterparty_username: Option<String>,
+    pub amount: f64,
+    /// Negative for money leaving the caller's account.
+    pub signed_amount: f64,
+    pub description: Option<String>,
+    pub status: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub items: Vec<TransactionView>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionDetail {
+    #[serde(flatten)]
+    pub transaction: TransactionView,
+    pub from_username: Option<String>,
+    pub to_username: Option<String>,
+    pub reference_number: String,
+    pub receipt_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceiptVerification {
+    pub valid: bool,
+    pub transaction_id: String,
+    pub amount: Option<f64>,
+    pub status: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeriodStatementResponse {
+    pub period: String,
+    pub account_number: String,
+    pub currency: String,
+    pub period_start: String,
+    pub period_end: String,
+    pub opening_balance: f64,
+    pub total_debits: f64,
+    pub total_credits: f64,
+    pub closing_balance: f64,
+    pub transaction_count: i32,
+    pub closed_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePayeeRequest {
+    pub nickname: String,
+    pub account_number: String,
+    pub default_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdatePayeeRequest {
+    pub nickname: Option<String>,
+    pub default_description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PayeeView {
+    pub id: String,
+    pub nickname: String,
+    pub account_number: String,
+    pub recipient_username: Option<String>,
+    pub default_description: Option<String>,
+    /// Cap on the next transfer while no payment has been made to this payee yet.
+    pub first_transfer_limit: Option<f64>,
+    pub last_paid_at: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecipientPreview {
+    pub matched_by: String,
+    pub masked_name: String,
+    pub masked_account: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateAliasRequest {
+    pub alias: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AliasView {
+    pub id: String,
+    pub alias: String,
+    pub kind: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountView {
+    pub account_number: String,
+    pub name: String,
+    pub kind: String,
+    pub balance: f64,
+    pub is_primary: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAccountRequest {
+    pub name: String,
+    /// `checking`, `savings` or `pot`; defaults to `pot`.
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameAccountRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InternalTransferRequest {
+    pub from_account: String,
+    pub to_account: String,
+    pub amount: f64,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeeQuoteResponse {
+    pub transaction_type: String,
+    pub amount: f64,
+    pub fee: f64,
+    pub total: f64,
+    pub currency: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LimitsResponse {
+    pub tier: String,
+    pub per_transaction: Option<f64>,
+    pub daily: Option<f64>,
+    pub daily_remaining: Option<f64>,
+    pub monthly: Option<f64>,
+    pub monthly_remaining: Option<f64>,
+    pub hourly_count: Option<i32>,
+    pub hourly_remaining: Option<i64>,
+    pub inbound_daily: Option<f64>,
+    pub inbound_daily_remaining: Option<f64>,
+    pub inbound_monthly: Option<f64>,
+    pub inbound_monthly_remaining: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RuleHitView {
+    pub rule: String,
+    pub action: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingReviewView {
+    pub transaction_id: String,
+    pub payment_type: String,
+    pub from_account: String,
+    pub sender_username: String,
+    pub to_account: String,
+    pub amount: f64,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub hits: Vec<RuleHitView>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewDecisionRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviewDecisionResponse {
+    pub transaction_id: String,
+    pub decision: String,
+    pub status: String,
+    pub reviewed_by: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationView {
+    pub id: String,
+    pub kind: String,
+    pub message: String,
+    pub transaction_id: Option<String>,
+    pub created_at: String,
+    pub read: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SanctionsReloadResponse {
+    pub entries: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KycDocumentView {
+    pub id: String,
+    pub kind: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub sha256: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KycSubmissionRequest {
+    pub requested_tier: String,
+    pub legal_name: String,
+    pub date_of_birth: chrono::NaiveDate,
+    pub address: String,
+    #[serde(default)]
+    pub document_ids: Vec<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KycSubmissionView {
+    pub id: String,
+    pub requested_tier: String,
+    pub status: String,
+    pub reason: Option<String>,
+    pub created_at: String,
+    pub decided_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KycStatusResponse {
+    pub tier: String,
+    pub latest_submission: Option<KycSubmissionView>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingKycView {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub current_tier: String,
+    pub requested_tier: String,
+    pub legal_name: String,
+    pub date_of_birth: String,
+    pub address: String,
+    pub document_ids: Vec<String>,
+    pub sanctions_matches: i64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminDepositRequest {
+    pub account_number: String,
+    pub amount: f64,
+    pub reference: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TopUpRequest {
+    pub amount: f64,
+    pub card_number: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FundingResponse {
+    pub transaction_id: String,
+    pub source: String,
+    pub amount: f64,
+    pub new_balance: f64,
+    pub reference: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExternalTransferRequest {
+    pub amount: f64,
+    pub routing_number: String,
+    pub account_number: String,
+    pub account_holder: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExternalTransferView {
+    pub id: String,
+    pub direction: String,
+    pub amount: f64,
+    pub status: String,
+    pub rail: String,
+    pub rail_reference: Option<String>,
+    pub routing_number: String,
+    pub account_last4: String,
+    pub return_code: Option<String>,
+    pub return_reason: Option<String>,
+    pub transaction_id: Option<String>,
+    pub created_at: String,
+    pub settled_at: Option<String>,
+}
@@ -0,0 +1,90 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+
+const MAX_LISTED: i64 = 100;
+
+/// Stores an in-app notification for `user_id`.
+pub async fn notify<'e, E: PgExecutor<'e>>(
+    executor: E,
+    user_id: Uuid,
+    kind: &str,
+    message: &str,
+    transaction_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO notifications (id, user_id, kind, message, transaction_id) VALUES ($1, $2, $3, $4, $5)"
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(kind)
+    .bind(message)
+    .bind(transaction_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct NotificationRow {
+    id: Uuid,
+    kind: String,
+    message: String,
+    transaction_id: Option<Uuid>,
+    created_at: NaiveDateTime,
+    read_at: Option<NaiveDateTime>,
+}
+
+pub async fn list_notifications(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query_as::<_, NotificationRow>(
+        "SELECT id, kind, message, transaction_id, created_at, read_at FROM notifications \
+         WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2"
+    )
+    .bind(user_id)
+    .bind(MAX_LISTED)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => HttpResponse::Ok().json(
+            rows.into_iter()
+                .map(|row| NotificationView {
+                    id: row.id.to_string(),
+                    kind: row.kind,
+                    message: row.message,
+                    transaction_id: row.transaction_id.map(|id| id.to_string()),
+                    created_at: row.created_at.and_utc().to_rfc3339(),
+                    read: row.read_at.is_some(),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn mark_read(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query("UPDATE notifications SET read_at = COALESCE(read_at, NOW()) WHERE id = $1 AND user_id = $2")
+        .bind(path.into_inner())
+        .bind(user_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(ErrorResponse {
+            error: "notification_not_found".to_string(),
+            message: "Notification not found".to_string(),
+        }),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
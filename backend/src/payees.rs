@@ -0,0 +1,333 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments;
+use crate::recipients;
+
+const DEFAULT_FIRST_TRANSFER_LIMIT: &str = "500";
+const MAX_NICKNAME_LEN: usize = 100;
+const MAX_DESCRIPTION_LEN: usize = 255;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PayeeError {
+    #[error("Payee not found")]
+    NotFound,
+    #[error("The first transfer to a new payee is limited to {0:.2}")]
+    FirstTransferLimit(Decimal),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl PayeeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PayeeError::NotFound => "payee_not_found",
+            PayeeError::FirstTransferLimit(_) => "first_transfer_limit",
+            PayeeError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let body = ErrorResponse {
+            error: self.code().to_string(),
+            message: self.to_string(),
+        };
+        match self {
+            PayeeError::Database(_) => HttpResponse::InternalServerError().finish(),
+            PayeeError::NotFound => HttpResponse::NotFound().json(body),
+            PayeeError::FirstTransferLimit(_) => HttpResponse::BadRequest().json(body),
+        }
+    }
+}
+
+/// Lower cap applied until the first payment to a payee has gone through,
+/// so a payee added by someone who has taken over the account cannot be
+/// used to drain it in one go.
+pub fn first_transfer_limit() -> Decimal {
+    env::var("PAYEE_FIRST_TRANSFER_LIMIT")
+        .ok()
+        .and_then(|v| Decimal::from_str(&v).ok())
+        .unwrap_or_else(|| Decimal::from_str(DEFAULT_FIRST_TRANSFER_LIMIT).expect("valid default"))
+}
+
+/// Payee details needed to make a transfer.
+#[derive(Debug)]
+pub struct PayeeTarget {
+    pub id: Uuid,
+    pub account_number: String,
+    pub default_description: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct PayeeLockRow {
+    id: Uuid,
+    account_number: String,
+    default_description: Option<String>,
+    first_paid_at: Option<NaiveDateTime>,
+}
+
+#[derive(sqlx::FromRow)]
+struct PayeeRow {
+    id: Uuid,
+    nickname: String,
+    account_number: String,
+    recipient_username: Option<String>,
+    default_description: Option<String>,
+    first_paid_at: Option<NaiveDateTime>,
+    last_paid_at: Option<NaiveDateTime>,
+    created_at: NaiveDateTime,
+}
+
+impl From<PayeeRow> for PayeeView {
+    fn from(row: PayeeRow) -> Self {
+        PayeeView {
+            id: row.id.to_string(),
+            nickname: row.nickname,
+            account_number: row.account_number,
+            recipient_username: row.recipient_username,
+            default_description: row.default_description,
+            first_transfer_limit: row
+                .first_paid_at
+                .is_none()
+                .then(|| payments::decimal_to_f64(first_transfer_limit())),
+            last_paid_at: row.last_paid_at.map(|t| t.and_utc().to_rfc3339()),
+            created_at: row.created_at.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+const SELECT_VIEW: &str = r#"
+    SELECT p.id, p.nickname, p.account_number, u.username AS recipient_username,
+           p.default_description, p.first_paid_at, p.last_paid_at, p.created_at
+    FROM payees p
+    LEFT JOIN users u ON u.account_number = p.account_number
+"#;
+
+/// Locks the payee for the rest of the caller's transaction and checks the
+/// first-transfer limit. Call [`record_payment`] once the transfer succeeds.
+pub async fn lock_for_transfer(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    payee_id: Uuid,
+    amount: Decimal,
+) -> Result<PayeeTarget, PayeeError> {
+    let payee = sqlx::query_as::<_, PayeeLockRow>(
+        "SELECT id, account_number, default_description, first_paid_at FROM payees WHERE id = $1 AND user_id = $2 FOR UPDATE"
+    )
+    .bind(payee_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(PayeeError::NotFound)?;
+
+    let limit = first_transfer_limit();
+    if payee.first_paid_at.is_none() && amount > limit {
+        return Err(PayeeError::FirstTransferLimit(limit));
+    }
+
+    Ok(PayeeTarget {
+        id: payee.id,
+        account_number: payee.account_number,
+        default_description: payee.default_description,
+    })
+}
+
+pub async fn record_payment(conn: &mut PgConnection, payee_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE payees SET first_paid_at = COALESCE(first_paid_at, NOW()), last_paid_at = NOW() WHERE id = $1")
+        .bind(payee_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+fn bad_request(error: &str, message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        error: error.to_string(),
+        message,
+    })
+}
+
+fn clean_nickname(value: &str) -> Result<String, String> {
+    let nickname = value.trim();
+    if nickname.is_empty() || nickname.chars().count() > MAX_NICKNAME_LEN {
+        return Err(format!("Nickname must be 1 to {} characters", MAX_NICKNAME_LEN));
+    }
+    Ok(nickname.to_string())
+}
+
+fn clean_description(value: Option<&str>) -> Result<Option<String>, String> {
+    let description = value.map(str::trim).filter(|d| !d.is_empty());
+    if description.is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LEN) {
+        return Err(format!("Default description must be at most {} characters", MAX_DESCRIPTION_LEN));
+    }
+    Ok(description.map(str::to_string))
+}
+
+async fn fetch_view(pool: &PgPool, user_id: Uuid, payee_id: Uuid) -> Result<Option<PayeeView>, sqlx::Error> {
+    let row = sqlx::query_as::<_, PayeeRow>(&format!("{} WHERE p.id = $1 AND p.user_id = $2", SELECT_VIEW))
+        .bind(payee_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(PayeeView::from))
+}
+
+pub async fn list_payees(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query_as::<_, PayeeRow>(&format!("{} WHERE p.user_id = $1 ORDER BY LOWER(p.nickname)", SELECT_VIEW))
+        .bind(user_id)
+        .fetch_all(pool.get_ref())
+        .await
+    {
+        Ok(rows) => HttpResponse::Ok().json(rows.into_iter().map(PayeeView::from).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn get_payee(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match fetch_view(pool.get_ref(), user_id, path.into_inner()).await {
+        Ok(Some(payee)) => HttpResponse::Ok().json(payee),
+        Ok(None) => PayeeError::NotFound.to_response(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn create_payee(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<CreatePayeeRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let nickname = match clean_nickname(&body.nickname) {
+        Ok(n) => n,
+        Err(message) => return bad_request("invalid_nickname", message),
+    };
+    let default_description = match clean_description(body.default_description.as_deref()) {
+        Ok(d) => d,
+        Err(message) => return bad_request("invalid_description", message),
+    };
+
+    // Payees are stored by account number so later alias changes cannot redirect them
+    let recipient = match recipients::resolve(pool.get_ref(), &body.account_number).await {
+        Ok(Some((_, r))) => r,
+        Ok(None) => return bad_request("recipient_not_found", "Recipient account not found".to_string()),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let account_number = recipient.account_number.as_str();
+    if recipient.user_id == user_id {
+        return bad_request("invalid_payee", "You cannot save your own account as a payee".to_string());
+    }
+
+    let payee_id = Uuid::new_v4();
+    match sqlx::query(
+        "INSERT INTO payees (id, user_id, nickname, account_number, default_description) VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (user_id, account_number) DO NOTHING"
+    )
+    .bind(payee_id)
+    .bind(user_id)
+    .bind(&nickname)
+    .bind(account_number)
+    .bind(&default_description)
+    .execute(pool.get_ref())
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => {
+            return HttpResponse::Conflict().json(ErrorResponse {
+                error: "payee_exists".to_string(),
+                message: "This account is already saved as a payee".to_string(),
+            });
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match fetch_view(pool.get_ref(), user_id, payee_id).await {
+        Ok(Some(payee)) => HttpResponse::Created().json(payee),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Only the nickname and default description can change; a different
+/// account is a different payee and starts over at the first-transfer limit.
+pub async fn update_payee(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<UpdatePayeeRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    let payee_id = path.into_inner();
+
+    let nickname = match body.nickname.as_deref().map(clean_nickname).transpose() {
+        Ok(n) => n,
+        Err(message) => return bad_request("invalid_nickname", message),
+    };
+    // An empty string clears the default description
+    let default_description = match clean_description(body.default_description.as_deref()) {
+        Ok(d) => d,
+        Err(message) => return bad_request("invalid_description", message),
+    };
+
+    match sqlx::query(
+        "UPDATE payees SET nickname = COALESCE($3, nickname), \
+                default_description = CASE WHEN $4 THEN $5 ELSE default_description END \
+         WHERE id = $1 AND user_id = $2"
+    )
+    .bind(payee_id)
+    .bind(user_id)
+    .bind(nickname)
+    .bind(body.default_description.is_some())
+    .bind(default_description)
+    .execute(pool.get_ref())
+    .await
+    {
+        Ok(result) if result.rows_affected() == 0 => return PayeeError::NotFound.to_response(),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match fetch_view(pool.get_ref(), user_id, payee_id).await {
+        Ok(Some(payee)) => HttpResponse::Ok().json(payee),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn delete_payee(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query("DELETE FROM payees WHERE id = $1 AND user_id = $2")
+        .bind(path.into_inner())
+        .bind(user_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => PayeeError::NotFound.to_response(),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
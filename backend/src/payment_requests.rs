@@ -0,0 +1,300 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments::{self, TransferError};
+use crate::{fees, recipients};
+
+const DEFAULT_EXPIRY_HOURS: i64 = 7 * 24;
+const MAX_EXPIRY_HOURS: i64 = 30 * 24;
+
+#[derive(sqlx::FromRow)]
+struct PaymentRequestRow {
+    id: Uuid,
+    requester_username: String,
+    requester_account: String,
+    payee_username: String,
+    payee_account: String,
+    amount: Decimal,
+    note: Option<String>,
+    status: String,
+    transaction_id: Option<Uuid>,
+    expires_at: NaiveDateTime,
+    created_at: NaiveDateTime,
+    responded_at: Option<NaiveDateTime>,
+}
+
+impl From<PaymentRequestRow> for PaymentRequestView {
+    fn from(row: PaymentRequestRow) -> Self {
+        PaymentRequestView {
+            id: row.id.to_string(),
+            requester_username: row.requester_username,
+            requester_account: row.requester_account,
+            payee_username: row.payee_username,
+            payee_account: row.payee_account,
+            amount: payments::decimal_to_f64(row.amount),
+            note: row.note,
+            status: row.status,
+            transaction_id: row.transaction_id.map(|id| id.to_string()),
+            expires_at: row.expires_at.and_utc().to_rfc3339(),
+            created_at: row.created_at.and_utc().to_rfc3339(),
+            responded_at: row.responded_at.map(|t| t.and_utc().to_rfc3339()),
+        }
+    }
+}
+
+const SELECT_VIEW: &str = r#"
+    SELECT pr.id, r.username AS requester_username, r.account_number AS requester_account,
+           p.username AS payee_username, p.account_number AS payee_account,
+           pr.amount, pr.note, pr.status, pr.transaction_id, pr.expires_at, pr.created_at, pr.responded_at
+    FROM payment_requests pr
+    JOIN users r ON r.id = pr.requester_id
+    JOIN users p ON p.id = pr.payee_id
+"#;
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorResponse {
+        error: "request_not_found".to_string(),
+        message: "Payment request not found".to_string(),
+    })
+}
+
+/// Marks pending requests past their expiry so every reader sees the same state.
+async fn expire_stale(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE payment_requests SET status = 'expired' WHERE status = 'pending' AND expires_at < NOW()")
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn fetch_view(pool: &PgPool, id: Uuid) -> Result<Option<PaymentRequestView>, sqlx::Error> {
+    let row = sqlx::query_as::<_, PaymentRequestRow>(&format!("{} WHERE pr.id = $1", SELECT_VIEW))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(PaymentRequestView::from))
+}
+
+pub async fn create_request(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<CreatePaymentRequest>,
+) -> HttpResponse {
+    let requester_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let amount = match payments::parse_amount(body.amount) {
+        Some(a) => a,
+        None => return TransferError::InvalidAmount.to_response(),
+    };
+
+    let expires_in_hours = body.expires_in_hours.unwrap_or(DEFAULT_EXPIRY_HOURS);
+    if !(1..=MAX_EXPIRY_HOURS).contains(&expires_in_hours) {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_expiry".to_string(),
+            message: format!("expires_in_hours must be between 1 and {}", MAX_EXPIRY_HOURS),
+        });
+    }
+
+    let payee = match recipients::resolve(pool.get_ref(), &body.target).await {
+        Ok(Some((_, p))) => p,
+        Ok(None) => return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "payee_not_found".to_string(),
+            message: "No user matches the given account number, username, email or alias".to_string(),
+        }),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if payee.user_id == requester_id {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_payee".to_string(),
+            message: "You cannot request money from yourself".to_string(),
+        });
+    }
+
+    let request_id = Uuid::new_v4();
+    let expires_at = Utc::now().naive_utc() + Duration::hours(expires_in_hours);
+    if sqlx::query(
+        "INSERT INTO payment_requests (id, requester_id, payee_id, amount, note, status, expires_at) VALUES ($1, $2, $3, $4, $5, 'pending', $6)"
+    )
+    .bind(request_id)
+    .bind(requester_id)
+    .bind(payee.user_id)
+    .bind(amount)
+    .bind(body.note.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(expires_at)
+    .execute(pool.get_ref())
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match fetch_view(pool.get_ref(), request_id).await {
+        Ok(Some(view)) => HttpResponse::Created().json(view),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+async fn list_requests(pool: &PgPool, req: &HttpRequest, column: &str) -> HttpResponse {
+    let user_id = match get_user_id_from_req(req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let mut conn = match pool.acquire().await {
+        Ok(c) => c,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if expire_stale(&mut conn).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match sqlx::query_as::<_, PaymentRequestRow>(&format!(
+        "{} WHERE pr.{} = $1 ORDER BY pr.created_at DESC LIMIT 100",
+        SELECT_VIEW, column
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await {
+        Ok(rows) => HttpResponse::Ok().json(rows.into_iter().map(PaymentRequestView::from).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn list_incoming(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    list_requests(pool.get_ref(), &req, "payee_id").await
+}
+
+pub async fn list_outgoing(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    list_requests(pool.get_ref(), &req, "requester_id").await
+}
+
+#[derive(sqlx::FromRow)]
+struct LockedRequestRow {
+    requester_id: Uuid,
+    payee_id: Uuid,
+    amount: Decimal,
+    note: Option<String>,
+    status: String,
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Accept,
+    Decline,
+    Cancel,
+}
+
+/// Applies a payee or requester decision to a pending request. Accepting runs
+/// the transfer in the same database transaction as the status change.
+async fn respond(pool: &PgPool, req: &HttpRequest, request_id: Uuid, action: Action) -> HttpResponse {
+    let user_id = match get_user_id_from_req(req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let mut tx = match pool.begin().await {
+        Ok(t) => t,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if expire_stale(&mut tx).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let request = match sqlx::query_as::<_, LockedRequestRow>(
+        "SELECT requester_id, payee_id, amount, note, status FROM payment_requests WHERE id = $1 FOR UPDATE"
+    )
+    .bind(request_id)
+    .fetch_optional(&mut *tx)
+    .await {
+        Ok(Some(r)) => r,
+        Ok(None) => return not_found(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let allowed = match action {
+        Action::Accept | Action::Decline => request.payee_id == user_id,
+        Action::Cancel => request.requester_id == user_id,
+    };
+    if !allowed {
+        return not_found();
+    }
+
+    if request.status != "pending" {
+        return HttpResponse::Conflict().json(ErrorResponse {
+            error: "request_not_pending".to_string(),
+            message: format!("Payment request is already {}", request.status),
+        });
+    }
+
+    let (status, transaction_id) = match action {
+        Action::Accept => {
+            let requester_account = match sqlx::query_scalar::<_, String>(
+                "SELECT account_number FROM users WHERE id = $1"
+            )
+            .bind(request.requester_id)
+            .fetch_one(&mut *tx)
+            .await {
+                Ok(a) => a,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+
+            match payments::transfer(
+                &mut tx,
+                user_id,
+                &requester_account,
+                request.amount,
+                request.note.as_deref(),
+                fees::PAYMENT_REQUEST,
+            ).await {
+                // A held payment still answers the request; the reviewer decides whether it settles
+                Ok(outcome) => ("accepted", Some(outcome.transaction_id)),
+                Err(e) => return e.to_response(),
+            }
+        }
+        Action::Decline => ("declined", None),
+        Action::Cancel => ("cancelled", None),
+    };
+
+    if sqlx::query(
+        "UPDATE payment_requests SET status = $1, transaction_id = $2, responded_at = NOW() WHERE id = $3"
+    )
+    .bind(status)
+    .bind(transaction_id)
+    .bind(request_id)
+    .execute(&mut *tx)
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match fetch_view(pool, request_id).await {
+        Ok(Some(view)) => HttpResponse::Ok().json(view),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn accept_request(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    respond(pool.get_ref(), &req, path.into_inner(), Action::Accept).await
+}
+
+pub async fn decline_request(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    respond(pool.get_ref(), &req, path.into_inner(), Action::Decline).await
+}
+
+pub async fn cancel_request(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    respond(pool.get_ref(), &req, path.into_inner(), Action::Cancel).await
+}
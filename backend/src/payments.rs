@@ -0,0 +1,437 @@
+use actix_web::HttpResponse;
+use chrono::NaiveDateTime;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use sqlx::PgConnection;
+use uuid::Uuid;
+use crate::account_numbers;
+use crate::fraud::{self, RuleAction};
+use crate::limits::{self, LimitBreach};
+use crate::models::{ErrorResponse, LimitExceededResponse};
+use crate::period_close;
+use crate::recipients;
+use crate::sanctions::{self, SanctionsHit};
+
+/// Reasons a single money movement can be refused.
+#[derive(Debug, thiserror::Error)]
+pub enum TransferError {
+    #[error("Amount must be greater than 0")]
+    InvalidAmount,
+    #[error("Insufficient funds for transfer")]
+    InsufficientFunds,
+    #[error("Recipient account not found")]
+    RecipientNotFound,
+    #[error("Account number is not valid, please check it for typos")]
+    InvalidAccountNumber,
+    #[error("Account not found")]
+    AccountNotFound,
+    #[error("The accounting period for this date is closed")]
+    PeriodClosed,
+    #[error("{0}")]
+    LimitExceeded(LimitBreach),
+    #[error("This payment was declined by our risk checks")]
+    Blocked,
+    #[error("This payment is no longer awaiting review")]
+    NotHeld,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl TransferError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransferError::InvalidAmount => "invalid_amount",
+            TransferError::InsufficientFunds => "insufficient_funds",
+            TransferError::RecipientNotFound => "recipient_not_found",
+            TransferError::InvalidAccountNumber => "invalid_account_number",
+            TransferError::AccountNotFound => "account_not_found",
+            TransferError::PeriodClosed => "period_closed",
+            TransferError::LimitExceeded(_) => "limit_exceeded",
+            TransferError::Blocked => "payment_blocked",
+            TransferError::NotHeld => "payment_not_held",
+            TransferError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        match self {
+            TransferError::Database(_) => HttpResponse::InternalServerError().finish(),
+            TransferError::LimitExceeded(breach) => HttpResponse::Forbidden().json(LimitExceededResponse {
+                error: self.code().to_string(),
+                message: self.to_string(),
+                limit_type: breach.kind.as_str().to_string(),
+                limit: decimal_to_f64(breach.limit),
+                remaining: decimal_to_f64(breach.remaining),
+            }),
+            TransferError::Blocked => HttpResponse::Forbidden().json(ErrorResponse {
+                error: self.code().to_string(),
+                message: self.to_string(),
+            }),
+            TransferError::NotHeld => HttpResponse::Conflict().json(ErrorResponse {
+                error: self.code().to_string(),
+                message: self.to_string(),
+            }),
+            _ => HttpResponse::BadRequest().json(ErrorResponse {
+                error: self.code().to_string(),
+                message: self.to_string(),
+            }),
+        }
+    }
+}
+
+/// Result of a recorded movement, as seen by the sender.
+#[derive(Debug)]
+pub struct TransferOutcome {
+    pub transaction_id: Uuid,
+    pub from_account: String,
+    pub to_account: String,
+    pub amount: Decimal,
+    /// Funds the sender can still spend.
+    pub sender_balance: Decimal,
+    pub created_at: NaiveDateTime,
+    /// Held in `pending_review` by screening rather than completed.
+    pub held_for_review: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct SenderRow {
+    account_number: String,
+    available: Decimal,
+    display_name: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct RecipientRow {
+    id: Uuid,
+    display_name: String,
+}
+
+/// Converts a client-supplied amount into a positive, cent-rounded decimal.
+pub fn parse_amount(value: f64) -> Option<Decimal> {
+    Decimal::from_f64(value)
+        .map(|d| d.round_dp(2))
+        .filter(|d| *d > Decimal::ZERO)
+}
+
+pub fn decimal_to_f64(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+/// Inserts a completed `transactions` row. Balances must already have been
+/// moved on the same connection.
+pub async fn record_transaction(
+    conn: &mut PgConnection,
+    from_account: &str,
+    to_account: &str,
+    amount: Decimal,
+    description: Option<&str>,
+) -> Result<(Uuid, NaiveDateTime), TransferError> {
+    record_transaction_with_parent(conn, from_account, to_account, amount, description, None).await
+}
+
+/// Like [`record_transaction`], for ledger lines that belong to another
+/// transaction, such as fees.
+pub async fn record_transaction_with_parent(
+    conn: &mut PgConnection,
+    from_account: &str,
+    to_account: &str,
+    amount: Decimal,
+    description: Option<&str>,
+    parent_transaction_id: Option<Uuid>,
+) -> Result<(Uuid, NaiveDateTime), TransferError> {
+    insert_transaction(conn, from_account, to_account, amount, description, "completed", parent_transaction_id).await
+}
+
+/// Maps a failed `transactions` write, recognising the closed period trigger.
+fn ledger_error(e: sqlx::Error) -> TransferError {
+    match &e {
+        sqlx::Error::Database(db) if db.code().as_deref() == Some(period_close::PERIOD_CLOSED_SQLSTATE) => {
+            TransferError::PeriodClosed
+        }
+        _ => TransferError::Database(e),
+    }
+}
+
+async fn insert_transaction(
+    conn: &mut PgConnection,
+    from_account: &str,
+    to_account: &str,
+    amount: Decimal,
+    description: Option<&str>,
+    status: &str,
+    parent_transaction_id: Option<Uuid>,
+) -> Result<(Uuid, NaiveDateTime), TransferError> {
+    let transaction_id = Uuid::new_v4();
+    let created_at = chrono::Utc::now().naive_utc();
+    sqlx::query(
+        "INSERT INTO transactions (id, from_account, to_account, amount, description, status, created_at, parent_transaction_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+    )
+    .bind(transaction_id)
+    .bind(from_account)
+    .bind(to_account)
+    .bind(amount)
+    .bind(description)
+    .bind(status)
+    .bind(created_at)
+    .bind(parent_transaction_id)
+    .execute(conn)
+    .await
+    .map_err(ledger_error)?;
+    Ok((transaction_id, created_at))
+}
+
+/// Moves `amount` from the sender to `recipient_account` and records the
+/// transaction. Every customer payment is screened against the fraud rules
+/// for `payment_type` and the sanctions list first; one flagged for review is
+/// recorded as `pending_review` with the sender's funds held instead of
+/// moved. Runs on the caller's connection so it can take part in a larger
+/// database transaction; the caller is responsible for committing.
+pub async fn transfer(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    recipient_account: &str,
+    amount: Decimal,
+    description: Option<&str>,
+    payment_type: &str,
+) -> Result<TransferOutcome, TransferError> {
+    if amount <= Decimal::ZERO {
+        return Err(TransferError::InvalidAmount);
+    }
+
+    let sender = sqlx::query_as::<_, SenderRow>(
+        "SELECT account_number, balance - held_balance AS available, COALESCE(full_name, username) AS display_name \
+         FROM users WHERE id = $1 FOR UPDATE"
+    )
+    .bind(sender_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if sender.available < amount {
+        return Err(TransferError::InsufficientFunds);
+    }
+
+    limits::check(&mut *conn, sender_id, &sender.account_number, amount).await?;
+
+    // Numbers failing their check digits can only match a legacy account
+    let checked = account_numbers::is_valid(recipient_account);
+    let recipient = sqlx::query_as::<_, RecipientRow>(
+        "SELECT id, COALESCE(full_name, username) AS display_name FROM users \
+         WHERE account_number = $1 AND ($2 OR legacy_account_number) FOR UPDATE"
+    )
+    .bind(recipient_account)
+    .bind(checked)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(if checked { TransferError::RecipientNotFound } else { recipients::not_found_error(recipient_account) })?;
+
+    let payment = fraud::Payment {
+        payment_type,
+        sender_id,
+        from_account: &sender.account_number,
+        to_account: recipient_account,
+        amount,
+    };
+    let mut screening = fraud::screen(&mut *conn, &payment).await?;
+    let mut sanctions_hits: Vec<(&str, Vec<SanctionsHit>)> = Vec::new();
+    for name in [sender.display_name.as_str(), recipient.display_name.as_str()] {
+        let hits = sanctions::screen_name(name);
+        if let Some(best) = hits.first() {
+            screening.hits.push(fraud::RuleHit {
+                rule: "sanctions_match".to_string(),
+                action: RuleAction::Review,
+                detail: format!("{} resembles listed {} ({:.2})", name, best.list_name, best.score),
+            });
+            screening.action = screening.action.max(RuleAction::Review);
+            sanctions_hits.push((name, hits));
+        }
+    }
+    let action = screening.action;
+
+    if action == RuleAction::Block {
+        log::warn!(
+            "blocked {} of {} from {} to {}",
+            payment_type,
+            amount,
+            sender.account_number,
+            recipient_account
+        );
+        return Err(TransferError::Blocked);
+    }
+
+    let held_for_review = action == RuleAction::Review;
+    if held_for_review {
+        sqlx::query("UPDATE users SET held_balance = held_balance + $1 WHERE id = $2")
+            .bind(amount)
+            .bind(sender_id)
+            .execute(&mut *conn)
+            .await?;
+    } else {
+        sqlx::query("UPDATE users SET balance = balance - $1 WHERE id = $2")
+            .bind(amount)
+            .bind(sender_id)
+            .execute(&mut *conn)
+            .await?;
+
+        sqlx::query("UPDATE users SET balance = balance + $1 WHERE id = $2")
+            .bind(amount)
+            .bind(recipient.id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    let status = if held_for_review { "pending_review" } else { "completed" };
+    let (transaction_id, created_at) =
+        insert_transaction(&mut *conn, &sender.account_number, recipient_account, amount, description, status, None).await?;
+
+    if held_for_review {
+        sqlx::query("INSERT INTO payment_reviews (transaction_id, payment_type) VALUES ($1, $2)")
+            .bind(transaction_id)
+            .bind(payment_type)
+            .execute(&mut *conn)
+            .await?;
+    }
+    fraud::record_hits(&mut *conn, transaction_id, &screening.hits).await?;
+    for (name, hits) in &sanctions_hits {
+        sanctions::record_matches(&mut *conn, &sanctions::Subject::Payment { transaction_id }, name, hits).await?;
+    }
+
+    Ok(TransferOutcome {
+        transaction_id,
+        from_account: sender.account_number,
+        to_account: recipient_account.to_string(),
+        amount,
+        sender_balance: sender.available - amount,
+        created_at,
+        held_for_review,
+    })
+}
+
+/// Whether an entry dated `created_at` falls inside a period closed up to
+/// `closed_through`.
+fn in_closed_period(created_at: NaiveDateTime, closed_through: Option<NaiveDateTime>) -> bool {
+    closed_through.is_some_and(|end| created_at < end)
+}
+
+/// Completes a payment held in `pending_review`: moves the held funds to the
+/// recipient and marks it completed. The payment keeps its original
+/// `created_at`, which its receipt signs; the approval time is recorded in
+/// `completed_at`. Fails with [`TransferError::NotHeld`] if the payment was
+/// already decided, and with [`TransferError::PeriodClosed`] if its month has
+/// been closed since, in which case it can only be rejected.
+pub async fn complete_held(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    transaction_id: Uuid,
+    to_account: &str,
+    amount: Decimal,
+) -> Result<TransferOutcome, TransferError> {
+    let created_at = sqlx::query_scalar::<_, NaiveDateTime>(
+        "UPDATE transactions SET status = 'completed', completed_at = NOW() \
+         WHERE id = $1 AND status = 'pending_review' RETURNING created_at"
+    )
+    .bind(transaction_id)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(ledger_error)?
+    .ok_or(TransferError::NotHeld)?;
+
+    let sender = sqlx::query_as::<_, SenderRow>(
+        "SELECT account_number, balance - held_balance AS available, COALESCE(full_name, username) AS display_name \
+         FROM users WHERE id = $1 FOR UPDATE"
+    )
+    .bind(sender_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let recipient = sqlx::query_as::<_, RecipientRow>(
+        "SELECT id, COALESCE(full_name, username) AS display_name FROM users WHERE account_number = $1 FOR UPDATE"
+    )
+        .bind(to_account)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(TransferError::RecipientNotFound)?;
+
+    // Approval posts the payment at its original date, so it must not land
+    // in a month already frozen for either account
+    let closed_through = sqlx::query_scalar::<_, Option<NaiveDateTime>>(
+        "SELECT MAX(period_end) FROM statements WHERE account_number IN ($1, $2)"
+    )
+    .bind(&sender.account_number)
+    .bind(to_account)
+    .fetch_one(&mut *conn)
+    .await?;
+    if in_closed_period(created_at, closed_through) {
+        return Err(TransferError::PeriodClosed);
+    }
+
+    sqlx::query("UPDATE users SET balance = balance - $1, held_balance = held_balance - $1 WHERE id = $2")
+        .bind(amount)
+        .bind(sender_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query("UPDATE users SET balance = balance + $1 WHERE id = $2")
+        .bind(amount)
+        .bind(recipient.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(TransferOutcome {
+        transaction_id,
+        from_account: sender.account_number,
+        to_account: to_account.to_string(),
+        amount,
+        sender_balance: sender.available,
+        created_at,
+        held_for_review: false,
+    })
+}
+
+/// Cancels a payment held in `pending_review`, giving the held funds back to
+/// the sender. Fails with [`TransferError::NotHeld`] if the payment was
+/// already decided.
+pub async fn release_held(
+    conn: &mut PgConnection,
+    sender_id: Uuid,
+    transaction_id: Uuid,
+    amount: Decimal,
+) -> Result<(), TransferError> {
+    let released = sqlx::query("UPDATE transactions SET status = 'rejected' WHERE id = $1 AND status = 'pending_review'")
+        .bind(transaction_id)
+        .execute(&mut *conn)
+        .await?;
+    if released.rows_affected() != 1 {
+        return Err(TransferError::NotHeld);
+    }
+    sqlx::query("UPDATE users SET held_balance = held_balance - $1 WHERE id = $2")
+        .bind(amount)
+        .bind(sender_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn approval_after_period_close_is_refused() {
+        // Held in March, approved after March was closed
+        let march_closed = Some(at(2026, 4, 1, 0));
+        assert!(in_closed_period(at(2026, 3, 31, 23), march_closed));
+        assert!(in_closed_period(at(2026, 3, 2, 9), march_closed));
+    }
+
+    #[test]
+    fn approval_in_open_period_is_allowed() {
+        assert!(!in_closed_period(at(2026, 4, 1, 0), Some(at(2026, 4, 1, 0))));
+        assert!(!in_closed_period(at(2026, 4, 15, 12), Some(at(2026, 4, 1, 0))));
+        assert!(!in_closed_period(at(2026, 3, 2, 9), None));
+    }
+}
@@ -0,0 +1,245 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::PgPool;
+use std::env;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::{ErrorResponse, PeriodStatementResponse};
+use crate::payments;
+
+/// SQLSTATE raised by the `transactions_closed_period` trigger, see db.rs.
+pub const PERIOD_CLOSED_SQLSTATE: &str = "DU001";
+
+const DEFAULT_JOB_INTERVAL_SECS: u64 = 3600;
+/// Wait this long after month end before freezing it, so transfers that
+/// started just before midnight have committed.
+const DEFAULT_CLOSE_GRACE_SECS: i64 = 3600;
+
+#[derive(sqlx::FromRow)]
+struct OpenAccountRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    last_period_end: Option<NaiveDateTime>,
+}
+
+#[derive(sqlx::FromRow)]
+struct LockedAccountRow {
+    account_number: String,
+    balance: Decimal,
+}
+
+#[derive(sqlx::FromRow)]
+struct PeriodTotalsRow {
+    total_debits: Decimal,
+    total_credits: Decimal,
+    transaction_count: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct StatementRow {
+    period: String,
+    account_number: String,
+    period_start: NaiveDateTime,
+    period_end: NaiveDateTime,
+    opening_balance: Decimal,
+    total_debits: Decimal,
+    total_credits: Decimal,
+    closing_balance: Decimal,
+    transaction_count: i32,
+    closed_at: NaiveDateTime,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("day 1 exists in every month")
+}
+
+fn next_month(start: NaiveDate) -> NaiveDate {
+    let (year, month) = if start.month() == 12 { (start.year() + 1, 1) } else { (start.year(), start.month() + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1).expect("first of month is a valid date")
+}
+
+/// Parses a `YYYY-MM` period into the first day of that month.
+pub fn parse_period(value: &str) -> Option<NaiveDate> {
+    let (year, month) = value.split_once('-')?;
+    if year.len() != 4 || month.len() != 2 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)
+}
+
+async fn period_totals(
+    conn: &mut sqlx::PgConnection,
+    account: &str,
+    start: NaiveDateTime,
+    end: Option<NaiveDateTime>,
+) -> Result<PeriodTotalsRow, sqlx::Error> {
+    sqlx::query_as::<_, PeriodTotalsRow>(
+        "SELECT COALESCE(SUM(CASE WHEN from_account = $1 AND to_account <> $1 THEN amount ELSE 0 END), 0) AS total_debits, \
+                COALESCE(SUM(CASE WHEN to_account = $1 AND from_account <> $1 THEN amount ELSE 0 END), 0) AS total_credits, \
+                COUNT(*) AS transaction_count \
+         FROM transactions \
+         WHERE (from_account = $1 OR to_account = $1) AND status = 'completed' \
+           AND created_at >= $2 AND ($3::TIMESTAMP IS NULL OR created_at < $3)"
+    )
+    .bind(account)
+    .bind(start)
+    .bind(end)
+    .fetch_one(conn)
+    .await
+}
+
+/// Closes one calendar month for a user and freezes it. The account row is
+/// locked so no transfer can land while the balances are worked out.
+async fn close_period(pool: &PgPool, user_id: Uuid, month: NaiveDate) -> Result<(), sqlx::Error> {
+    let start = month.and_hms_opt(0, 0, 0).expect("midnight is valid");
+    let end = next_month(month).and_hms_opt(0, 0, 0).expect("midnight is valid");
+
+    let mut tx = pool.begin().await?;
+
+    let account = sqlx::query_as::<_, LockedAccountRow>(
+        "SELECT account_number, balance FROM users WHERE id = $1 FOR UPDATE"
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let previous_closing = sqlx::query_scalar::<_, Decimal>(
+        "SELECT closing_balance FROM statements WHERE user_id = $1 AND period_end = $2"
+    )
+    .bind(user_id)
+    .bind(start)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let opening_balance = match previous_closing {
+        Some(closing) => closing,
+        None => {
+            // First statement: walk back from the live balance
+            let since = period_totals(&mut tx, &account.account_number, start, None).await?;
+            account.balance - since.total_credits + since.total_debits
+        }
+    };
+
+    let totals = period_totals(&mut tx, &account.account_number, start, Some(end)).await?;
+    let closing_balance = opening_balance + totals.total_credits - totals.total_debits;
+
+    sqlx::query(
+        "INSERT INTO statements (id, user_id, account_number, period, period_start, period_end, opening_balance, total_debits, total_credits, closing_balance, transaction_count) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+         ON CONFLICT (user_id, period) DO NOTHING"
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(&account.account_number)
+    .bind(month.format("%Y-%m").to_string())
+    .bind(start)
+    .bind(end)
+    .bind(opening_balance)
+    .bind(totals.total_debits)
+    .bind(totals.total_credits)
+    .bind(closing_balance)
+    .bind(totals.transaction_count as i32)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+/// Closes every month that ended more than the grace period ago and has no
+/// statement yet. Returns the number of statements written.
+pub async fn close_due_periods(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let grace = Duration::seconds(env_or("STATEMENT_CLOSE_GRACE_SECS", DEFAULT_CLOSE_GRACE_SECS));
+    let cutoff = Utc::now().naive_utc() - grace;
+
+    let accounts = sqlx::query_as::<_, OpenAccountRow>(
+        "SELECT u.id, u.created_at, MAX(s.period_end) AS last_period_end \
+         FROM users u LEFT JOIN statements s ON s.user_id = u.id \
+         GROUP BY u.id, u.created_at"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut closed = 0;
+    for account in accounts {
+        let mut month = first_of_month(account.last_period_end.unwrap_or(account.created_at).date());
+        while next_month(month).and_hms_opt(0, 0, 0).expect("midnight is valid") <= cutoff {
+            close_period(pool, account.id, month).await?;
+            closed += 1;
+            month = next_month(month);
+        }
+    }
+    Ok(closed)
+}
+
+/// Runs [`close_due_periods`] in the background every
+/// `STATEMENT_JOB_INTERVAL_SECS`.
+pub fn spawn_job(pool: PgPool) {
+    let every = std::time::Duration::from_secs(env_or("STATEMENT_JOB_INTERVAL_SECS", DEFAULT_JOB_INTERVAL_SECS));
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            match close_due_periods(&pool).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("closed {} monthly statement(s)", n),
+                Err(e) => log::error!("monthly statement job failed: {}", e),
+            }
+        }
+    });
+}
+
+/// `GET /api/statements/{period}` with `period` as `YYYY-MM`. Serves the
+/// stored month-end statement rather than recomputing it.
+pub async fn get_period_statement(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let period = path.into_inner();
+    if parse_period(&period).is_none() {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_period".to_string(),
+            message: "Period must be formatted as YYYY-MM".to_string(),
+        });
+    }
+
+    let row = match sqlx::query_as::<_, StatementRow>(
+        "SELECT period, account_number, period_start, period_end, opening_balance, total_debits, total_credits, closing_balance, transaction_count, closed_at \
+         FROM statements WHERE user_id = $1 AND period = $2"
+    )
+    .bind(user_id)
+    .bind(&period)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(Some(row)) => row,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse {
+            error: "statement_not_found".to_string(),
+            message: "No closed statement exists for this period".to_string(),
+        }),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    HttpResponse::Ok().json(PeriodStatementResponse {
+        period: row.period,
+        account_number: row.account_number,
+        currency: "USD".to_string(),
+        period_start: row.period_start.and_utc().to_rfc3339(),
+        period_end: row.period_end.and_utc().to_rfc3339(),
+        opening_balance: payments::decimal_to_f64(row.opening_balance),
+        total_debits: payments::decimal_to_f64(row.total_debits),
+        total_credits: payments::decimal_to_f64(row.total_credits),
+        closing_balance: payments::decimal_to_f64(row.closing_balance),
+        transaction_count: row.transaction_count,
+        closed_at: row.closed_at.and_utc().to_rfc3339(),
+    })
+}
@@ -0,0 +1,413 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::emvco;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments::{self, TransferError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Prefix identifying a server-signed DeltaUp QR payload.
+const PAYLOAD_PREFIX: &str = "DUP1";
+const ONE_TIME_DEFAULT_EXPIRY_MINUTES: i64 = 15;
+const STATIC_DEFAULT_EXPIRY_MINUTES: i64 = 365 * 24 * 60;
+const MAX_EXPIRY_MINUTES: i64 = 365 * 24 * 60;
+const MAX_REFERENCE_LEN: usize = 140;
+
+#[derive(Debug, thiserror::Error)]
+pub enum QrError {
+    #[error("QR code is not a valid DeltaUp payment code")]
+    Malformed,
+    #[error("QR code signature is invalid")]
+    BadSignature,
+    #[error("QR code has expired")]
+    Expired,
+    #[error("QR code is no longer valid")]
+    Revoked,
+    #[error("QR code has already been used")]
+    AlreadyUsed,
+    #[error("Amount must be greater than 0")]
+    InvalidAmount,
+    #[error("Amount is required for this QR code")]
+    AmountRequired,
+    #[error("Amount does not match the amount fixed by this QR code")]
+    AmountMismatch,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl QrError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            QrError::Malformed | QrError::BadSignature => "invalid_qr",
+            QrError::Expired => "qr_expired",
+            QrError::Revoked => "qr_revoked",
+            QrError::AlreadyUsed => "qr_already_used",
+            QrError::InvalidAmount | QrError::AmountRequired | QrError::AmountMismatch => "invalid_amount",
+            QrError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        match self {
+            QrError::Database(_) => HttpResponse::InternalServerError().finish(),
+            QrError::AlreadyUsed => HttpResponse::Conflict().json(ErrorResponse {
+                error: self.code().to_string(),
+                message: self.to_string(),
+            }),
+            _ => HttpResponse::BadRequest().json(ErrorResponse {
+                error: self.code().to_string(),
+                message: self.to_string(),
+            }),
+        }
+    }
+}
+
+/// Claims carried inside a signed QR code. The `id` doubles as the nonce and
+/// as the key of the matching `qr_codes` row.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QrPayload {
+    pub id: Uuid,
+    pub account: String,
+    pub amount: Option<String>,
+    pub reference: Option<String>,
+    pub exp: i64,
+    pub one_time: bool,
+}
+
+impl QrPayload {
+    pub fn fixed_amount(&self) -> Result<Option<Decimal>, QrError> {
+        self.amount
+            .as_deref()
+            .map(|a| Decimal::from_str(a).map_err(|_| QrError::Malformed))
+            .transpose()
+    }
+}
+
+fn signing_key() -> Vec<u8> {
+    env::var("QR_SIGNING_SECRET")
+        .or_else(|_| env::var("JWT_SECRET"))
+        .unwrap_or_else(|_| "your-secret-key".to_string())
+        .into_bytes()
+}
+
+fn mac_for(message: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(&signing_key()).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac
+}
+
+/// Encodes the payload as `DUP1.<base64url json>.<base64url hmac-sha256>`.
+pub fn sign_payload(payload: &QrPayload) -> String {
+    let body = URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload).expect("payload serializes"));
+    let signed = format!("{}.{}", PAYLOAD_PREFIX, body);
+    let signature = URL_SAFE_NO_PAD.encode(mac_for(signed.as_bytes()).finalize().into_bytes());
+    format!("{}.{}", signed, signature)
+}
+
+/// Checks the signature and expiry of a QR string and returns its claims.
+pub fn verify_payload(qr_data: &str) -> Result<QrPayload, QrError> {
+    let qr_data = qr_data.trim();
+    let (signed, signature) = qr_data.rsplit_once('.').ok_or(QrError::Malformed)?;
+    let (prefix, body) = signed.split_once('.').ok_or(QrError::Malformed)?;
+    if prefix != PAYLOAD_PREFIX {
+        return Err(QrError::Malformed);
+    }
+
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| QrError::Malformed)?;
+    mac_for(signed.as_bytes())
+        .verify_slice(&signature)
+        .map_err(|_| QrError::BadSignature)?;
+
+    let body = URL_SAFE_NO_PAD.decode(body).map_err(|_| QrError::Malformed)?;
+    let payload: QrPayload = serde_json::from_slice(&body).map_err(|_| QrError::Malformed)?;
+
+    if payload.exp < Utc::now().timestamp() {
+        return Err(QrError::Expired);
+    }
+
+    Ok(payload)
+}
+
+/// Works out the amount to charge for a verified code: the fixed amount when
+/// the code carries one, otherwise the amount the payer entered.
+pub fn resolve_amount(payload: &QrPayload, payer_amount: Option<f64>) -> Result<Decimal, QrError> {
+    let payer_amount = payer_amount.map(|a| payments::parse_amount(a).ok_or(QrError::InvalidAmount)).transpose()?;
+    match (payload.fixed_amount()?, payer_amount) {
+        (Some(fixed), Some(entered)) if fixed != entered => Err(QrError::AmountMismatch),
+        (Some(fixed), _) => Ok(fixed),
+        (None, Some(entered)) => Ok(entered),
+        (None, None) => Err(QrError::AmountRequired),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct QrCodeStateRow {
+    revoked_at: Option<chrono::NaiveDateTime>,
+    used_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Locks the stored code for the duration of the payment transaction and
+/// rejects codes that were revoked or, for one-time codes, already redeemed.
+pub async fn claim_code(conn: &mut PgConnection, payload: &QrPayload) -> Result<(), QrError> {
+    let state = sqlx::query_as::<_, QrCodeStateRow>(
+        "SELECT revoked_at, used_at FROM qr_codes WHERE id = $1 AND account_number = $2 FOR UPDATE"
+    )
+    .bind(payload.id)
+    .bind(&payload.account)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(QrError::Revoked)?;
+
+    if state.revoked_at.is_some() {
+        return Err(QrError::Revoked);
+    }
+    if payload.one_time && state.used_at.is_some() {
+        return Err(QrError::AlreadyUsed);
+    }
+    Ok(())
+}
+
+pub async fn mark_used(conn: &mut PgConnection, payload: &QrPayload, transaction_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE qr_codes SET used_at = NOW(), last_transaction_id = $1 WHERE id = $2")
+        .bind(transaction_id)
+        .bind(payload.id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn generate(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<QRGenerateRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let amount = match body.amount {
+        Some(a) => match payments::parse_amount(a) {
+            Some(a) => Some(a),
+            None => return TransferError::InvalidAmount.to_response(),
+        },
+        None => None,
+    };
+
+    let reference = body.reference.as_deref().map(str::trim).filter(|r| !r.is_empty()).map(str::to_string);
+    if reference.as_ref().is_some_and(|r| r.len() > MAX_REFERENCE_LEN) {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_reference".to_string(),
+            message: format!("Reference must be at most {} characters", MAX_REFERENCE_LEN),
+        });
+    }
+
+    // Codes with a fixed amount are single-use unless the merchant asks otherwise
+    let one_time = body.one_time.unwrap_or(amount.is_some());
+    let expires_in = body.expires_in_minutes.unwrap_or(if one_time {
+        ONE_TIME_DEFAULT_EXPIRY_MINUTES
+    } else {
+        STATIC_DEFAULT_EXPIRY_MINUTES
+    });
+    if !(1..=MAX_EXPIRY_MINUTES).contains(&expires_in) {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_expiry".to_string(),
+            message: format!("expires_in_minutes must be between 1 and {}", MAX_EXPIRY_MINUTES),
+        });
+    }
+
+    let account_number = match sqlx::query_scalar::<_, String>(
+        "SELECT account_number FROM users WHERE id = $1"
+    )
+    .bind(user_id)
+    .fetch_one(pool.get_ref())
+    .await {
+        Ok(a) => a,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    let expires_at = Utc::now() + Duration::minutes(expires_in);
+    let payload = QrPayload {
+        id: Uuid::new_v4(),
+        account: account_number.clone(),
+        amount: amount.map(|a| a.to_string()),
+        reference: reference.clone(),
+        exp: expires_at.timestamp(),
+        one_time,
+    };
+    let qr_data = sign_payload(&payload);
+
+    if sqlx::query(
+        "INSERT INTO qr_codes (id, user_id, account_number, amount, reference, one_time, payload, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+    )
+    .bind(payload.id)
+    .bind(user_id)
+    .bind(&account_number)
+    .bind(amount)
+    .bind(&reference)
+    .bind(one_time)
+    .bind(&qr_data)
+    .bind(expires_at.naive_utc())
+    .execute(pool.get_ref())
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Created().json(QRCodeResponse {
+        id: payload.id.to_string(),
+        qr_data,
+        account_number,
+        amount: amount.map(payments::decimal_to_f64),
+        reference,
+        one_time,
+        expires_at: expires_at.to_rfc3339(),
+    })
+}
+
+#[derive(sqlx::FromRow)]
+struct MerchantRow {
+    username: String,
+    account_number: String,
+}
+
+/// Issues an EMVCo merchant-presented string for the caller's account so
+/// partner terminals and third-party wallets can print or read it.
+pub async fn generate_emvco(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<EmvQRGenerateRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let amount = match body.amount {
+        Some(a) => match payments::parse_amount(a) {
+            Some(a) => Some(a),
+            None => return TransferError::InvalidAmount.to_response(),
+        },
+        None => None,
+    };
+    let reference = body.reference.as_deref().map(str::trim).filter(|r| !r.is_empty());
+
+    let merchant = match sqlx::query_as::<_, MerchantRow>(
+        "SELECT username, account_number FROM users WHERE id = $1"
+    )
+    .bind(user_id)
+    .fetch_one(pool.get_ref())
+    .await {
+        Ok(m) => m,
+        Err(_) => return HttpResponse::NotFound().finish(),
+    };
+
+    let city = env::var("EMVCO_MERCHANT_CITY").unwrap_or_else(|_| "ONLINE".to_string());
+    let mcc = env::var("EMVCO_MCC").unwrap_or_else(|_| "5999".to_string());
+    let qr_data = emvco::generate(&merchant.account_number, &merchant.username, &city, &mcc, amount, reference);
+
+    HttpResponse::Ok().json(EmvQRResponse {
+        qr_data,
+        account_number: merchant.account_number,
+        amount: amount.map(payments::decimal_to_f64),
+        reference: reference.map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(amount: Option<&str>, exp: i64) -> QrPayload {
+        QrPayload {
+            id: Uuid::new_v4(),
+            account: "123456789092".to_string(),
+            amount: amount.map(str::to_string),
+            reference: Some("INV-42".to_string()),
+            exp,
+            one_time: true,
+        }
+    }
+
+    fn in_an_hour() -> i64 {
+        (Utc::now() + Duration::hours(1)).timestamp()
+    }
+
+    #[test]
+    fn signed_payloads_verify() {
+        let original = payload(Some("12.50"), in_an_hour());
+        let qr_data = sign_payload(&original);
+        assert!(qr_data.starts_with("DUP1."));
+
+        let verified = verify_payload(&qr_data).unwrap();
+        assert_eq!(verified.id, original.id);
+        assert_eq!(verified.account, original.account);
+        assert_eq!(verified.amount.as_deref(), Some("12.50"));
+        assert_eq!(verified.reference.as_deref(), Some("INV-42"));
+        assert_eq!(verified.exp, original.exp);
+        assert!(verified.one_time);
+    }
+
+    #[test]
+    fn tampering_breaks_the_signature() {
+        let qr_data = sign_payload(&payload(Some("12.50"), in_an_hour()));
+        let (signed, signature) = qr_data.rsplit_once('.').unwrap();
+
+        // Same signature over a body asking for a larger amount
+        let forged_body = sign_payload(&payload(Some("1250.00"), in_an_hour()));
+        let (forged_signed, _) = forged_body.rsplit_once('.').unwrap();
+        assert!(matches!(
+            verify_payload(&format!("{}.{}", forged_signed, signature)),
+            Err(QrError::BadSignature)
+        ));
+
+        let mut bad_signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        bad_signature[0] ^= 1;
+        assert!(matches!(
+            verify_payload(&format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(bad_signature))),
+            Err(QrError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let qr_data = sign_payload(&payload(None, in_an_hour()));
+        let other_prefix = qr_data.replacen(PAYLOAD_PREFIX, "DUP2", 1);
+        assert!(matches!(verify_payload(&other_prefix), Err(QrError::Malformed)));
+        assert!(matches!(verify_payload("123456789092"), Err(QrError::Malformed)));
+    }
+
+    #[test]
+    fn rejects_expired_codes() {
+        let qr_data = sign_payload(&payload(None, Utc::now().timestamp() - 1));
+        assert!(matches!(verify_payload(&qr_data), Err(QrError::Expired)));
+    }
+
+    #[test]
+    fn fixed_amounts_must_match() {
+        let fixed = payload(Some("12.50"), in_an_hour());
+        assert_eq!(resolve_amount(&fixed, None).unwrap(), Decimal::from_str("12.50").unwrap());
+        assert_eq!(resolve_amount(&fixed, Some(12.5)).unwrap(), Decimal::from_str("12.50").unwrap());
+        assert!(matches!(resolve_amount(&fixed, Some(12.51)), Err(QrError::AmountMismatch)));
+    }
+
+    #[test]
+    fn static_codes_take_the_payer_amount() {
+        let open = payload(None, in_an_hour());
+        assert_eq!(resolve_amount(&open, Some(7.25)).unwrap(), Decimal::from_str("7.25").unwrap());
+        assert!(matches!(resolve_amount(&open, None), Err(QrError::AmountRequired)));
+        assert!(matches!(resolve_amount(&open, Some(0.0)), Err(QrError::InvalidAmount)));
+    }
+}
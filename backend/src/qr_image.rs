@@ -0,0 +1,203 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{NaiveDateTime, Utc};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::env;
+use std::io::Cursor;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::ErrorResponse;
+
+const DEFAULT_SIZE: u32 = 512;
+const MIN_SIZE: u32 = 128;
+const MAX_SIZE: u32 = 2048;
+/// Quiet zone width, in modules, required around a QR symbol.
+const QUIET_ZONE: u32 = 4;
+/// Fraction of the symbol width a centred logo may cover. Kept well under
+/// the ~30% that error-correction level H can recover.
+const LOGO_RATIO: f32 = 0.2;
+
+#[derive(Deserialize)]
+pub struct RenderQuery {
+    pub size: Option<u32>,
+    pub ecc: Option<String>,
+    pub logo: Option<bool>,
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredCodeRow {
+    payload: String,
+    expires_at: NaiveDateTime,
+    revoked_at: Option<NaiveDateTime>,
+}
+
+fn bad_request(error: &str, message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        error: error.to_string(),
+        message: message.to_string(),
+    })
+}
+
+fn parse_ec_level(value: &str) -> Option<EcLevel> {
+    match value.to_ascii_uppercase().as_str() {
+        "L" => Some(EcLevel::L),
+        "M" => Some(EcLevel::M),
+        "Q" => Some(EcLevel::Q),
+        "H" => Some(EcLevel::H),
+        _ => None,
+    }
+}
+
+/// Loads the merchant logo configured through `QR_LOGO_PATH`.
+fn load_logo() -> Option<(Vec<u8>, DynamicImage)> {
+    let path = env::var("QR_LOGO_PATH").ok()?;
+    let bytes = std::fs::read(path).ok()?;
+    let decoded = image::load_from_memory_with_format(&bytes, ImageFormat::Png).ok()?;
+    Some((bytes, decoded))
+}
+
+/// Pixel size of one module so the whole symbol, quiet zone included, fits
+/// in `size` pixels.
+fn module_size(code: &QrCode, size: u32) -> u32 {
+    (size / (code.width() as u32 + 2 * QUIET_ZONE)).max(1)
+}
+
+fn render_png(code: &QrCode, size: u32, logo: Option<&DynamicImage>) -> Result<Vec<u8>, image::ImageError> {
+    let module = module_size(code, size);
+    let mut img: RgbaImage = code
+        .render::<Rgba<u8>>()
+        .module_dimensions(module, module)
+        .build();
+
+    if let Some(logo) = logo {
+        let side = (img.width() as f32 * LOGO_RATIO) as u32;
+        let logo = imageops::resize(logo, side, side, FilterType::Lanczos3);
+        let pad = module;
+        let backing = RgbaImage::from_pixel(side + 2 * pad, side + 2 * pad, Rgba([255, 255, 255, 255]));
+        let x = (img.width() - backing.width()) / 2;
+        let y = (img.height() - backing.height()) / 2;
+        imageops::overlay(&mut img, &backing, x as i64, y as i64);
+        imageops::overlay(&mut img, &logo, (x + pad) as i64, (y + pad) as i64);
+    }
+
+    let mut out = Vec::new();
+    DynamicImage::ImageRgba8(img).write_to(&mut Cursor::new(&mut out), ImageFormat::Png)?;
+    Ok(out)
+}
+
+fn render_svg(code: &QrCode, size: u32, logo_png: Option<&[u8]>) -> String {
+    let module = module_size(code, size);
+    let mut out = code
+        .render::<svg::Color>()
+        .module_dimensions(module, module)
+        .build();
+
+    if let Some(logo_png) = logo_png {
+        let total = (code.width() as u32 + 2 * QUIET_ZONE) * module;
+        let side = (total as f32 * LOGO_RATIO) as u32;
+        let backing = side + 2 * module;
+        let offset = (total - backing) / 2;
+        let overlay = format!(
+            r##"<rect x="{o}" y="{o}" width="{b}" height="{b}" fill="#fff"/><image x="{i}" y="{i}" width="{s}" height="{s}" href="data:image/png;base64,{data}"/>"##,
+            o = offset,
+            b = backing,
+            i = offset + module,
+            s = side,
+            data = STANDARD.encode(logo_png),
+        );
+        if let Some(end) = out.rfind("</svg>") {
+            out.insert_str(end, &overlay);
+        }
+    }
+
+    out
+}
+
+/// Serves a stored signed QR code as `GET /api/qr/{id}.png` or `.svg` so
+/// merchants can print it without a client-side encoder.
+pub async fn render(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<(Uuid, String)>,
+    query: web::Query<RenderQuery>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    // The route only matches png or svg, see main.rs
+    let (code_id, format) = path.into_inner();
+
+    let size = query.size.unwrap_or(DEFAULT_SIZE);
+    if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+        return bad_request("invalid_size", &format!("Size must be between {} and {}", MIN_SIZE, MAX_SIZE));
+    }
+
+    let mut ec_level = match query.ecc.as_deref().map(parse_ec_level) {
+        Some(Some(level)) => level,
+        Some(None) => return bad_request("invalid_ecc", "Error correction level must be L, M, Q or H"),
+        None => EcLevel::M,
+    };
+
+    let logo = if query.logo.unwrap_or(false) {
+        match load_logo() {
+            Some(logo) => {
+                // The logo hides modules, so only the highest level can recover them
+                ec_level = EcLevel::H;
+                Some(logo)
+            }
+            None => return bad_request("logo_unavailable", "No QR logo is configured"),
+        }
+    } else {
+        None
+    };
+
+    let stored = match sqlx::query_as::<_, StoredCodeRow>(
+        "SELECT payload, expires_at, revoked_at FROM qr_codes WHERE id = $1 AND user_id = $2"
+    )
+    .bind(code_id)
+    .bind(user_id)
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(Some(row)) => row,
+        Ok(None) => return HttpResponse::NotFound().json(ErrorResponse {
+            error: "qr_not_found".to_string(),
+            message: "QR code not found".to_string(),
+        }),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if stored.revoked_at.is_some() || stored.expires_at < Utc::now().naive_utc() {
+        return HttpResponse::Gone().json(ErrorResponse {
+            error: "qr_expired".to_string(),
+            message: "QR code has expired or was revoked".to_string(),
+        });
+    }
+
+    let code = match QrCode::with_error_correction_level(stored.payload.as_bytes(), ec_level) {
+        Ok(c) => c,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if format == "png" {
+        match render_png(&code, size, logo.as_ref().map(|(_, img)| img)) {
+            Ok(bytes) => HttpResponse::Ok()
+                .content_type("image/png")
+                .insert_header(("Cache-Control", "private, max-age=300"))
+                .body(bytes),
+            Err(_) => HttpResponse::InternalServerError().finish(),
+        }
+    } else {
+        HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .insert_header(("Cache-Control", "private, max-age=300"))
+            .body(render_svg(&code, size, logo.as_ref().map(|(bytes, _)| bytes.as_slice())))
+    }
+}
@@ -0,0 +1,712 @@
+//! External payment rails: deposits pulled from and withdrawals pushed to a
+//! customer's account at another bank. A transfer is `pending` until the rail
+//! reports it `settled` or `failed`; a settled transfer can still come back
+//! as `returned`. Rails report status by signed callback to
+//! `POST /api/rails/{rail}/events`.
+//!
+//! Withdrawals are debited when requested and put back if they fail or are
+//! returned. Deposits are credited only once they settle, and debited again
+//! if they are returned. Withdrawals count towards the customer's outgoing
+//! tier limits and deposits, from the moment they are requested, towards
+//! the inbound ones (see [`limits::check_inbound`]). The other side of every
+//! posting is the external clearing house account.
+//!
+//! A deposit can be returned after its funds were spent. The return is
+//! still posted in full, leaving the account overdrawn; the customer is told
+//! how much they owe and the shortfall is logged for operations.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{Duration, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sha2::Sha256;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::sync::OnceLock;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments::{self, TransferError};
+use crate::{ach, house, limits, notifications, sanctions};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const DEPOSIT: &str = "deposit";
+pub const WITHDRAWAL: &str = "withdrawal";
+
+const MAX_HOLDER_LEN: usize = 22;
+const MAX_LISTED: i64 = 100;
+const SIGNATURE_HEADER: &str = "X-Rail-Signature";
+const DEFAULT_MOCK_INTERVAL_SECS: u64 = 15;
+const DEFAULT_MOCK_SETTLE_SECS: i64 = 30;
+
+/// NACHA return reason codes the rails report.
+pub const RETURN_CODES: [(&str, &str); 20] = [
+    ("R01", "Insufficient funds"),
+    ("R02", "Account closed"),
+    ("R03", "No account or unable to locate account"),
+    ("R04", "Invalid account number"),
+    ("R05", "Unauthorized debit to consumer account"),
+    ("R06", "Returned per originator's request"),
+    ("R07", "Authorization revoked by customer"),
+    ("R08", "Payment stopped"),
+    ("R09", "Uncollected funds"),
+    ("R10", "Customer advises not authorized"),
+    ("R11", "Customer advises entry not in accordance with the terms of the authorization"),
+    ("R12", "Account sold to another financial institution"),
+    ("R14", "Representative payee deceased"),
+    ("R15", "Beneficiary or account holder deceased"),
+    ("R16", "Account frozen"),
+    ("R17", "File record edit criteria"),
+    ("R20", "Non-transaction account"),
+    ("R23", "Credit entry refused by receiver"),
+    ("R24", "Duplicate entry"),
+    ("R29", "Corporate customer advises not authorized"),
+];
+
+pub fn return_reason(code: &str) -> Option<&'static str> {
+    RETURN_CODES.iter().find(|(c, _)| *c == code).map(|(_, reason)| *reason)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RailError {
+    #[error("Routing number is not valid")]
+    InvalidRoutingNumber,
+    #[error("Bank account number must be 4 to 17 digits")]
+    InvalidAccountNumber,
+    #[error("Account holder name must be 1 to {} characters", MAX_HOLDER_LEN)]
+    InvalidHolder,
+    #[error("The rail refused the transfer: {0}")]
+    Rejected(String),
+    #[error("The account holder could not be cleared automatically; our compliance team will contact you")]
+    UnderReview,
+    #[error("Deposits and withdrawals are not available")]
+    Unavailable,
+    #[error("Callback signature is invalid")]
+    BadSignature,
+    #[error("No external transfer with this reference")]
+    NotFound,
+    #[error("Cannot move a {from} transfer to {to}")]
+    InvalidTransition { from: String, to: String },
+    #[error("Unknown return code {0}")]
+    UnknownReturnCode(String),
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl RailError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RailError::InvalidRoutingNumber => "invalid_routing_number",
+            RailError::InvalidAccountNumber => "invalid_bank_account",
+            RailError::InvalidHolder => "invalid_account_holder",
+            RailError::Rejected(_) => "rail_rejected",
+            RailError::UnderReview => "transfer_under_review",
+            RailError::Unavailable => "rails_unavailable",
+            RailError::BadSignature => "bad_signature",
+            RailError::NotFound => "external_transfer_not_found",
+            RailError::InvalidTransition { .. } => "invalid_status_transition",
+            RailError::UnknownReturnCode(_) => "unknown_return_code",
+            RailError::Transfer(e) => e.code(),
+            RailError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let body = ErrorResponse {
+            error: self.code().to_string(),
+            message: self.to_string(),
+        };
+        match self {
+            RailError::Database(_) => HttpResponse::InternalServerError().finish(),
+            RailError::Transfer(e) => e.to_response(),
+            RailError::BadSignature => HttpResponse::Unauthorized().json(body),
+            RailError::NotFound => HttpResponse::NotFound().json(body),
+            RailError::UnderReview => HttpResponse::Forbidden().json(body),
+            RailError::Unavailable => HttpResponse::ServiceUnavailable().json(body),
+            RailError::InvalidTransition { .. } => HttpResponse::Conflict().json(body),
+            RailError::Rejected(_) => HttpResponse::UnprocessableEntity().json(body),
+            RailError::InvalidRoutingNumber
+            | RailError::InvalidAccountNumber
+            | RailError::InvalidHolder
+            | RailError::UnknownReturnCode(_) => HttpResponse::BadRequest().json(body),
+        }
+    }
+}
+
+/// A deposit or withdrawal as handed to a rail.
+pub struct RailTransfer<'a> {
+    pub id: Uuid,
+    pub direction: &'a str,
+    pub amount: Decimal,
+    pub routing_number: &'a str,
+    pub account_number: &'a str,
+    pub account_holder: &'a str,
+}
+
+/// A connection to an external payment network.
+pub trait PaymentRail: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Hands a transfer to the rail and returns the rail's reference for it.
+    /// The outcome is reported later through a status callback.
+    fn submit(&self, transfer: &RailTransfer) -> Result<String, RailError>;
+}
+
+/// Simulated bank gateway for development; it only runs with
+/// `MOCK_RAIL_ENABLED=true`. It accepts every well-formed transfer and
+/// settles it after `MOCK_RAIL_SETTLE_SECS`, except for these external account
+/// endings: `0000` fails with R03, `1111` fails with R01 and `2222` settles
+/// and is then returned with R02.
+pub struct MockRail;
+
+impl MockRail {
+    pub const NAME: &'static str = "mock";
+
+    /// What happens to a pending transfer to `account_number`.
+    fn outcome(account_number: &str) -> RailEvent {
+        let (status, return_code) = if account_number.ends_with("0000") {
+            ("failed", Some("R03"))
+        } else if account_number.ends_with("1111") {
+            ("failed", Some("R01"))
+        } else {
+            ("settled", None)
+        };
+        RailEvent {
+            reference: String::new(),
+            status: status.to_string(),
+            return_code: return_code.map(str::to_string),
+        }
+    }
+}
+
+impl PaymentRail for MockRail {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn submit(&self, transfer: &RailTransfer) -> Result<String, RailError> {
+        if transfer.account_number.chars().all(|c| c == '0') {
+            return Err(RailError::Rejected("account number is all zeros".to_string()));
+        }
+        let reference = format!("MOCK-{}", &transfer.id.simple().to_string()[..16].to_uppercase());
+        log::debug!(
+            "mock rail accepted {} {} of {} for {} at routing {}",
+            transfer.direction,
+            reference,
+            transfer.amount,
+            transfer.account_holder,
+            transfer.routing_number
+        );
+        Ok(reference)
+    }
+}
+
+struct ActiveRail {
+    rail: Box<dyn PaymentRail>,
+    webhook_key: Vec<u8>,
+}
+
+static RAIL: OnceLock<Option<ActiveRail>> = OnceLock::new();
+
+/// Selects the rail named by `PAYMENT_RAIL`, `ach` or `mock`, and loads the
+/// callback key from `RAIL_WEBHOOK_SECRET`. Called once at startup so a
+/// misconfigured rail stops the server; the mock rail moves money without a
+/// bank and must be enabled explicitly. Without `PAYMENT_RAIL` deposits and
+/// withdrawals are unavailable.
+pub fn init() -> Result<Option<&'static str>, String> {
+    let active = match env::var("PAYMENT_RAIL").ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) {
+        None => None,
+        Some(name) => {
+            let rail: Box<dyn PaymentRail> = match name.as_str() {
+                MockRail::NAME if env::var("MOCK_RAIL_ENABLED").is_ok_and(|v| v.trim() == "true") => Box::new(MockRail),
+                MockRail::NAME => {
+                    return Err("the mock rail settles transfers without a bank; set MOCK_RAIL_ENABLED=true to use it".to_string())
+                }
+                ach::AchRail::NAME => Box::new(ach::AchRail),
+                other => return Err(format!("unknown PAYMENT_RAIL {}", other)),
+            };
+            let webhook_key = env::var("RAIL_WEBHOOK_SECRET")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .ok_or("RAIL_WEBHOOK_SECRET must be set to use a payment rail")?
+                .into_bytes();
+            Some(ActiveRail { rail, webhook_key })
+        }
+    };
+    let name = active.as_ref().map(|a| a.rail.name());
+    RAIL.set(active).map_err(|_| "payment rail already configured".to_string())?;
+    Ok(name)
+}
+
+fn active() -> Option<&'static ActiveRail> {
+    RAIL.get().and_then(Option::as_ref)
+}
+
+/// The rail chosen by [`init`], if any.
+pub fn rail() -> Option<&'static dyn PaymentRail> {
+    active().map(|a| a.rail.as_ref())
+}
+
+/// Checks the ABA routing number check digit.
+pub fn routing_number_valid(routing: &str) -> bool {
+    if routing.len() != 9 || !routing.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let sum: u32 = routing
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .zip([3, 7, 1].iter().cycle())
+        .map(|(d, w)| d * w)
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+struct BankAccount {
+    routing_number: String,
+    account_number: String,
+    account_holder: String,
+}
+
+fn validate_bank_account(body: &ExternalTransferRequest) -> Result<BankAccount, RailError> {
+    let routing_number = body.routing_number.trim();
+    if !routing_number_valid(routing_number) {
+        return Err(RailError::InvalidRoutingNumber);
+    }
+    let account_number = body.account_number.trim();
+    if !(4..=17).contains(&account_number.len()) || !account_number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(RailError::InvalidAccountNumber);
+    }
+    let account_holder = body.account_holder.trim();
+    if account_holder.is_empty() || account_holder.chars().count() > MAX_HOLDER_LEN || !account_holder.is_ascii() {
+        return Err(RailError::InvalidHolder);
+    }
+    Ok(BankAccount {
+        routing_number: routing_number.to_string(),
+        account_number: account_number.to_string(),
+        account_holder: account_holder.to_string(),
+    })
+}
+
+#[derive(sqlx::FromRow)]
+struct ExternalTransferRow {
+    id: Uuid,
+    user_id: Uuid,
+    direction: String,
+    amount: Decimal,
+    status: String,
+    rail: String,
+    rail_reference: Option<String>,
+    routing_number: String,
+    external_account: String,
+    return_code: Option<String>,
+    transaction_id: Option<Uuid>,
+    created_at: NaiveDateTime,
+    settled_at: Option<NaiveDateTime>,
+}
+
+const TRANSFER_COLUMNS: &str = "id, user_id, direction, amount, status, rail, rail_reference, routing_number, \
+     external_account, return_code, transaction_id, created_at, settled_at";
+
+impl From<ExternalTransferRow> for ExternalTransferView {
+    fn from(row: ExternalTransferRow) -> Self {
+        ExternalTransferView {
+            id: row.id.to_string(),
+            direction: row.direction,
+            amount: payments::decimal_to_f64(row.amount),
+            status: row.status,
+            rail: row.rail,
+            rail_reference: row.rail_reference,
+            routing_number: row.routing_number,
+            account_last4: row.external_account[row.external_account.len().saturating_sub(4)..].to_string(),
+            return_reason: row.return_code.as_deref().and_then(return_reason).map(str::to_string),
+            return_code: row.return_code,
+            transaction_id: row.transaction_id.map(|id| id.to_string()),
+            created_at: row.created_at.and_utc().to_rfc3339(),
+            settled_at: row.settled_at.map(|t| t.and_utc().to_rfc3339()),
+        }
+    }
+}
+
+/// Moves `amount` between a customer's primary account and the clearing
+/// house account and records the ledger line. A positive amount credits the
+/// customer.
+async fn post(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    amount: Decimal,
+    description: &str,
+    parent: Option<Uuid>,
+) -> Result<Uuid, TransferError> {
+    let account_number = sqlx::query_scalar::<_, String>(
+        "UPDATE users SET balance = balance + $2 WHERE id = $1 RETURNING account_number"
+    )
+    .bind(user_id)
+    .bind(amount)
+    .fetch_one(&mut *conn)
+    .await?;
+    let clearing = house::adjust(&mut *conn, house::EXTERNAL_CLEARING, -amount).await?;
+    let (from, to) = if amount > Decimal::ZERO {
+        (clearing.as_str(), account_number.as_str())
+    } else {
+        (account_number.as_str(), clearing.as_str())
+    };
+    let (transaction_id, _) =
+        payments::record_transaction_with_parent(&mut *conn, from, to, amount.abs(), Some(description), parent).await?;
+    Ok(transaction_id)
+}
+
+#[derive(sqlx::FromRow)]
+struct CustomerRow {
+    account_number: String,
+    available: Decimal,
+}
+
+async fn create(
+    pool: &PgPool,
+    user_id: Uuid,
+    direction: &'static str,
+    body: &ExternalTransferRequest,
+) -> Result<ExternalTransferView, RailError> {
+    let amount = payments::parse_amount(body.amount).ok_or(TransferError::InvalidAmount)?;
+    let bank = validate_bank_account(body)?;
+
+    // The external account holder is the counterparty of both directions
+    let hits = sanctions::screen_name(&bank.account_holder);
+    if !hits.is_empty() {
+        let mut conn = pool.acquire().await?;
+        sanctions::record_matches(&mut conn, &sanctions::Subject::ExternalTransfer { user_id }, &bank.account_holder, &hits)
+            .await?;
+        log::warn!("{} for {} held on sanctions screening", direction, user_id);
+        return Err(RailError::UnderReview);
+    }
+
+    let rail = rail().ok_or(RailError::Unavailable)?;
+    let id = Uuid::new_v4();
+
+    let mut tx = pool.begin().await?;
+    let customer = sqlx::query_as::<_, CustomerRow>(
+        "SELECT account_number, balance - held_balance AS available FROM users WHERE id = $1 FOR UPDATE"
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if direction == WITHDRAWAL {
+        limits::check(&mut tx, user_id, &customer.account_number, amount).await?;
+    } else {
+        limits::check_inbound(&mut tx, user_id, &customer.account_number, amount).await?;
+    }
+
+    // Withdrawals leave the account now and come back if the rail fails them
+    let transaction_id = if direction == WITHDRAWAL {
+        if customer.available < amount {
+            return Err(TransferError::InsufficientFunds.into());
+        }
+        let description = format!("Withdrawal to ****{}", &bank.account_number[bank.account_number.len() - 4..]);
+        Some(post(&mut tx, user_id, -amount, &description, None).await?)
+    } else {
+        None
+    };
+
+    let reference = rail.submit(&RailTransfer {
+        id,
+        direction,
+        amount,
+        routing_number: &bank.routing_number,
+        account_number: &bank.account_number,
+        account_holder: &bank.account_holder,
+    })?;
+
+    let row = sqlx::query_as::<_, ExternalTransferRow>(&format!(
+        "INSERT INTO external_transfers \
+             (id, user_id, direction, amount, rail, rail_reference, routing_number, external_account, account_holder, transaction_id) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING {}",
+        TRANSFER_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .bind(direction)
+    .bind(amount)
+    .bind(rail.name())
+    .bind(&reference)
+    .bind(&bank.routing_number)
+    .bind(&bank.account_number)
+    .bind(&bank.account_holder)
+    .bind(transaction_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    log::info!("{} {} of {} submitted to {} as {}", direction, id, amount, rail.name(), reference);
+    Ok(row.into())
+}
+
+async fn create_response(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<ExternalTransferRequest>,
+    direction: &'static str,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match create(pool.get_ref(), user_id, direction, &body).await {
+        Ok(transfer) => HttpResponse::Accepted().json(transfer),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// `POST /api/deposits` pulls money in from the caller's external bank
+/// account. It is credited once the rail settles it.
+pub async fn create_deposit(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<ExternalTransferRequest>,
+) -> HttpResponse {
+    create_response(pool, req, body, DEPOSIT).await
+}
+
+/// `POST /api/withdrawals` pays money out to the caller's external bank
+/// account. It is debited straight away.
+pub async fn create_withdrawal(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<ExternalTransferRequest>,
+) -> HttpResponse {
+    create_response(pool, req, body, WITHDRAWAL).await
+}
+
+/// `GET /api/external-transfers` lists the caller's deposits and withdrawals.
+pub async fn list_transfers(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query_as::<_, ExternalTransferRow>(&format!(
+        "SELECT {} FROM external_transfers WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2",
+        TRANSFER_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(MAX_LISTED)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => HttpResponse::Ok().json(rows.into_iter().map(ExternalTransferView::from).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// A status report from a rail.
+#[derive(Debug, Deserialize)]
+pub struct RailEvent {
+    pub reference: String,
+    pub status: String,
+    pub return_code: Option<String>,
+}
+
+/// Applies a status report to the transfer the rail knows as
+/// `event.reference`. Repeating the current status is a no-op, so rails may
+/// retry deliveries.
+pub async fn apply_event(pool: &PgPool, rail_name: &str, event: &RailEvent) -> Result<ExternalTransferView, RailError> {
+    let mut tx = pool.begin().await?;
+    let transfer = apply_event_on(&mut tx, rail_name, event).await?;
+    tx.commit().await?;
+    Ok(transfer)
+}
+
+/// Like [`apply_event`], on the caller's transaction.
+pub async fn apply_event_on(
+    tx: &mut PgConnection,
+    rail_name: &str,
+    event: &RailEvent,
+) -> Result<ExternalTransferView, RailError> {
+    if let Some(code) = &event.return_code {
+        if return_reason(code).is_none() {
+            return Err(RailError::UnknownReturnCode(code.clone()));
+        }
+    }
+
+    let row = sqlx::query_as::<_, ExternalTransferRow>(&format!(
+        "SELECT {} FROM external_transfers WHERE rail = $1 AND rail_reference = $2 FOR UPDATE",
+        TRANSFER_COLUMNS
+    ))
+    .bind(rail_name)
+    .bind(&event.reference)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(RailError::NotFound)?;
+
+    if row.status == event.status {
+        return Ok(row.into());
+    }
+    let last4 = &row.external_account[row.external_account.len().saturating_sub(4)..];
+    let reason = event.return_code.as_deref().and_then(return_reason).unwrap_or("no reason given");
+    let (transaction_id, message) = match (row.status.as_str(), event.status.as_str(), row.direction.as_str()) {
+        ("pending", "settled", DEPOSIT) => {
+            let description = format!("Deposit from ****{}", last4);
+            let id = post(&mut *tx, row.user_id, row.amount, &description, None).await?;
+            (Some(id), format!("Your deposit of {} has arrived.", row.amount))
+        }
+        ("pending", "settled", _) => (row.transaction_id, format!("Your withdrawal of {} has been paid out.", row.amount)),
+        ("pending", "failed", DEPOSIT) => {
+            (None, format!("Your deposit of {} could not be collected: {}", row.amount, reason))
+        }
+        ("pending", "failed", _) | ("settled", "returned", WITHDRAWAL) => {
+            let description = format!("Withdrawal {} {}", event.status, event.return_code.as_deref().unwrap_or(""));
+            post(&mut *tx, row.user_id, row.amount, description.trim(), row.transaction_id).await?;
+            (row.transaction_id, format!("Your withdrawal of {} was {} and refunded: {}", row.amount, event.status, reason))
+        }
+        ("settled", "returned", _) => {
+            let description = format!("Deposit returned {}", event.return_code.as_deref().unwrap_or(""));
+            post(&mut *tx, row.user_id, -row.amount, description.trim(), row.transaction_id).await?;
+            let balance = sqlx::query_scalar::<_, Decimal>("SELECT balance FROM users WHERE id = $1")
+                .bind(row.user_id)
+                .fetch_one(&mut *tx)
+                .await?;
+            let mut message = format!("Your deposit of {} was returned by your bank: {}", row.amount, reason);
+            if balance < Decimal::ZERO {
+                log::warn!("returned deposit {} left {} overdrawn by {}", row.id, row.user_id, -balance);
+                message.push_str(&format!(
+                    ". The funds had already been used, so your account is overdrawn by {}; please add funds to cover it.",
+                    -balance
+                ));
+            }
+            (row.transaction_id, message)
+        }
+        (from, to, _) => {
+            return Err(RailError::InvalidTransition { from: from.to_string(), to: to.to_string() });
+        }
+    };
+
+    let updated = sqlx::query_as::<_, ExternalTransferRow>(&format!(
+        "UPDATE external_transfers SET status = $2, return_code = $3, transaction_id = $4, updated_at = NOW(), \
+             settled_at = CASE WHEN $2 = 'settled' THEN NOW() ELSE settled_at END \
+         WHERE id = $1 RETURNING {}",
+        TRANSFER_COLUMNS
+    ))
+    .bind(row.id)
+    .bind(&event.status)
+    .bind(&event.return_code)
+    .bind(transaction_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    notifications::notify(&mut *tx, row.user_id, &format!("{}_{}", row.direction, event.status), &message, transaction_id)
+        .await?;
+
+    log::info!("{} {} is now {}", row.direction, row.id, event.status);
+    Ok(updated.into())
+}
+
+fn verify_signature(req: &HttpRequest, body: &[u8]) -> Result<(), RailError> {
+    let key = &active().ok_or(RailError::Unavailable)?.webhook_key;
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| URL_SAFE_NO_PAD.decode(v.trim()).ok())
+        .ok_or(RailError::BadSignature)?;
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.verify_slice(&signature).map_err(|_| RailError::BadSignature)
+}
+
+/// `POST /api/rails/{rail}/events` receives status callbacks. The body is
+/// authenticated by an HMAC-SHA256 signature in `X-Rail-Signature`.
+pub async fn receive_event(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> HttpResponse {
+    if let Err(e) = verify_signature(&req, &body) {
+        return e.to_response();
+    }
+    let event: RailEvent = match serde_json::from_slice(&body) {
+        Ok(e) => e,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "invalid_event".to_string(),
+                message: e.to_string(),
+            })
+        }
+    };
+    match apply_event(pool.get_ref(), &path.into_inner(), &event).await {
+        Ok(transfer) => HttpResponse::Ok().json(transfer),
+        Err(e) => e.to_response(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct MockDueRow {
+    rail_reference: String,
+    status: String,
+    external_account: String,
+}
+
+/// Settles, fails and returns transfers on the mock rail once they are
+/// `MOCK_RAIL_SETTLE_SECS` old. Events go through [`apply_event`], the same
+/// path as signed callbacks.
+async fn run_mock_rail(pool: &PgPool, settle_after: Duration) -> Result<usize, sqlx::Error> {
+    let due = Utc::now().naive_utc() - settle_after;
+    let rows = sqlx::query_as::<_, MockDueRow>(
+        "SELECT rail_reference, status, external_account FROM external_transfers \
+         WHERE rail = $1 AND rail_reference IS NOT NULL \
+           AND ((status = 'pending' AND created_at <= $2) \
+             OR (status = 'settled' AND settled_at <= $2 AND external_account LIKE '%2222')) \
+         ORDER BY created_at"
+    )
+    .bind(MockRail::NAME)
+    .bind(due)
+    .fetch_all(pool)
+    .await?;
+
+    let mut applied = 0;
+    for row in rows {
+        let event = if row.status == "settled" {
+            RailEvent { reference: row.rail_reference, status: "returned".to_string(), return_code: Some("R02".to_string()) }
+        } else {
+            RailEvent { reference: row.rail_reference, ..MockRail::outcome(&row.external_account) }
+        };
+        match apply_event(pool, MockRail::NAME, &event).await {
+            Ok(_) => applied += 1,
+            Err(RailError::Database(e)) => return Err(e),
+            Err(e) => log::error!("mock rail could not apply {:?}: {}", event, e),
+        }
+    }
+    Ok(applied)
+}
+
+/// Runs the mock rail's settlement simulation when it is the active rail.
+pub fn spawn_job(pool: PgPool) {
+    if rail().map(|r| r.name()) != Some(MockRail::NAME) {
+        return;
+    }
+    let every = env::var("MOCK_RAIL_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MOCK_INTERVAL_SECS);
+    let settle_after = Duration::seconds(
+        env::var("MOCK_RAIL_SETTLE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MOCK_SETTLE_SECS),
+    );
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(every));
+        loop {
+            interval.tick().await;
+            match run_mock_rail(&pool, settle_after).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("mock rail reported {} status change(s)", n),
+                Err(e) => log::error!("mock rail run failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,183 @@
+//! Receipt rendering, kept free of HTTP concerns so that notification emails
+//! can attach the same documents the API serves.
+
+use chrono::NaiveDateTime;
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
+use sha2::Sha256;
+use std::env;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Everything printed on a receipt.
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub transaction_id: Uuid,
+    pub reference: String,
+    pub created_at: NaiveDateTime,
+    pub from_account: String,
+    pub from_username: Option<String>,
+    pub to_account: String,
+    pub to_username: Option<String>,
+    pub amount: Decimal,
+    pub currency: String,
+    pub description: Option<String>,
+    pub status: String,
+}
+
+fn signing_key() -> Vec<u8> {
+    env::var("RECEIPT_SIGNING_SECRET")
+        .or_else(|_| env::var("JWT_SECRET"))
+        .unwrap_or_else(|_| "your-secret-key".to_string())
+        .into_bytes()
+}
+
+fn reference_mac(transaction_id: Uuid, amount: Decimal, created_at: NaiveDateTime) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(&signing_key()).expect("HMAC accepts any key length");
+    mac.update(transaction_id.as_bytes());
+    mac.update(amount.round_dp(2).to_string().as_bytes());
+    mac.update(&created_at.and_utc().timestamp_micros().to_be_bytes());
+    mac
+}
+
+/// Derives the reference number printed on a receipt, formatted as
+/// `DU-XXXX-XXXX-XXXX-XXXX`. It is an HMAC over the transaction's identity,
+/// amount and time, so it cannot be forged without the server secret.
+pub fn reference_number(transaction_id: Uuid, amount: Decimal, created_at: NaiveDateTime) -> String {
+    let digest = reference_mac(transaction_id, amount, created_at).finalize().into_bytes();
+    let hex: String = digest[..8].iter().map(|b| format!("{:02X}", b)).collect();
+    let groups: Vec<&str> = (0..hex.len()).step_by(4).map(|i| &hex[i..i + 4]).collect();
+    format!("DU-{}", groups.join("-"))
+}
+
+/// Checks a reference number presented by a third party against the stored transaction.
+pub fn verify_reference(reference: &str, transaction_id: Uuid, amount: Decimal, created_at: NaiveDateTime) -> bool {
+    let expected = reference_number(transaction_id, amount, created_at);
+    let given = reference.trim().to_ascii_uppercase();
+    // Compare in constant time; both sides are short fixed-format strings
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn party(account: &str, username: Option<&str>) -> String {
+    match username {
+        Some(name) => format!("{} ({})", name, account),
+        None => account.to_string(),
+    }
+}
+
+impl Receipt {
+    fn lines(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Reference", self.reference.clone()),
+            ("Transaction ID", self.transaction_id.to_string()),
+            ("Date", self.created_at.and_utc().format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+            ("From", party(&self.from_account, self.from_username.as_deref())),
+            ("To", party(&self.to_account, self.to_username.as_deref())),
+            ("Amount", format!("{:.2} {}", self.amount, self.currency)),
+            ("Description", self.description.clone().unwrap_or_else(|| "-".to_string())),
+            ("Status", self.status.clone()),
+        ]
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders a self-contained, printable HTML receipt.
+pub fn render_html(receipt: &Receipt) -> String {
+    let rows: String = receipt
+        .lines()
+        .iter()
+        .map(|(label, value)| format!("<tr><th>{}</th><td>{}</td></tr>", label, escape_html(value)))
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>DeltaUp receipt {reference}</title>
+<style>
+body {{ font-family: -apple-system, Helvetica, Arial, sans-serif; max-width: 560px; margin: 40px auto; color: #111; }}
+h1 {{ font-size: 20px; margin-bottom: 4px; }}
+p.sub {{ color: #666; margin-top: 0; }}
+table {{ width: 100%; border-collapse: collapse; }}
+th, td {{ text-align: left; padding: 8px 0; border-bottom: 1px solid #eee; vertical-align: top; }}
+th {{ width: 40%; color: #555; font-weight: 500; }}
+footer {{ margin-top: 24px; font-size: 12px; color: #888; }}
+@media print {{ body {{ margin: 0; }} }}
+</style>
+</head>
+<body>
+<h1>DeltaUp payment receipt</h1>
+<p class="sub">{reference}</p>
+<table>{rows}</table>
+<footer>Quote the reference number above to verify this receipt with DeltaUp.</footer>
+</body>
+</html>
+"#,
+        reference = escape_html(&receipt.reference),
+        rows = rows,
+    )
+}
+
+/// PDF string literals only allow printable ASCII without unescaped parens.
+fn escape_pdf(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+        .collect::<String>()
+        .replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+/// Renders a single-page PDF using the built-in Helvetica font, written by
+/// hand to avoid pulling in a PDF toolkit for a handful of text lines.
+pub fn render_pdf(receipt: &Receipt) -> Vec<u8> {
+    let mut content = String::from("BT\n/F1 18 Tf\n72 760 Td\n(DeltaUp payment receipt) Tj\n/F1 11 Tf\n");
+    for (idx, (label, value)) in receipt.lines().iter().enumerate() {
+        let offset = if idx == 0 { -36 } else { -22 };
+        content.push_str(&format!("0 {} Td\n({}:) Tj\n", offset, escape_pdf(label)));
+        content.push_str(&format!("160 0 Td\n({}) Tj\n-160 0 Td\n", escape_pdf(value)));
+    }
+    content.push_str("/F1 9 Tf\n0 -40 Td\n(Quote the reference number above to verify this receipt with DeltaUp.) Tj\nET\n");
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 842] /Resources << /Font << /F1 4 0 R >> >> /Contents 5 0 R >>".to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+    ];
+
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (idx, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", idx + 1, object).as_bytes());
+    }
+
+    let xref_at = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_at
+        )
+        .as_bytes(),
+    );
+    out
+}
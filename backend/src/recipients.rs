@@ -0,0 +1,285 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+use crate::account_numbers;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments::TransferError;
+
+const MIN_PHONE_DIGITS: usize = 8;
+const MAX_PHONE_DIGITS: usize = 15;
+const MIN_TAG_LEN: usize = 3;
+const MAX_TAG_LEN: usize = 30;
+const MAX_ALIASES_PER_USER: i64 = 5;
+
+/// How a recipient string was interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipientKind {
+    AccountNumber,
+    Email,
+    Phone,
+    Tag,
+    Username,
+}
+
+impl RecipientKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecipientKind::AccountNumber => "account_number",
+            RecipientKind::Email => "email",
+            RecipientKind::Phone => "phone",
+            RecipientKind::Tag => "tag",
+            RecipientKind::Username => "username",
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ResolvedRecipient {
+    pub user_id: Uuid,
+    pub account_number: String,
+    pub username: String,
+}
+
+/// Normalises a phone handle to `+` and digits, E.164 style.
+fn normalize_phone(value: &str) -> Option<String> {
+    let digits: String = value
+        .strip_prefix('+')?
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.'))
+        .collect();
+    let valid = digits.chars().all(|c| c.is_ascii_digit())
+        && (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits.len());
+    valid.then(|| format!("+{}", digits))
+}
+
+/// Normalises a `$tag` to lower case.
+fn normalize_tag(value: &str) -> Option<String> {
+    let tag = value.strip_prefix('$')?.to_ascii_lowercase();
+    let valid = (MIN_TAG_LEN..=MAX_TAG_LEN).contains(&tag.len())
+        && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    valid.then(|| format!("${}", tag))
+}
+
+/// Works out what kind of identifier `input` is and the normalised value to
+/// look up. Phone handles start with `+`, custom tags with `$`, and a
+/// leading `@` on a username is ignored.
+pub fn classify(input: &str) -> Option<(RecipientKind, String)> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    if account_numbers::is_well_formed(input) {
+        return Some((RecipientKind::AccountNumber, input.to_string()));
+    }
+    if input.starts_with('+') {
+        return normalize_phone(input).map(|p| (RecipientKind::Phone, p));
+    }
+    if input.starts_with('$') {
+        return normalize_tag(input).map(|t| (RecipientKind::Tag, t));
+    }
+    if input.contains('@') && !input.starts_with('@') {
+        return Some((RecipientKind::Email, input.to_string()));
+    }
+    Some((RecipientKind::Username, input.trim_start_matches('@').to_string()))
+}
+
+/// Resolves an account number, username, email or registered alias to the
+/// account it belongs to. Phone aliases count only once verified.
+pub async fn resolve<'e, E: PgExecutor<'e>>(
+    executor: E,
+    input: &str,
+) -> Result<Option<(RecipientKind, ResolvedRecipient)>, sqlx::Error> {
+    let Some((kind, value)) = classify(input) else {
+        return Ok(None);
+    };
+    let sql = match kind {
+        // A number failing its check digits can only be a legacy one
+        RecipientKind::AccountNumber if !account_numbers::is_valid(&value) => {
+            "SELECT id AS user_id, account_number, username FROM users WHERE account_number = $1 AND legacy_account_number"
+        }
+        RecipientKind::AccountNumber => "SELECT id AS user_id, account_number, username FROM users WHERE account_number = $1",
+        RecipientKind::Email => "SELECT id AS user_id, account_number, username FROM users WHERE LOWER(email) = LOWER($1)",
+        RecipientKind::Username => "SELECT id AS user_id, account_number, username FROM users WHERE username = $1",
+        RecipientKind::Phone | RecipientKind::Tag => {
+            "SELECT u.id AS user_id, u.account_number, u.username FROM aliases a JOIN users u ON u.id = a.user_id \
+             WHERE a.alias = $1 AND a.verified_at IS NOT NULL"
+        }
+    };
+    let recipient = sqlx::query_as::<_, ResolvedRecipient>(sql)
+        .bind(value)
+        .fetch_optional(executor)
+        .await?;
+    Ok(recipient.map(|r| (kind, r)))
+}
+
+/// The error to report when [`resolve`] finds nothing: a twelve-digit
+/// number with wrong check digits is almost certainly a typo.
+pub fn not_found_error(input: &str) -> TransferError {
+    let input = input.trim();
+    if account_numbers::is_well_formed(input) && !account_numbers::is_valid(input) {
+        TransferError::InvalidAccountNumber
+    } else {
+        TransferError::RecipientNotFound
+    }
+}
+
+/// Shows enough of a name to recognise it without revealing it: `al***e`.
+pub fn mask_name(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    match chars.len() {
+        0 => String::new(),
+        1..=2 => format!("{}***", chars[0]),
+        n => format!("{}{}***{}", chars[0], chars[1], chars[n - 1]),
+    }
+}
+
+fn mask_account(account: &str) -> String {
+    let tail: String = account.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    format!("****{}", tail)
+}
+
+#[derive(Deserialize)]
+pub struct LookupQuery {
+    pub q: String,
+}
+
+/// `GET /api/recipients/lookup?q=` previews who a payment would go to.
+pub async fn lookup(pool: web::Data<PgPool>, req: HttpRequest, query: web::Query<LookupQuery>) -> HttpResponse {
+    if get_user_id_from_req(&req).await.is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match resolve(pool.get_ref(), &query.q).await {
+        Ok(Some((kind, recipient))) => HttpResponse::Ok().json(RecipientPreview {
+            matched_by: kind.as_str().to_string(),
+            masked_name: mask_name(&recipient.username),
+            masked_account: mask_account(&recipient.account_number),
+        }),
+        Ok(None) => match not_found_error(&query.q) {
+            TransferError::InvalidAccountNumber => TransferError::InvalidAccountNumber.to_response(),
+            _ => HttpResponse::NotFound().json(ErrorResponse {
+                error: "recipient_not_found".to_string(),
+                message: "No account matches this account number, username, email or alias".to_string(),
+            }),
+        },
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AliasRow {
+    id: Uuid,
+    alias: String,
+    kind: String,
+    created_at: NaiveDateTime,
+}
+
+impl From<AliasRow> for AliasView {
+    fn from(row: AliasRow) -> Self {
+        AliasView {
+            id: row.id.to_string(),
+            alias: row.alias,
+            kind: row.kind,
+            created_at: row.created_at.and_utc().to_rfc3339(),
+        }
+    }
+}
+
+pub async fn list_aliases(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query_as::<_, AliasRow>(
+        "SELECT id, alias, kind, created_at FROM aliases WHERE user_id = $1 ORDER BY created_at"
+    )
+    .bind(user_id)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => HttpResponse::Ok().json(rows.into_iter().map(AliasView::from).collect::<Vec<_>>()),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn create_alias(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    body: web::Json<CreateAliasRequest>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    // Anyone could claim someone else's number, so phone aliases wait for
+    // verification by one-time code; until that exists only tags are taken
+    let (kind, alias) = match classify(&body.alias) {
+        Some((kind @ RecipientKind::Tag, alias)) => (kind, alias),
+        Some((RecipientKind::Phone, _)) => return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "phone_alias_unavailable".to_string(),
+            message: "Phone numbers cannot be registered as aliases until they can be verified; use a $tag".to_string(),
+        }),
+        _ => return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "invalid_alias".to_string(),
+            message: format!("Alias must be a $tag of {} to {} letters, digits, _ or .", MIN_TAG_LEN, MAX_TAG_LEN),
+        }),
+    };
+
+    let count = match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM aliases WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(c) => c,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if count >= MAX_ALIASES_PER_USER {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "alias_limit".to_string(),
+            message: format!("At most {} aliases can be registered", MAX_ALIASES_PER_USER),
+        });
+    }
+
+    match sqlx::query_as::<_, AliasRow>(
+        "INSERT INTO aliases (id, user_id, alias, kind, verified_at) VALUES ($1, $2, $3, $4, NOW()) \
+         ON CONFLICT (alias) DO NOTHING RETURNING id, alias, kind, created_at"
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(&alias)
+    .bind(kind.as_str())
+    .fetch_optional(pool.get_ref())
+    .await {
+        Ok(Some(row)) => HttpResponse::Created().json(AliasView::from(row)),
+        Ok(None) => HttpResponse::Conflict().json(ErrorResponse {
+            error: "alias_taken".to_string(),
+            message: "This alias is already registered".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn delete_alias(pool: web::Data<PgPool>, req: HttpRequest, path: web::Path<Uuid>) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match sqlx::query("DELETE FROM aliases WHERE id = $1 AND user_id = $2")
+        .bind(path.into_inner())
+        .bind(user_id)
+        .execute(pool.get_ref())
+        .await
+    {
+        Ok(result) if result.rows_affected() == 0 => HttpResponse::NotFound().json(ErrorResponse {
+            error: "alias_not_found".to_string(),
+            message: "Alias not found".to_string(),
+        }),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,309 @@
+//! Manual review of payments held by fraud screening. Reviewers are users
+//! whose `role` is `reviewer` or `admin`. A reviewer can never decide a
+//! payment they sent or would receive (four-eyes principle), and every
+//! decision records who made it and why.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::payments::{self, TransferError};
+use crate::{fees, notifications};
+
+const REVIEWER_ROLES: [&str; 2] = ["reviewer", "admin"];
+const MAX_REASON_LEN: usize = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReviewError {
+    #[error("Only reviewers can do this")]
+    NotReviewer,
+    #[error("No held payment with this id")]
+    NotFound,
+    #[error("This payment has already been decided")]
+    AlreadyDecided,
+    #[error("Reviewers cannot decide their own payments")]
+    OwnPayment,
+    #[error("A reason of up to {} characters is required", MAX_REASON_LEN)]
+    ReasonRequired,
+    #[error(transparent)]
+    Transfer(#[from] TransferError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl ReviewError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ReviewError::NotReviewer => "forbidden",
+            ReviewError::NotFound => "review_not_found",
+            ReviewError::AlreadyDecided => "already_decided",
+            ReviewError::OwnPayment => "four_eyes_required",
+            ReviewError::ReasonRequired => "reason_required",
+            ReviewError::Transfer(e) => e.code(),
+            ReviewError::Database(_) => "internal_error",
+        }
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        let body = ErrorResponse {
+            error: self.code().to_string(),
+            message: self.to_string(),
+        };
+        match self {
+            ReviewError::Database(_) => HttpResponse::InternalServerError().finish(),
+            ReviewError::Transfer(e) => e.to_response(),
+            ReviewError::NotReviewer | ReviewError::OwnPayment => HttpResponse::Forbidden().json(body),
+            ReviewError::NotFound => HttpResponse::NotFound().json(body),
+            ReviewError::AlreadyDecided => HttpResponse::Conflict().json(body),
+            ReviewError::ReasonRequired => HttpResponse::BadRequest().json(body),
+        }
+    }
+}
+
+pub(crate) async fn is_reviewer(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1 AND role = ANY($2))")
+        .bind(user_id)
+        .bind(&REVIEWER_ROLES[..])
+        .fetch_one(pool)
+        .await
+}
+
+#[derive(sqlx::FromRow)]
+struct PendingRow {
+    transaction_id: Uuid,
+    payment_type: String,
+    from_account: String,
+    sender_username: String,
+    to_account: String,
+    amount: Decimal,
+    description: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow)]
+struct HitRow {
+    transaction_id: Uuid,
+    rule_name: String,
+    action: String,
+    detail: String,
+}
+
+/// `GET /api/admin/reviews` lists held payments, oldest first, with the rule
+/// hits that held them.
+pub async fn list_pending(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let reviewer_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match is_reviewer(pool.get_ref(), reviewer_id).await {
+        Ok(true) => {}
+        Ok(false) => return ReviewError::NotReviewer.to_response(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let pending = match sqlx::query_as::<_, PendingRow>(
+        "SELECT r.transaction_id, r.payment_type, t.from_account, u.username AS sender_username, \
+                t.to_account, t.amount, t.description, t.created_at \
+         FROM payment_reviews r \
+         JOIN transactions t ON t.id = r.transaction_id \
+         JOIN users u ON u.account_number = t.from_account \
+         WHERE r.decision IS NULL AND t.status = 'pending_review' \
+         ORDER BY t.created_at"
+    )
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let ids: Vec<Uuid> = pending.iter().map(|p| p.transaction_id).collect();
+    let hits = match sqlx::query_as::<_, HitRow>(
+        "SELECT transaction_id, rule_name, action, detail FROM screening_hits \
+         WHERE transaction_id = ANY($1) ORDER BY created_at"
+    )
+    .bind(&ids)
+    .fetch_all(pool.get_ref())
+    .await {
+        Ok(rows) => rows,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let mut hits_by_payment: HashMap<Uuid, Vec<RuleHitView>> = HashMap::new();
+    for hit in hits {
+        hits_by_payment.entry(hit.transaction_id).or_default().push(RuleHitView {
+            rule: hit.rule_name,
+            action: hit.action,
+            detail: hit.detail,
+        });
+    }
+
+    HttpResponse::Ok().json(
+        pending
+            .into_iter()
+            .map(|p| PendingReviewView {
+                transaction_id: p.transaction_id.to_string(),
+                payment_type: p.payment_type,
+                from_account: p.from_account,
+                sender_username: p.sender_username,
+                to_account: p.to_account,
+                amount: payments::decimal_to_f64(p.amount),
+                description: p.description,
+                created_at: p.created_at.and_utc().to_rfc3339(),
+                hits: hits_by_payment.remove(&p.transaction_id).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[derive(sqlx::FromRow)]
+struct HeldRow {
+    payment_type: String,
+    decision: Option<String>,
+    sender_id: Uuid,
+    recipient_id: Option<Uuid>,
+    to_account: String,
+    amount: Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Approve,
+    Reject,
+}
+
+impl Decision {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Approve => "approved",
+            Decision::Reject => "rejected",
+        }
+    }
+}
+
+/// Applies a decision on the caller's transaction. Returns the sender and
+/// amount so the sender can be told the outcome.
+async fn decide_held(
+    conn: &mut PgConnection,
+    reviewer_id: Uuid,
+    transaction_id: Uuid,
+    decision: Decision,
+    reason: &str,
+) -> Result<(Uuid, Decimal), ReviewError> {
+    let held = sqlx::query_as::<_, HeldRow>(
+        "SELECT r.payment_type, r.decision, s.id AS sender_id, rcpt.id AS recipient_id, t.to_account, t.amount \
+         FROM payment_reviews r \
+         JOIN transactions t ON t.id = r.transaction_id \
+         JOIN users s ON s.account_number = t.from_account \
+         LEFT JOIN users rcpt ON rcpt.account_number = t.to_account \
+         WHERE r.transaction_id = $1 \
+         FOR UPDATE OF r"
+    )
+    .bind(transaction_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ReviewError::NotFound)?;
+
+    if held.decision.is_some() {
+        return Err(ReviewError::AlreadyDecided);
+    }
+    if held.sender_id == reviewer_id || held.recipient_id == Some(reviewer_id) {
+        return Err(ReviewError::OwnPayment);
+    }
+
+    match decision {
+        Decision::Approve => {
+            let outcome =
+                payments::complete_held(&mut *conn, held.sender_id, transaction_id, &held.to_account, held.amount).await?;
+            fees::apply(&mut *conn, held.sender_id, &outcome, &held.payment_type).await?;
+        }
+        Decision::Reject => payments::release_held(&mut *conn, held.sender_id, transaction_id, held.amount).await?,
+    }
+
+    sqlx::query(
+        "UPDATE payment_reviews SET decision = $2, reviewed_by = $3, reason = $4, decided_at = NOW() WHERE transaction_id = $1"
+    )
+    .bind(transaction_id)
+    .bind(decision.as_str())
+    .bind(reviewer_id)
+    .bind(reason)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok((held.sender_id, held.amount))
+}
+
+async fn decide(
+    pool: &PgPool,
+    req: &HttpRequest,
+    transaction_id: Uuid,
+    body: &ReviewDecisionRequest,
+    decision: Decision,
+) -> Result<ReviewDecisionResponse, ReviewError> {
+    let reviewer_id = get_user_id_from_req(req).await.ok_or(ReviewError::NotReviewer)?;
+    if !is_reviewer(pool, reviewer_id).await? {
+        return Err(ReviewError::NotReviewer);
+    }
+    let reason = body.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        return Err(ReviewError::ReasonRequired);
+    }
+
+    let mut tx = pool.begin().await?;
+    let (sender_id, amount) = decide_held(&mut tx, reviewer_id, transaction_id, decision, reason).await?;
+    let message = match decision {
+        Decision::Approve => format!("Your payment of {} has been approved and completed.", amount),
+        Decision::Reject => format!("Your payment of {} was declined and the funds released: {}", amount, reason),
+    };
+    notifications::notify(&mut *tx, sender_id, &format!("payment_{}", decision.as_str()), &message, Some(transaction_id))
+        .await?;
+    tx.commit().await?;
+
+    log::info!("payment {} {} by {}", transaction_id, decision.as_str(), reviewer_id);
+    Ok(ReviewDecisionResponse {
+        transaction_id: transaction_id.to_string(),
+        decision: decision.as_str().to_string(),
+        status: match decision {
+            Decision::Approve => "completed",
+            Decision::Reject => "rejected",
+        }
+        .to_string(),
+        reviewed_by: reviewer_id.to_string(),
+        reason: reason.to_string(),
+    })
+}
+
+/// `POST /api/admin/reviews/{transaction_id}/approve` executes a held payment.
+pub async fn approve(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<ReviewDecisionRequest>,
+) -> HttpResponse {
+    if get_user_id_from_req(&req).await.is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match decide(pool.get_ref(), &req, path.into_inner(), &body, Decision::Approve).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => e.to_response(),
+    }
+}
+
+/// `POST /api/admin/reviews/{transaction_id}/reject` releases a held payment's
+/// funds back to the sender.
+pub async fn reject(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: web::Json<ReviewDecisionRequest>,
+) -> HttpResponse {
+    if get_user_id_from_req(&req).await.is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match decide(pool.get_ref(), &req, path.into_inner(), &body, Decision::Reject).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(e) => e.to_response(),
+    }
+}
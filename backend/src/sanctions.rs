@@ -0,0 +1,489 @@
+//! Sanctions screening against a local watchlist in the OFAC SDN CSV or XML
+//! layout, named by `SANCTIONS_LIST_PATH`. Names are compared after
+//! normalisation (case, accents, punctuation and word order are ignored) using
+//! Jaro-Winkler similarity; a score of at least `SANCTIONS_MATCH_THRESHOLD`
+//! is a hit. Every hit is stored in `sanctions_matches` for audit.
+//!
+//! The list is held in memory and reloaded when the file changes, or on
+//! demand through `POST /api/admin/sanctions/reload`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::models::*;
+use crate::reviews;
+
+const DEFAULT_MATCH_THRESHOLD: f64 = 0.92;
+const DEFAULT_RELOAD_INTERVAL_SECS: u64 = 300;
+/// Normalised names shorter than this match too much to be screened.
+const MIN_NAME_LEN: usize = 4;
+/// Value OFAC uses for an empty CSV field.
+const CSV_NULL: &str = "-0-";
+
+#[derive(Debug, Clone)]
+struct ListEntry {
+    uid: String,
+    name: String,
+    program: String,
+    /// Normalised primary name and aliases.
+    keys: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Watchlist {
+    entries: Vec<ListEntry>,
+    /// Modification time of the file the list was read from.
+    modified: Option<SystemTime>,
+}
+
+static WATCHLIST: RwLock<Option<Arc<Watchlist>>> = RwLock::new(None);
+
+/// Upper-cases, strips accents and punctuation and sorts the words, so
+/// "Muñoz, José" and "JOSE MUNOZ" compare equal.
+fn normalize(name: &str) -> String {
+    let cleaned: String = name
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_uppercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+fn jaro(a: &[char], b: &[char]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0usize;
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let a_seq = a.iter().zip(&a_matched).filter(|(_, m)| **m).map(|(c, _)| c);
+    let b_seq = b.iter().zip(&b_matched).filter(|(_, m)| **m).map(|(c, _)| c);
+    let transpositions = a_seq.zip(b_seq).filter(|(x, y)| x != y).count() / 2;
+
+    let m = matches as f64;
+    (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64) / m) / 3.0
+}
+
+/// Jaro-Winkler similarity between 0 and 1, boosting a shared prefix of up
+/// to four characters.
+fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let sim = jaro(&a, &b);
+    let prefix = a.iter().zip(&b).take(4).take_while(|(x, y)| x == y).count();
+    sim + prefix as f64 * 0.1 * (1.0 - sim)
+}
+
+fn match_threshold() -> f64 {
+    env::var("SANCTIONS_MATCH_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|t| (0.0..=1.0).contains(t))
+        .unwrap_or(DEFAULT_MATCH_THRESHOLD)
+}
+
+/// Best similarity between a normalised name and any of the entry's names.
+fn score(entry: &ListEntry, key: &str) -> f64 {
+    entry.keys.iter().map(|k| jaro_winkler(key, k)).fold(0.0, f64::max)
+}
+
+fn entry(uid: String, name: String, program: String, aliases: Vec<String>) -> ListEntry {
+    let keys = std::iter::once(&name)
+        .chain(&aliases)
+        .map(|n| normalize(n))
+        .filter(|k| k.len() >= MIN_NAME_LEN)
+        .collect();
+    ListEntry { uid, name, program, keys }
+}
+
+/// Reads `sdn.csv`: entry number, name, type and program are the first four
+/// columns. There is no header row, but one is skipped if present.
+fn parse_csv(data: &[u8]) -> Result<Vec<ListEntry>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let uid = record.get(0).unwrap_or_default();
+        if uid.is_empty() || !uid.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let field = |i: usize| record.get(i).filter(|v| *v != CSV_NULL).unwrap_or_default().to_string();
+        entries.push(entry(uid.to_string(), field(1), field(3), Vec::new()));
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Deserialize)]
+struct SdnList {
+    #[serde(rename = "sdnEntry", default)]
+    entries: Vec<SdnEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SdnName {
+    #[serde(rename = "firstName")]
+    first_name: Option<String>,
+    #[serde(rename = "lastName")]
+    last_name: Option<String>,
+}
+
+fn full_name(first: Option<&str>, last: Option<&str>) -> String {
+    [first, last].into_iter().flatten().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Debug, Deserialize)]
+struct SdnEntry {
+    uid: String,
+    #[serde(rename = "firstName")]
+    first_name: Option<String>,
+    #[serde(rename = "lastName")]
+    last_name: Option<String>,
+    #[serde(rename = "programList")]
+    programs: Option<SdnPrograms>,
+    #[serde(rename = "akaList")]
+    aliases: Option<SdnAliases>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SdnPrograms {
+    #[serde(rename = "program", default)]
+    programs: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SdnAliases {
+    #[serde(rename = "aka", default)]
+    aliases: Vec<SdnName>,
+}
+
+/// Reads `sdn.xml`, including each entry's aliases.
+fn parse_xml(data: &[u8]) -> Result<Vec<ListEntry>, String> {
+    let xml = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+    let list: SdnList = quick_xml::de::from_str(xml).map_err(|e| e.to_string())?;
+    Ok(list
+        .entries
+        .into_iter()
+        .map(|e| {
+            let program = e.programs.map(|p| p.programs.join(", ")).unwrap_or_default();
+            let aliases = e
+                .aliases
+                .map(|a| a.aliases.iter().map(|n| full_name(n.first_name.as_deref(), n.last_name.as_deref())).collect())
+                .unwrap_or_default();
+            entry(e.uid, full_name(e.first_name.as_deref(), e.last_name.as_deref()), program, aliases)
+        })
+        .collect())
+}
+
+fn read_list(path: &Path) -> Result<Watchlist, String> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let is_xml = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xml"));
+    let entries = if is_xml { parse_xml(&data)? } else { parse_csv(&data)? };
+    Ok(Watchlist { entries, modified })
+}
+
+/// (Re)loads the list from `SANCTIONS_LIST_PATH`. Returns the number of
+/// entries, or `None` when no list is configured.
+pub fn reload() -> Result<Option<usize>, String> {
+    let Ok(path) = env::var("SANCTIONS_LIST_PATH") else {
+        return Ok(None);
+    };
+    let list = read_list(Path::new(&path))?;
+    let count = list.entries.len();
+    *WATCHLIST.write().expect("watchlist lock poisoned") = Some(Arc::new(list));
+    Ok(Some(count))
+}
+
+fn current() -> Option<Arc<Watchlist>> {
+    WATCHLIST.read().expect("watchlist lock poisoned").clone()
+}
+
+/// A watchlist entry resembling a screened name.
+#[derive(Debug, Clone)]
+pub struct SanctionsHit {
+    pub list_uid: String,
+    pub list_name: String,
+    pub program: String,
+    pub score: f64,
+}
+
+/// Best match per list entry for `name` scoring at or above the threshold.
+pub fn screen_name(name: &str) -> Vec<SanctionsHit> {
+    let Some(list) = current() else {
+        return Vec::new();
+    };
+    let key = normalize(name);
+    if key.len() < MIN_NAME_LEN {
+        return Vec::new();
+    }
+    let threshold = match_threshold();
+    let mut hits: Vec<SanctionsHit> = list
+        .entries
+        .iter()
+        .filter_map(|e| {
+            let score = score(e, &key);
+            (score >= threshold).then(|| SanctionsHit {
+                list_uid: e.uid.clone(),
+                list_name: e.name.clone(),
+                program: e.program.clone(),
+                score,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits
+}
+
+/// What a screened name belongs to, for the audit trail.
+pub enum Subject {
+    Registration { email: String },
+    Payment { transaction_id: Uuid },
+    Kyc { submission_id: Uuid },
+    ExternalTransfer { user_id: Uuid },
+}
+
+/// Stores hits for audit.
+pub async fn record_matches(
+    conn: &mut PgConnection,
+    subject: &Subject,
+    screened_name: &str,
+    hits: &[SanctionsHit],
+) -> Result<(), sqlx::Error> {
+    let (kind, email, transaction_id, submission_id, user_id) = match subject {
+        Subject::Registration { email } => ("registration", Some(email.as_str()), None, None, None),
+        Subject::Payment { transaction_id } => ("payment", None, Some(*transaction_id), None, None),
+        Subject::Kyc { submission_id } => ("kyc", None, None, Some(*submission_id), None),
+        Subject::ExternalTransfer { user_id } => ("external_transfer", None, None, None, Some(*user_id)),
+    };
+    for hit in hits {
+        sqlx::query(
+            "INSERT INTO sanctions_matches \
+                 (id, subject_type, screened_name, email, transaction_id, kyc_submission_id, user_id, list_uid, list_name, program, score) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
+        )
+        .bind(Uuid::new_v4())
+        .bind(kind)
+        .bind(screened_name)
+        .bind(email)
+        .bind(transaction_id)
+        .bind(submission_id)
+        .bind(user_id)
+        .bind(&hit.list_uid)
+        .bind(&hit.list_name)
+        .bind(&hit.program)
+        .bind(hit.score)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Reloads the list whenever its file changes, checking every
+/// `SANCTIONS_RELOAD_INTERVAL_SECS`.
+pub fn spawn_job() {
+    let Ok(path) = env::var("SANCTIONS_LIST_PATH") else {
+        log::warn!("SANCTIONS_LIST_PATH is not set, sanctions screening is disabled");
+        return;
+    };
+    let every = env::var("SANCTIONS_RELOAD_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_RELOAD_INTERVAL_SECS);
+    actix_rt::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(every));
+        loop {
+            interval.tick().await;
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            let loaded = current().and_then(|l| l.modified);
+            if loaded.is_some() && modified == loaded {
+                continue;
+            }
+            match reload() {
+                Ok(Some(n)) => log::info!("loaded {} sanctions list entries from {}", n, path),
+                Ok(None) => {}
+                Err(e) => log::error!("failed to load sanctions list: {}", e),
+            }
+        }
+    });
+}
+
+/// `POST /api/admin/sanctions/reload` re-reads the list file now.
+pub async fn reload_list(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+    match reviews::is_reviewer(pool.get_ref(), user_id).await {
+        Ok(true) => {}
+        Ok(false) => return reviews::ReviewError::NotReviewer.to_response(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match reload() {
+        Ok(Some(entries)) => HttpResponse::Ok().json(SanctionsReloadResponse { entries }),
+        Ok(None) => HttpResponse::BadRequest().json(ErrorResponse {
+            error: "sanctions_list_not_configured".to_string(),
+            message: "SANCTIONS_LIST_PATH is not set".to_string(),
+        }),
+        Err(e) => {
+            log::error!("failed to load sanctions list: {}", e);
+            HttpResponse::UnprocessableEntity().json(ErrorResponse {
+                error: "invalid_sanctions_list".to_string(),
+                message: e,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-4, "{} is not {}", actual, expected);
+    }
+
+    fn matches(name: &str, listed: &ListEntry) -> bool {
+        score(listed, &normalize(name)) >= DEFAULT_MATCH_THRESHOLD
+    }
+
+    const CSV_FIXTURE: &str = "ent_num,SDN_Name,SDN_Type,Program\n\
+        36,\"AEROCARIBBEAN AIRLINES\",-0- ,\"CUBA\",-0- ,-0- \n\
+        2674,\"PETROV, Ivan\",\"individual\",\"RUSSIA-EO14024\"\n\
+        not-a-number,\"IGNORED NAME\",-0-,\"SDGT\"\n\
+        ,\"NO ID\",-0-,\"SDGT\"\n\
+        9640,\"SHORT ROW\"\n";
+
+    const XML_FIXTURE: &str = r#"<?xml version="1.0" standalone="yes"?>
+        <sdnList xmlns="https://sanctionslistservice.ofac.treas.gov/api/PublicationPreview/exports/XML">
+          <publshInformation><Publish_Date>10/01/2026</Publish_Date></publshInformation>
+          <sdnEntry>
+            <uid>2674</uid>
+            <firstName>Ivan</firstName>
+            <lastName>Petrov</lastName>
+            <sdnType>Individual</sdnType>
+            <programList><program>RUSSIA-EO14024</program><program>CYBER2</program></programList>
+            <akaList>
+              <aka><uid>501</uid><type>a.k.a.</type><firstName>Vanya</firstName><lastName>Petrovsky</lastName></aka>
+            </akaList>
+          </sdnEntry>
+          <sdnEntry>
+            <uid>36</uid>
+            <lastName>AEROCARIBBEAN AIRLINES</lastName>
+            <sdnType>Entity</sdnType>
+          </sdnEntry>
+        </sdnList>"#;
+
+    #[test]
+    fn jaro_winkler_matches_known_values() {
+        assert_close(jaro_winkler("MARTHA", "MARHTA"), 0.9611);
+        assert_close(jaro_winkler("DWAYNE", "DUANE"), 0.84);
+        assert_close(jaro_winkler("DIXON", "DICKSONX"), 0.8133);
+        assert_close(jaro_winkler("SAME", "SAME"), 1.0);
+        assert_close(jaro_winkler("ABCD", "WXYZ"), 0.0);
+    }
+
+    #[test]
+    fn normalisation_ignores_case_accents_punctuation_and_order() {
+        assert_eq!(normalize("Muñoz, José"), "JOSE MUNOZ");
+        assert_eq!(normalize("JOSE MUNOZ"), "JOSE MUNOZ");
+        assert_eq!(normalize("  o'brien-SMITH "), "BRIEN O SMITH");
+        assert_eq!(normalize("zoë ÿves"), "YVES ZOE");
+    }
+
+    #[test]
+    fn near_misses_around_the_threshold() {
+        let listed = entry("2674".into(), "PETROV, Ivan".into(), "RUSSIA-EO14024".into(), Vec::new());
+        // Other spellings of the same name
+        assert!(matches("Ivan Petrova", &listed));
+        assert!(matches("Ivàn Pétrov", &listed));
+        assert!(matches("IVN PETROV", &listed));
+        // Just under the threshold: close, but a different name
+        assert_close(jaro_winkler(&normalize("Ivan Popov"), &normalize("Petrov, Ivan")), 0.9055);
+        assert!(!matches("Ivan Popov", &listed));
+        assert!(!matches("Maria Gonzalez", &listed));
+    }
+
+    #[test]
+    fn short_names_are_not_keys() {
+        let listed = entry("1".into(), "Li".into(), "SDGT".into(), vec!["Wei Li".into()]);
+        assert_eq!(listed.keys, vec!["LI WEI".to_string()]);
+    }
+
+    #[test]
+    fn reads_the_csv_layout() {
+        let entries = parse_csv(CSV_FIXTURE.as_bytes()).unwrap();
+        let summary: Vec<(&str, &str, &str)> =
+            entries.iter().map(|e| (e.uid.as_str(), e.name.as_str(), e.program.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("36", "AEROCARIBBEAN AIRLINES", "CUBA"),
+                ("2674", "PETROV, Ivan", "RUSSIA-EO14024"),
+                ("9640", "SHORT ROW", ""),
+            ]
+        );
+        assert_eq!(entries[1].keys, vec!["IVAN PETROV".to_string()]);
+    }
+
+    #[test]
+    fn rejects_a_csv_that_is_not_utf8() {
+        assert!(parse_csv(b"36,\"AERO\xffCARIBBEAN\",-0-,\"CUBA\"\n").is_err());
+    }
+
+    #[test]
+    fn reads_the_xml_layout_with_aliases() {
+        let entries = parse_xml(XML_FIXTURE.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].uid, "2674");
+        assert_eq!(entries[0].name, "Ivan Petrov");
+        assert_eq!(entries[0].program, "RUSSIA-EO14024, CYBER2");
+        assert_eq!(entries[0].keys, vec!["IVAN PETROV".to_string(), "PETROVSKY VANYA".to_string()]);
+        assert!(matches("Vanya Petrovski", &entries[0]));
+        assert_eq!(entries[1].name, "AEROCARIBBEAN AIRLINES");
+        assert_eq!(entries[1].program, "");
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        assert!(parse_xml(b"<sdnList><sdnEntry><uid>1</uid><lastName>X</sdnEntry></sdnList>").is_err());
+        // Every entry needs a uid
+        assert!(parse_xml(b"<sdnList><sdnEntry><lastName>NO UID</lastName></sdnEntry></sdnList>").is_err());
+        assert!(parse_xml(b"<sdnList>\xff</sdnList>").is_err());
+    }
+}
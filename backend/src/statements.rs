@@ -0,0 +1,499 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::handlers::get_user_id_from_req;
+use crate::history::{self, TransactionFilter};
+use crate::iso20022;
+use crate::models::ErrorResponse;
+
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+const MAX_PERIOD_DAYS: i64 = 366;
+
+#[derive(Deserialize)]
+pub struct StatementQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub format: Option<String>,
+}
+
+/// One booked movement with the balance right after it.
+#[derive(Debug, Clone)]
+pub struct StatementLine {
+    pub transaction_id: Uuid,
+    pub posted_at: NaiveDateTime,
+    pub description: Option<String>,
+    pub counterparty_account: String,
+    pub counterparty_name: Option<String>,
+    /// Negative for debits.
+    pub amount: Decimal,
+    pub balance: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub account_number: String,
+    pub currency: String,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    pub opening_balance: Decimal,
+    pub closing_balance: Decimal,
+    pub lines: Vec<StatementLine>,
+}
+
+#[derive(sqlx::FromRow)]
+struct AccountRow {
+    account_number: String,
+    balance: Decimal,
+}
+
+/// Net effect on `account` of completed transactions strictly after `after`.
+/// Transfers to oneself net to zero.
+pub(crate) async fn net_movement_after(pool: &PgPool, account: &str, after: NaiveDateTime) -> Result<Decimal, sqlx::Error> {
+    sqlx::query_scalar::<_, Decimal>(
+        "SELECT COALESCE(SUM(CASE WHEN from_account = to_account THEN 0 \
+                                  WHEN from_account = $1 THEN -amount ELSE amount END), 0) \
+         FROM transactions \
+         WHERE (from_account = $1 OR to_account = $1) AND status = 'completed' AND created_at > $2"
+    )
+    .bind(account)
+    .bind(after)
+    .fetch_one(pool)
+    .await
+}
+
+#[derive(sqlx::FromRow)]
+struct SnapshotRow {
+    closing_balance: Decimal,
+    period_end: NaiveDateTime,
+}
+
+/// Balance of the user's account including every completed transaction up
+/// to and including `as_of`. Starts from the latest month-end statement
+/// before `as_of` when there is one, otherwise walks back from the live
+/// balance; both agree because statements are derived from the live balance
+/// and closed periods are frozen.
+pub async fn balance_as_of(pool: &PgPool, user_id: Uuid, as_of: NaiveDateTime) -> Result<Decimal, sqlx::Error> {
+    let snapshot = sqlx::query_as::<_, SnapshotRow>(
+        "SELECT closing_balance, period_end FROM statements \
+         WHERE user_id = $1 AND period_end <= $2 ORDER BY period_end DESC LIMIT 1"
+    )
+    .bind(user_id)
+    .bind(as_of)
+    .fetch_optional(pool)
+    .await?;
+
+    match snapshot {
+        Some(snapshot) => sqlx::query_scalar::<_, Decimal>(
+            "SELECT $3 + COALESCE(SUM(CASE WHEN t.from_account = t.to_account THEN 0 \
+                                           WHEN t.from_account = u.account_number THEN -t.amount ELSE t.amount END), 0) \
+             FROM users u \
+             LEFT JOIN transactions t ON (t.from_account = u.account_number OR t.to_account = u.account_number) \
+                  AND t.status = 'completed' AND t.created_at >= $4 AND t.created_at <= $2 \
+             WHERE u.id = $1"
+        )
+        .bind(user_id)
+        .bind(as_of)
+        .bind(snapshot.closing_balance)
+        .bind(snapshot.period_end)
+        .fetch_one(pool)
+        .await,
+        // One statement, so the live balance and the movements after `as_of`
+        // come from the same snapshot
+        None => sqlx::query_scalar::<_, Decimal>(
+            "SELECT u.balance - COALESCE(SUM(CASE WHEN t.from_account = t.to_account THEN 0 \
+                                                  WHEN t.from_account = u.account_number THEN -t.amount ELSE t.amount END), 0) \
+             FROM users u \
+             LEFT JOIN transactions t ON (t.from_account = u.account_number OR t.to_account = u.account_number) \
+                  AND t.status = 'completed' AND t.created_at > $2 \
+             WHERE u.id = $1 \
+             GROUP BY u.balance"
+        )
+        .bind(user_id)
+        .bind(as_of)
+        .fetch_one(pool)
+        .await,
+    }
+}
+
+/// Builds a statement for the user's account between `from` and `to`
+/// inclusive. Balances are derived backwards from the live balance so they
+/// always reconcile with `users.balance`.
+pub async fn build_statement(
+    pool: &PgPool,
+    user_id: Uuid,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<Statement, sqlx::Error> {
+    let account = sqlx::query_as::<_, AccountRow>("SELECT account_number, balance FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    let closing_balance = account.balance - net_movement_after(pool, &account.account_number, to).await?;
+
+    let filter = TransactionFilter {
+        from: Some(from.and_utc()),
+        to: Some(to.and_utc()),
+        status: Some("completed".to_string()),
+        ..Default::default()
+    };
+    let mut qb = history::base_query(&account.account_number);
+    history::push_filters(&mut qb, &account.account_number, &filter);
+    qb.push(" ORDER BY t.created_at ASC, t.id ASC");
+    let rows = qb.build_query_as::<history::HistoryRow>().fetch_all(pool).await?;
+
+    let period_net: Decimal = rows
+        .iter()
+        .map(|r| match (r.from_account == r.to_account, r.is_sent_by(&account.account_number)) {
+            (true, _) => Decimal::ZERO,
+            (false, true) => -r.amount,
+            (false, false) => r.amount,
+        })
+        .sum();
+    let opening_balance = closing_balance - period_net;
+
+    let mut balance = opening_balance;
+    let lines = rows
+        .into_iter()
+        .map(|r| {
+            let sent = r.is_sent_by(&account.account_number);
+            let amount = if r.from_account == r.to_account {
+                Decimal::ZERO
+            } else if sent {
+                -r.amount
+            } else {
+                r.amount
+            };
+            balance += amount;
+            StatementLine {
+                transaction_id: r.id,
+                posted_at: r.created_at,
+                description: r.description,
+                counterparty_account: if sent { r.to_account } else { r.from_account },
+                counterparty_name: r.counterparty_username,
+                amount,
+                balance,
+            }
+        })
+        .collect();
+
+    Ok(Statement {
+        account_number: account.account_number,
+        currency: "USD".to_string(),
+        from,
+        to,
+        opening_balance,
+        closing_balance,
+        lines,
+    })
+}
+
+pub fn render_csv(statement: &Statement) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["date", "transaction_id", "description", "counterparty_account", "counterparty_name", "debit", "credit", "amount", "balance"])?;
+
+    let opening_date = statement.from.format("%Y-%m-%d").to_string();
+    let opening = format!("{:.2}", statement.opening_balance);
+    writer.write_record([opening_date.as_str(), "", "Opening balance", "", "", "", "", "", opening.as_str()])?;
+
+    for line in &statement.lines {
+        let (debit, credit) = if line.amount < Decimal::ZERO {
+            (format!("{:.2}", -line.amount), String::new())
+        } else {
+            (String::new(), format!("{:.2}", line.amount))
+        };
+        writer.write_record([
+            line.posted_at.format("%Y-%m-%d").to_string(),
+            line.transaction_id.to_string(),
+            line.description.clone().unwrap_or_default(),
+            line.counterparty_account.clone(),
+            line.counterparty_name.clone().unwrap_or_default(),
+            debit,
+            credit,
+            format!("{:.2}", line.amount),
+            format!("{:.2}", line.balance),
+        ])?;
+    }
+
+    let closing_date = statement.to.format("%Y-%m-%d").to_string();
+    let closing = format!("{:.2}", statement.closing_balance);
+    writer.write_record([closing_date.as_str(), "", "Closing balance", "", "", "", "", "", closing.as_str()])?;
+
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+pub(crate) fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn ofx_date(value: NaiveDateTime) -> String {
+    value.format("%Y%m%d%H%M%S.000[0:UTC]").to_string()
+}
+
+/// OFX 2.2 bank statement. `FITID` is the transaction UUID so importers can
+/// de-duplicate overlapping downloads.
+pub fn render_ofx(statement: &Statement) -> String {
+    let now = ofx_date(Utc::now().naive_utc());
+    let transactions: String = statement
+        .lines
+        .iter()
+        .map(|line| {
+            let name = line.counterparty_name.as_deref().unwrap_or(&line.counterparty_account);
+            let memo = line
+                .description
+                .as_deref()
+                .map(|d| format!("<MEMO>{}</MEMO>", escape_xml(d)))
+                .unwrap_or_default();
+            format!(
+                "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{:.2}</TRNAMT><FITID>{}</FITID><NAME>{}</NAME>{}</STMTTRN>\n",
+                if line.amount < Decimal::ZERO { "DEBIT" } else { "CREDIT" },
+                ofx_date(line.posted_at),
+                line.amount,
+                line.transaction_id,
+                escape_xml(&name.chars().take(32).collect::<String>()),
+                memo,
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE" OLDFILEUID="NONE" NEWFILEUID="NONE"?>
+<OFX>
+<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS><DTSERVER>{now}</DTSERVER><LANGUAGE>ENG</LANGUAGE><FI><ORG>DeltaUp</ORG><FID>DELTAUP</FID></FI></SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1><STMTTRNRS><TRNUID>{account}-{start}</TRNUID><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>
+<STMTRS><CURDEF>{currency}</CURDEF>
+<BANKACCTFROM><BANKID>DELTAUP</BANKID><ACCTID>{account}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>
+<BANKTRANLIST><DTSTART>{start}</DTSTART><DTEND>{end}</DTEND>
+{transactions}</BANKTRANLIST>
+<LEDGERBAL><BALAMT>{closing:.2}</BALAMT><DTASOF>{end}</DTASOF></LEDGERBAL>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+"#,
+        now = now,
+        account = escape_xml(&statement.account_number),
+        start = ofx_date(statement.from),
+        end = ofx_date(statement.to),
+        currency = statement.currency,
+        transactions = transactions,
+        closing = statement.closing_balance,
+    )
+}
+
+/// QIF has no balance records, so the opening balance is written as the
+/// conventional first "Opening Balance" entry. The `N` field carries the
+/// transaction id for de-duplication.
+pub fn render_qif(statement: &Statement) -> String {
+    let qif_line = |value: &str| value.replace(['\r', '\n'], " ");
+    let mut out = String::from("!Type:Bank\n");
+    out.push_str(&format!(
+        "D{}\nT{:.2}\nPOpening Balance\nL[DeltaUp {}]\n^\n",
+        statement.from.format("%m/%d/%Y"),
+        statement.opening_balance,
+        statement.account_number,
+    ));
+    for line in &statement.lines {
+        out.push_str(&format!("D{}\n", line.posted_at.format("%m/%d/%Y")));
+        out.push_str(&format!("T{:.2}\n", line.amount));
+        out.push_str(&format!("N{}\n", line.transaction_id));
+        out.push_str(&format!(
+            "P{}\n",
+            qif_line(line.counterparty_name.as_deref().unwrap_or(&line.counterparty_account))
+        ));
+        if let Some(description) = &line.description {
+            out.push_str(&format!("M{}\n", qif_line(description)));
+        }
+        out.push_str("^\n");
+    }
+    out
+}
+
+fn bad_request(error: &str, message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse {
+        error: error.to_string(),
+        message,
+    })
+}
+
+/// Resolves and validates the requested statement window.
+pub fn statement_window(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<(NaiveDateTime, NaiveDateTime), String> {
+    let to = to.unwrap_or_else(Utc::now).naive_utc();
+    let from = from.map(|f| f.naive_utc()).unwrap_or(to - Duration::days(DEFAULT_PERIOD_DAYS));
+    if from > to {
+        return Err("from must be before to".to_string());
+    }
+    if to - from > Duration::days(MAX_PERIOD_DAYS) {
+        return Err(format!("Statements may cover at most {} days", MAX_PERIOD_DAYS));
+    }
+    Ok((from, to))
+}
+
+pub async fn export_statement(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    query: web::Query<StatementQuery>,
+) -> HttpResponse {
+    let user_id = match get_user_id_from_req(&req).await {
+        Some(id) => id,
+        None => return HttpResponse::Unauthorized().finish(),
+    };
+
+    let format = query.format.as_deref().unwrap_or("csv");
+    if !["csv", "ofx", "qif", "camt053"].contains(&format) {
+        return bad_request("unsupported_format", "Format must be csv, ofx, qif or camt053".to_string());
+    }
+
+    let (from, to) = match statement_window(query.from, query.to) {
+        Ok(window) => window,
+        Err(message) => return bad_request("invalid_period", message),
+    };
+
+    let statement = match build_statement(pool.get_ref(), user_id, from, to).await {
+        Ok(s) => s,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let (body, content_type) = match format {
+        "csv" => match render_csv(&statement) {
+            Ok(bytes) => (bytes, "text/csv; charset=utf-8"),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        "ofx" => (render_ofx(&statement).into_bytes(), "application/x-ofx"),
+        "camt053" => (iso20022::render_camt053(&statement).into_bytes(), "application/xml"),
+        _ => (render_qif(&statement).into_bytes(), "application/qif"),
+    };
+
+    let extension = if format == "camt053" { "xml" } else { format };
+    let filename = format!(
+        "deltaup-{}-{}-{}.{}",
+        statement.account_number,
+        from.format("%Y%m%d"),
+        to.format("%Y%m%d"),
+        extension
+    );
+
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::str::FromStr;
+
+    const RENT_ID: &str = "0b6e7c2e-5f0a-4a53-9d0e-3c1f2a7b8d41";
+    const SALARY_ID: &str = "7d1c9e44-2b3a-4f6e-8a5d-9e0f1b2c3d4e";
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap().and_hms_opt(hour, 30, 0).unwrap()
+    }
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn statement() -> Statement {
+        Statement {
+            account_number: "123456789092".to_string(),
+            currency: "USD".to_string(),
+            from: at(1, 0),
+            to: at(31, 23),
+            opening_balance: dec("100.00"),
+            closing_balance: dec("114.50"),
+            lines: vec![
+                StatementLine {
+                    transaction_id: Uuid::parse_str(RENT_ID).unwrap(),
+                    posted_at: at(3, 9),
+                    description: Some("Rent <March>\nflat 2".to_string()),
+                    counterparty_account: "987654321012".to_string(),
+                    counterparty_name: Some("Smith & Sons".to_string()),
+                    amount: dec("-25.50"),
+                    balance: dec("74.50"),
+                },
+                StatementLine {
+                    transaction_id: Uuid::parse_str(SALARY_ID).unwrap(),
+                    posted_at: at(28, 17),
+                    description: None,
+                    counterparty_account: "555555555012".to_string(),
+                    counterparty_name: None,
+                    amount: dec("40.00"),
+                    balance: dec("114.50"),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn csv_has_opening_running_and_closing_balances() {
+        let data = render_csv(&statement()).unwrap();
+        let mut reader = csv::Reader::from_reader(data.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["date", "transaction_id", "description", "counterparty_account", "counterparty_name", "debit", "credit", "amount", "balance"]
+        );
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], vec!["2026-03-01", "", "Opening balance", "", "", "", "", "", "100.00"]);
+        assert_eq!(
+            rows[1],
+            vec!["2026-03-03", RENT_ID, "Rent <March>\nflat 2", "987654321012", "Smith & Sons", "25.50", "", "-25.50", "74.50"]
+        );
+        assert_eq!(rows[2], vec!["2026-03-28", SALARY_ID, "", "555555555012", "", "", "40.00", "40.00", "114.50"]);
+        assert_eq!(rows[3], vec!["2026-03-31", "", "Closing balance", "", "", "", "", "", "114.50"]);
+    }
+
+    #[test]
+    fn ofx_uses_transaction_ids_as_fitids() {
+        let ofx = render_ofx(&statement());
+        assert!(ofx.contains(&format!(
+            "<STMTTRN><TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20260303093000.000[0:UTC]</DTPOSTED><TRNAMT>-25.50</TRNAMT>\
+             <FITID>{}</FITID><NAME>Smith &amp; Sons</NAME><MEMO>Rent &lt;March&gt;\nflat 2</MEMO></STMTTRN>",
+            RENT_ID
+        )));
+        assert!(ofx.contains(&format!(
+            "<STMTTRN><TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20260328173000.000[0:UTC]</DTPOSTED><TRNAMT>40.00</TRNAMT>\
+             <FITID>{}</FITID><NAME>555555555012</NAME></STMTTRN>",
+            SALARY_ID
+        )));
+        assert!(ofx.contains("<ACCTID>123456789092</ACCTID>"));
+        assert!(ofx.contains("<DTSTART>20260301003000.000[0:UTC]</DTSTART><DTEND>20260331233000.000[0:UTC]</DTEND>"));
+        assert!(ofx.contains("<LEDGERBAL><BALAMT>114.50</BALAMT><DTASOF>20260331233000.000[0:UTC]</DTASOF></LEDGERBAL>"));
+        assert!(ofx.contains("<TRNUID>123456789092-20260301003000.000[0:UTC]</TRNUID>"));
+    }
+
+    #[test]
+    fn ofx_is_stable_apart_from_the_server_time() {
+        let without_server_time = |ofx: String| {
+            let start = ofx.find("<DTSERVER>").unwrap();
+            let end = ofx.find("</DTSERVER>").unwrap();
+            format!("{}{}", &ofx[..start], &ofx[end..])
+        };
+        assert_eq!(without_server_time(render_ofx(&statement())), without_server_time(render_ofx(&statement())));
+    }
+
+    #[test]
+    fn qif_starts_with_the_opening_balance() {
+        let expected = format!(
+            "!Type:Bank\n\
+             D03/01/2026\nT100.00\nPOpening Balance\nL[DeltaUp 123456789092]\n^\n\
+             D03/03/2026\nT-25.50\nN{}\nPSmith & Sons\nMRent <March> flat 2\n^\n\
+             D03/28/2026\nT40.00\nN{}\nP555555555012\n^\n",
+            RENT_ID, SALARY_ID
+        );
+        assert_eq!(render_qif(&statement()), expected);
+    }
+}
@@ -24,7 +24,7 @@ Stack: Next.js 15, Rust/Actix, Postgres, Docker
 Auth: JWT (7d expiry)
 Features:
 - Register/Login: Hash pwd, 12-digit acc #
-- Balance: New users get $1000
+- Balance: New accounts start at $0; fund them by card top-up or deposit
 - Transfers: Instant, ACID compliant
 - QR: Generate/Scan (Front/Rear cam support)
 - Market Watch: Real-time crypto prices (powered by CoinGecko API)
@@ -86,9 +86,9 @@ export default function Chatbot({ isOpen, onClose }: ChatbotProps) {
                     const balance = await transactionAPI.getBalance()
                     return JSON.stringify(balance)
                 case 'get_transactions':
-                    const transactions = await transactionAPI.getTransactions()
                     // Limit to last 5 to save tokens
-                    const recent = Array.isArray(transactions) ? transactions.slice(0, 5) : []
+                    const transactions = await transactionAPI.getTransactions({ limit: 5 })
+                    const recent = Array.isArray(transactions?.items) ? transactions.items : []
                     return JSON.stringify(recent)
                 case 'get_market_prices':
                     const prices = await externalAPI.getMarketData()
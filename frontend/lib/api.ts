@@ -40,7 +40,7 @@ api.interceptors.response.use(
 
 // API functions
 export const authAPI = {
-    register: async (data: { username: string; email: string; password: string }) => {
+    register: async (data: { username: string; email: string; password: string; full_name?: string }) => {
         const response = await api.post('/api/auth/register', data)
         return response.data
     },
@@ -57,7 +57,7 @@ export const authAPI = {
 }
 
 export const transactionAPI = {
-    transfer: async (data: { recipient_account: string; amount: number; description?: string }) => {
+    transfer: async (data: { recipient_account?: string; payee_id?: string; amount: number; description?: string }) => {
         const response = await api.post('/api/transfer', data)
         return response.data
     },
@@ -67,13 +67,202 @@ export const transactionAPI = {
         return response.data
     },
 
-    qrPayment: async (data: { qr_data: string }) => {
+    qrPayment: async (data: { qr_data: string; amount?: number }) => {
         const response = await api.post('/api/qr-payment', data)
         return response.data
     },
 
-    getTransactions: async () => {
-        const response = await api.get('/api/transactions')
+    generateQR: async (data: { amount?: number; reference?: string; one_time?: boolean }) => {
+        const response = await api.post('/api/qr/generate', data)
+        return response.data
+    },
+
+    getTransactions: async (params?: {
+        from?: string
+        to?: string
+        min_amount?: number
+        max_amount?: number
+        counterparty?: string
+        status?: string
+        direction?: 'sent' | 'received'
+        q?: string
+        cursor?: string
+        limit?: number
+    }) => {
+        const response = await api.get('/api/transactions', { params })
+        return response.data
+    },
+}
+
+export const payeeAPI = {
+    list: async () => {
+        const response = await api.get('/api/payees')
+        return response.data
+    },
+
+    create: async (data: { nickname: string; account_number: string; default_description?: string }) => {
+        const response = await api.post('/api/payees', data)
+        return response.data
+    },
+
+    update: async (id: string, data: { nickname?: string; default_description?: string }) => {
+        const response = await api.put(`/api/payees/${id}`, data)
+        return response.data
+    },
+
+    remove: async (id: string) => {
+        await api.delete(`/api/payees/${id}`)
+    },
+}
+
+export const accountAPI = {
+    list: async () => {
+        const response = await api.get('/api/accounts')
+        return response.data
+    },
+
+    open: async (data: { name: string; kind?: 'checking' | 'savings' | 'pot' }) => {
+        const response = await api.post('/api/accounts', data)
+        return response.data
+    },
+
+    rename: async (accountNumber: string, name: string) => {
+        const response = await api.put(`/api/accounts/${accountNumber}`, { name })
+        return response.data
+    },
+
+    close: async (accountNumber: string) => {
+        const response = await api.delete(`/api/accounts/${accountNumber}`)
+        return response.data
+    },
+
+    move: async (data: { from_account: string; to_account: string; amount: number; description?: string }) => {
+        const response = await api.post('/api/accounts/transfer', data)
+        return response.data
+    },
+}
+
+export const notificationAPI = {
+    list: async () => {
+        const response = await api.get('/api/notifications')
+        return response.data
+    },
+
+    markRead: async (id: string) => {
+        await api.post(`/api/notifications/${id}/read`)
+    },
+}
+
+export const reviewAPI = {
+    pending: async () => {
+        const response = await api.get('/api/admin/reviews')
+        return response.data
+    },
+
+    approve: async (transactionId: string, reason: string) => {
+        const response = await api.post(`/api/admin/reviews/${transactionId}/approve`, { reason })
+        return response.data
+    },
+
+    reject: async (transactionId: string, reason: string) => {
+        const response = await api.post(`/api/admin/reviews/${transactionId}/reject`, { reason })
+        return response.data
+    },
+}
+
+export const kycAPI = {
+    status: async () => {
+        const response = await api.get('/api/kyc')
+        return response.data
+    },
+
+    uploadDocument: async (kind: string, file: File) => {
+        const response = await api.post('/api/kyc/documents', file, {
+            params: { kind },
+            headers: { 'Content-Type': file.type },
+        })
+        return response.data
+    },
+
+    submit: async (data: {
+        requested_tier: 'basic' | 'full'
+        legal_name: string
+        date_of_birth: string
+        address: string
+        document_ids?: string[]
+    }) => {
+        const response = await api.post('/api/kyc/submissions', data)
+        return response.data
+    },
+
+    pending: async () => {
+        const response = await api.get('/api/admin/kyc/submissions')
+        return response.data
+    },
+
+    approve: async (id: string, reason: string) => {
+        const response = await api.post(`/api/admin/kyc/submissions/${id}/approve`, { reason })
+        return response.data
+    },
+
+    reject: async (id: string, reason: string) => {
+        const response = await api.post(`/api/admin/kyc/submissions/${id}/reject`, { reason })
+        return response.data
+    },
+}
+
+export const fundingAPI = {
+    topUp: async (amount: number, cardNumber: string) => {
+        const response = await api.post('/api/topups', { amount, card_number: cardNumber })
+        return response.data
+    },
+
+    adminDeposit: async (accountNumber: string, amount: number, reference: string) => {
+        const response = await api.post('/api/admin/deposits', { account_number: accountNumber, amount, reference })
+        return response.data
+    },
+}
+
+export interface BankAccountDetails {
+    routing_number: string
+    account_number: string
+    account_holder: string
+}
+
+export const railAPI = {
+    deposit: async (amount: number, bank: BankAccountDetails) => {
+        const response = await api.post('/api/deposits', { amount, ...bank })
+        return response.data
+    },
+
+    withdraw: async (amount: number, bank: BankAccountDetails) => {
+        const response = await api.post('/api/withdrawals', { amount, ...bank })
+        return response.data
+    },
+
+    list: async () => {
+        const response = await api.get('/api/external-transfers')
+        return response.data
+    },
+}
+
+export const limitAPI = {
+    get: async () => {
+        const response = await api.get('/api/limits')
+        return response.data
+    },
+}
+
+export const feeAPI = {
+    quote: async (transactionType: 'transfer' | 'qr_payment', amount: number) => {
+        const response = await api.get('/api/fees/quote', { params: { transaction_type: transactionType, amount } })
+        return response.data
+    },
+}
+
+export const recipientAPI = {
+    lookup: async (q: string) => {
+        const response = await api.get('/api/recipients/lookup', { params: { q } })
         return response.data
     },
 }
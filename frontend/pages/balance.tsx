@@ -3,10 +3,20 @@ import { useRouter } from 'next/router'
 import { transactionAPI } from '@/lib/api'
 import Preloader from '@/components/Preloader'
 
+interface AccountData {
+  account_number: string
+  name: string
+  kind: string
+  balance: number
+  is_primary: boolean
+}
+
 interface BalanceData {
   balance?: number
   account_number?: string
   currency?: string
+  accounts?: AccountData[]
+  total_balance?: number
 }
 
 export default function Balance() {
@@ -120,6 +130,31 @@ export default function Balance() {
               </div>
             </div>
 
+            {/* Accounts and pots */}
+            {balance.accounts && balance.accounts.length > 1 && (
+              <div className="glass-panel p-8 rounded-3xl">
+                <div className="flex justify-between items-baseline mb-6">
+                  <h3 className="text-xl font-bold text-foreground">Accounts</h3>
+                  <span className="text-sm text-muted">
+                    Total ${(balance.total_balance ?? 0).toLocaleString('en-US', { minimumFractionDigits: 2 })}
+                  </span>
+                </div>
+                <ul className="divide-y divide-border">
+                  {balance.accounts.map(account => (
+                    <li key={account.account_number} className="flex justify-between items-center py-3">
+                      <div>
+                        <p className="font-semibold text-foreground">{account.name}</p>
+                        <p className="text-xs text-muted font-mono">{account.account_number} · {account.kind}</p>
+                      </div>
+                      <p className="font-bold font-mono text-foreground">
+                        ${account.balance.toLocaleString('en-US', { minimumFractionDigits: 2 })}
+                      </p>
+                    </li>
+                  ))}
+                </ul>
+              </div>
+            )}
+
             {/* Action Cards */}
             <div className="glass-panel p-8 rounded-3xl">
               <h3 className="text-xl font-bold text-foreground mb-8">Quick Actions</h3>
@@ -55,8 +55,8 @@ export default function Dashboard() {
 
     const fetchTransactions = async () => {
         try {
-            const response = await transactionAPI.getTransactions()
-            setTransactions(Array.isArray(response) ? response.slice(0, 5) : [])
+            const response = await transactionAPI.getTransactions({ limit: 5 })
+            setTransactions(Array.isArray(response?.items) ? response.items : [])
         } catch (err) {
             console.error('Failed to fetch transactions:', err)
         }
@@ -6,10 +6,25 @@ import jsqr from 'jsqr'
 import { transactionAPI, getUser } from '@/lib/api'
 
 interface ScannedPaymentData {
+  raw: string
   account: string
-  amount: number
-  description: string
-  timestamp: string
+  amount: number | null
+  reference: string | null
+}
+
+// Signed codes look like `DUP1.<base64url payload>.<signature>`; the payload is
+// decoded here for the confirmation preview only, the server verifies it.
+const decodeSignedPayload = (raw: string): ScannedPaymentData => {
+  const [prefix, body] = raw.split('.')
+  if (prefix !== 'DUP1' || !body) throw new Error('Unsupported QR code')
+  const json = atob(body.replace(/-/g, '+').replace(/_/g, '/'))
+  const payload = JSON.parse(json)
+  return {
+    raw,
+    account: payload.account,
+    amount: payload.amount ? parseFloat(payload.amount) : null,
+    reference: payload.reference ?? null,
+  }
 }
 
 export default function QRPayment() {
@@ -27,6 +42,7 @@ export default function QRPayment() {
   const [scanning, setScanning] = useState(false)
   const [facingMode, setFacingMode] = useState<'user' | 'environment'>('environment')
   const [scannedData, setScannedData] = useState<ScannedPaymentData | null>(null)
+  const [payerAmount, setPayerAmount] = useState('')
   const scanningRef = useRef(false)
 
   useEffect(() => {
@@ -46,14 +62,11 @@ export default function QRPayment() {
       return
     }
 
-    const qrData = JSON.stringify({
-      account: user?.account_number,
-      amount: parseFloat(amount),
-      description: description || 'Payment',
-      timestamp: new Date().toISOString()
-    })
-
     try {
+      const { qr_data: qrData } = await transactionAPI.generateQR({
+        amount: parseFloat(amount),
+        reference: description || 'Payment',
+      })
       const url = await QRCode.toDataURL(qrData, {
         width: 300,
         margin: 2,
@@ -64,8 +77,8 @@ export default function QRPayment() {
       })
       setQrCodeUrl(url)
       setError('')
-    } catch (err) {
-      setError('Failed to generate QR code')
+    } catch (err: any) {
+      setError(err.response?.data?.message || 'Failed to generate QR code')
     }
   }
 
@@ -146,8 +159,9 @@ export default function QRPayment() {
       if (code) {
         scanningRef.current = false
         try {
-          const paymentData = JSON.parse(code.data) as ScannedPaymentData
+          const paymentData = decodeSignedPayload(code.data)
           setScannedData(paymentData)
+          setPayerAmount('')
           if (typeof window !== 'undefined' && window.navigator && window.navigator.vibrate) {
             window.navigator.vibrate(200)
           }
@@ -178,10 +192,19 @@ export default function QRPayment() {
     setError('')
     setSuccess('')
 
+    const paidAmount = scannedData.amount ?? parseFloat(payerAmount)
+    if (!paidAmount || paidAmount <= 0) {
+      setError('Please enter a valid amount')
+      setLoading(false)
+      return
+    }
+
     try {
-      const qrData = JSON.stringify(scannedData)
-      await transactionAPI.qrPayment({ qr_data: qrData })
-      setSuccess(`Payment of $${scannedData.amount.toFixed(2)} sent successfully!`)
+      await transactionAPI.qrPayment({
+        qr_data: scannedData.raw,
+        amount: scannedData.amount === null ? paidAmount : undefined,
+      })
+      setSuccess(`Payment of $${paidAmount.toFixed(2)} sent successfully!`)
       stopScanning()
       setTimeout(() => setSuccess(''), 3000)
     } catch (err: any) {
@@ -336,7 +359,19 @@ export default function QRPayment() {
                         <div className="space-y-4 mb-8 text-left">
                           <div className="pb-3 border-b border-border flex justify-between">
                             <span className="text-muted text-sm">Amount:</span>
-                            <span className="font-mono font-bold text-primary">${scannedData.amount.toFixed(2)}</span>
+                            {scannedData.amount !== null ? (
+                              <span className="font-mono font-bold text-primary">${scannedData.amount.toFixed(2)}</span>
+                            ) : (
+                              <input
+                                type="number"
+                                min="0.01"
+                                step="0.01"
+                                value={payerAmount}
+                                onChange={(e) => setPayerAmount(e.target.value)}
+                                className="w-24 px-2 py-1 bg-surface-highlight border border-border rounded-lg font-mono text-right"
+                                placeholder="0.00"
+                              />
+                            )}
                           </div>
                           <div className="pb-3 border-b border-border flex justify-between">
                             <span className="text-muted text-sm">To:</span>
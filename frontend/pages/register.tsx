@@ -7,6 +7,7 @@ export default function Register() {
     const router = useRouter()
     const [formData, setFormData] = useState({
         username: '',
+        fullName: '',
         email: '',
         password: '',
         confirmPassword: ''
@@ -33,6 +34,7 @@ export default function Register() {
         try {
             const response = await authAPI.register({
                 username: formData.username,
+                full_name: formData.fullName || undefined,
                 email: formData.email,
                 password: formData.password
             })
@@ -42,7 +44,7 @@ export default function Register() {
 
             router.push('/dashboard')
         } catch (err: any) {
-            setError(err.response?.data?.error || 'Registration failed. Please try again.')
+            setError(err.response?.data?.message || err.response?.data?.error || 'Registration failed. Please try again.')
         } finally {
             setLoading(false)
         }
@@ -86,6 +88,20 @@ export default function Register() {
                             />
                         </div>
 
+                        <div>
+                            <label htmlFor="fullName" className="block text-sm font-semibold text-foreground mb-2">
+                                Full Name
+                            </label>
+                            <input
+                                id="fullName"
+                                type="text"
+                                value={formData.fullName}
+                                onChange={(e) => setFormData({ ...formData, fullName: e.target.value })}
+                                className="w-full px-4 py-3 bg-surface-highlight border border-border rounded-xl text-foreground placeholder-muted focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary transition-all font-sans"
+                                placeholder="John Doe"
+                            />
+                        </div>
+
                         <div>
                             <label htmlFor="email" className="block text-sm font-semibold text-foreground mb-2">
                                 Email Address
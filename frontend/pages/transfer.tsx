@@ -1,25 +1,68 @@
-import React, { useState } from 'react'
+import React, { useEffect, useState } from 'react'
 import { useRouter } from 'next/router'
-import { transactionAPI } from '@/lib/api'
+import { feeAPI, payeeAPI, recipientAPI, transactionAPI } from '@/lib/api'
 
 interface FormData {
+  payee_id: string
   recipient_account: string
   amount: string
   description: string
 }
 
+interface Payee {
+  id: string
+  nickname: string
+  account_number: string
+  default_description: string | null
+  first_transfer_limit: number | null
+}
+
 export default function Transfer() {
   const router = useRouter()
   const [loading, setLoading] = useState(false)
   const [error, setError] = useState('')
   const [success, setSuccess] = useState('')
+  const [payees, setPayees] = useState<Payee[]>([])
+  const [savePayee, setSavePayee] = useState(false)
+  const [nickname, setNickname] = useState('')
+  const [preview, setPreview] = useState('')
+  const [feeQuote, setFeeQuote] = useState('')
   const [formData, setFormData] = useState<FormData>({
+    payee_id: '',
     recipient_account: '',
     amount: '',
     description: '',
   })
 
-  const handleChange = (e: React.ChangeEvent<HTMLInputElement | HTMLTextAreaElement>) => {
+  useEffect(() => {
+    payeeAPI.list().then(setPayees).catch(() => setPayees([]))
+  }, [])
+
+  const selectedPayee = payees.find(p => p.id === formData.payee_id)
+
+  const lookupRecipient = async () => {
+    const q = formData.recipient_account.trim()
+    if (!q) return setPreview('')
+    try {
+      const match = await recipientAPI.lookup(q)
+      setPreview(`${match.masked_name} · account ${match.masked_account}`)
+    } catch {
+      setPreview('No matching recipient')
+    }
+  }
+
+  const quoteFee = async () => {
+    const amount = parseFloat(formData.amount)
+    if (!(amount > 0)) return setFeeQuote('')
+    try {
+      const quote = await feeAPI.quote('transfer', amount)
+      setFeeQuote(quote.fee > 0 ? `Fee $${quote.fee.toFixed(2)} · total $${quote.total.toFixed(2)}` : 'No fee')
+    } catch {
+      setFeeQuote('')
+    }
+  }
+
+  const handleChange = (e: React.ChangeEvent<HTMLInputElement | HTMLTextAreaElement | HTMLSelectElement>) => {
     const { name, value } = e.target
     setFormData(prev => ({ ...prev, [name]: value }))
   }
@@ -31,17 +74,31 @@ export default function Transfer() {
     setSuccess('')
 
     try {
-      await transactionAPI.transfer({
-        recipient_account: formData.recipient_account,
+      let payeeId = formData.payee_id
+      if (!payeeId && savePayee) {
+        const payee = await payeeAPI.create({
+          nickname: nickname || formData.recipient_account,
+          account_number: formData.recipient_account,
+        })
+        payeeId = payee.id
+      }
+
+      const result = await transactionAPI.transfer({
+        ...(payeeId ? { payee_id: payeeId } : { recipient_account: formData.recipient_account }),
         amount: parseFloat(formData.amount),
         description: formData.description
       })
 
-      setSuccess('Transfer successful! 🎉')
-      setFormData({ recipient_account: '', amount: '', description: '' })
+      setSuccess(result.status === 'pending_review'
+        ? 'Transfer submitted. It is being reviewed and will complete once approved.'
+        : result.fee > 0
+        ? `Transfer successful! Fee $${result.fee.toFixed(2)}, total $${result.total_debited.toFixed(2)} 🎉`
+        : 'Transfer successful! 🎉')
+      setFeeQuote('')
+      setFormData({ payee_id: '', recipient_account: '', amount: '', description: '' })
       setTimeout(() => router.push('/dashboard'), 2000)
     } catch (err: any) {
-      setError(err.response?.data?.error || 'Transfer failed')
+      setError(err.response?.data?.message || err.response?.data?.error || 'Transfer failed')
     } finally {
       setLoading(false)
     }
@@ -87,20 +144,63 @@ export default function Transfer() {
               )}
 
               <form onSubmit={handleSubmit} className="space-y-7">
-                <div>
-                  <label className="block text-sm font-semibold text-foreground mb-2.5">
-                    Recipient Account Number
-                  </label>
-                  <input
-                    type="text"
-                    name="recipient_account"
-                    value={formData.recipient_account}
-                    onChange={handleChange}
-                    placeholder="Enter 12-digit account number"
-                    required
-                    className="w-full px-4 py-3.5 bg-surface-highlight border border-border rounded-xl text-foreground placeholder-muted focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary transition-all font-mono"
-                  />
-                </div>
+                {payees.length > 0 && (
+                  <div>
+                    <label className="block text-sm font-semibold text-foreground mb-2.5">
+                      Saved Payee
+                    </label>
+                    <select
+                      name="payee_id"
+                      value={formData.payee_id}
+                      onChange={handleChange}
+                      className="w-full px-4 py-3.5 bg-surface-highlight border border-border rounded-xl text-foreground focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary transition-all"
+                    >
+                      <option value="">New recipient</option>
+                      {payees.map(p => (
+                        <option key={p.id} value={p.id}>
+                          {p.nickname} ({p.account_number})
+                        </option>
+                      ))}
+                    </select>
+                    {selectedPayee?.first_transfer_limit != null && (
+                      <p className="text-xs text-muted mt-2">
+                        First transfer to this payee is limited to ${selectedPayee.first_transfer_limit.toFixed(2)}
+                      </p>
+                    )}
+                  </div>
+                )}
+
+                {!formData.payee_id && (
+                  <div>
+                    <label className="block text-sm font-semibold text-foreground mb-2.5">
+                      Recipient
+                    </label>
+                    <input
+                      type="text"
+                      name="recipient_account"
+                      value={formData.recipient_account}
+                      onChange={handleChange}
+                      onBlur={lookupRecipient}
+                      placeholder="Account number, username, email, +phone or $tag"
+                      required
+                      className="w-full px-4 py-3.5 bg-surface-highlight border border-border rounded-xl text-foreground placeholder-muted focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary transition-all font-mono"
+                    />
+                    {preview && <p className="text-xs text-muted mt-2">{preview}</p>}
+                    <label className="flex items-center gap-2 mt-3 text-sm text-muted">
+                      <input type="checkbox" checked={savePayee} onChange={e => setSavePayee(e.target.checked)} />
+                      Save as payee
+                    </label>
+                    {savePayee && (
+                      <input
+                        type="text"
+                        value={nickname}
+                        onChange={e => setNickname(e.target.value)}
+                        placeholder="Nickname"
+                        className="w-full mt-3 px-4 py-3.5 bg-surface-highlight border border-border rounded-xl text-foreground placeholder-muted focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary transition-all"
+                      />
+                    )}
+                  </div>
+                )}
 
                 <div>
                   <label className="block text-sm font-semibold text-foreground mb-2.5">
@@ -113,6 +213,7 @@ export default function Transfer() {
                       name="amount"
                       value={formData.amount}
                       onChange={handleChange}
+                      onBlur={quoteFee}
                       placeholder="0.00"
                       step="0.01"
                       min="0.01"
@@ -120,6 +221,7 @@ export default function Transfer() {
                       className="w-full pl-9 pr-4 py-3.5 bg-surface-highlight border border-border rounded-xl text-foreground placeholder-muted focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary transition-all font-mono text-xl font-bold"
                     />
                   </div>
+                  {feeQuote && <p className="text-xs text-muted mt-2">{feeQuote}</p>}
                 </div>
 
                 <div>
@@ -130,7 +232,7 @@ export default function Transfer() {
                     name="description"
                     value={formData.description}
                     onChange={handleChange}
-                    placeholder="What is this transfer for?"
+                    placeholder={selectedPayee?.default_description || 'What is this transfer for?'}
                     rows={4}
                     className="w-full px-4 py-3.5 bg-surface-highlight border border-border rounded-xl text-foreground placeholder-muted focus:outline-none focus:ring-2 focus:ring-primary/50 focus:border-primary resize-none transition-all"
                   />